@@ -0,0 +1,317 @@
+use std::{net::SocketAddr, time::Duration};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpStream, ToSocketAddrs, UdpSocket}, time::{timeout, timeout_at, Instant}};
+
+use crate::{constants::*, request, response, ActiveCode, Diagnostic, HeaderNegativeCode, Iso13400Error, LogicAddress, Message, Payload, RoutingActiveType, Version};
+
+/// The maximum length of UDP datagram received.
+const MAX_DATAGRAM_SIZE: usize = 0xFFFF;
+/// The UDS negative response SID.
+const UDS_NEGATIVE_RESPONSE: u8 = 0x7F;
+/// The UDS negative response code of response pending, the server needs more time.
+const UDS_RESPONSE_PENDING: u8 = 0x78;
+
+/// The configuration of [`DoIpClient`].
+///
+/// * `version`: the protocol version of messages sent, it's decided by the `std20xx` feature by default.
+/// * `address`: the logical address of client(0x0E00 ~ 0x0FFF).
+/// * `activation`: the activation type of routing activation request.
+/// * `user_def`: the VM specific data of routing activation request.
+/// * `response_timeout`: the time in milliseconds waited for the UDS response after the acknowledgement(P2 client).
+/// * `pending_timeout`: the time in milliseconds waited for the UDS response after the response pending(P2* client).
+/// * `max_payload`: the maximum payload length accepted, the larger message is rejected with header NACK.
+#[derive(Debug, Clone)]
+pub struct DoIpConfig {
+    pub version: Version,
+    pub address: LogicAddress,
+    pub activation: RoutingActiveType,
+    pub user_def: Option<u32>,
+    pub response_timeout: u64,
+    pub pending_timeout: u64,
+    pub max_payload: usize,
+}
+
+impl Default for DoIpConfig {
+    fn default() -> Self {
+        #[cfg(feature = "std2010")]
+        let version = Version::ISO13400_2_2010;
+        #[cfg(feature = "std2012")]
+        let version = Version::ISO13400_2_2012;
+        #[cfg(feature = "std2019")]
+        let version = Version::ISO13400_2_2019;
+
+        Self {
+            version,
+            address: LogicAddress::Client(0x0E00),
+            activation: Default::default(),
+            user_def: Default::default(),
+            response_timeout: 5000,
+            pending_timeout: 5000,
+            max_payload: 0x0040_0000,
+        }
+    }
+}
+
+/// The DoIP client(tester) connected to a DoIP entity over TCP.
+///
+/// The alive check requests from the entity are answered when waiting for any response.
+pub struct DoIpClient {
+    stream: TcpStream,
+    config: DoIpConfig,
+    /// The logical address of DoIP entity, it's got from the routing activation response.
+    entity: Option<LogicAddress>,
+    /// The bytes received but not framed yet, they are kept if receiving is cancelled(e.g. timeout).
+    buffer: Vec<u8>,
+    /// The remaining length of the rejected payload that is discarded.
+    discard: usize,
+    /// The connection is closed after the header NACK that requires closing the socket.
+    closed: bool,
+}
+
+impl DoIpClient {
+    /// Send the vehicle identification request to `target`(e.g. the broadcast address with [`UDP_SERVER_PORT`])
+    /// and collect the responses received in `wait` milliseconds, see [`A_DOIP_ANNOUNCE_WAIT`].
+    pub async fn discover(target: SocketAddr, wait: u64) -> Result<Vec<(SocketAddr, response::VehicleID)>, Iso13400Error> {
+        let local: SocketAddr = match target {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.set_broadcast(true)?;
+
+        // the default version(0xFF) is allowed for the vehicle identification request.
+        let data: Vec<u8> = Message { version: Version::Default, payload: Payload::ReqVehicleId(request::VehicleID) }.into();
+        socket.send_to(&data, target).await?;
+
+        let deadline = Instant::now() + Duration::from_millis(wait);
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        let mut result = Vec::new();
+        while let Ok(received) = timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+            let (size, addr) = received?;
+            match Message::try_from(&buffer[..size]) {
+                Ok(Message { payload: Payload::RespVehicleId(v), .. }) => result.push((addr, v)),
+                Ok(msg) => log::warn!("ISO 13400-2 - ignored {:?} from {}", msg.payload.payload_type(), addr),
+                Err(e) => log::warn!("ISO 13400-2 - invalid message from {}: {}", addr, e),
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Connect to the DoIP entity(e.g. `(ip, TCP_SERVER_PORT)`) and activate the routing.
+    pub async fn connect(addr: impl ToSocketAddrs, config: DoIpConfig) -> Result<Self, Iso13400Error> {
+        let stream = timeout(Duration::from_millis(A_DOIP_CTRL), TcpStream::connect(addr)).await
+            .map_err(|_| Iso13400Error::Timeout("connection"))??;
+        stream.set_nodelay(true)?;
+
+        let mut client = Self { stream, config, entity: None, buffer: Vec::new(), discard: 0, closed: false };
+        client.activate_routing().await?;
+
+        Ok(client)
+    }
+
+    #[inline]
+    pub fn config(&self) -> &DoIpConfig {
+        &self.config
+    }
+
+    /// The logical address of DoIP entity if the routing is activated.
+    #[inline]
+    pub fn entity(&self) -> Option<LogicAddress> {
+        self.entity
+    }
+
+    /// Send the routing activation request and wait for the response.
+    ///
+    /// # Returns
+    ///
+    /// [`Iso13400Error::RoutingActivationFailed`] if the response code is not [`ActiveCode::Success`].
+    pub async fn activate_routing(&mut self) -> Result<response::RoutingActive, Iso13400Error> {
+        let request = request::RoutingActive::new(self.config.address, self.config.activation, self.config.user_def);
+        self.send(Payload::ReqRoutingActive(request)).await?;
+
+        let deadline = Instant::now() + Duration::from_millis(A_DOIP_CTRL);
+        loop {
+            match self.receive_until(deadline, "routing activation response").await?.payload {
+                Payload::RespRoutingActive(v) => {
+                    return match v.active_code() {
+                        ActiveCode::Success => {
+                            self.entity = Some(v.src_addr());
+                            Ok(v)
+                        },
+                        code => Err(Iso13400Error::RoutingActivationFailed(code)),
+                    };
+                },
+                payload => log::warn!("ISO 13400-2 - ignored {:?} when activating routing", payload.payload_type()),
+            }
+        }
+    }
+
+    /// Send the UDS request to `target` and wait for the acknowledgement and the response.
+    ///
+    /// The response pending(`7F xx 78`) isn't returned, the final response is waited for `pending_timeout` after each one.
+    ///
+    /// # Returns
+    ///
+    /// The UDS response, or [`Iso13400Error::DiagnosticNegative`] if the request is not acknowledged.
+    pub async fn diagnostic(&mut self, target: LogicAddress, data: Vec<u8>) -> Result<Vec<u8>, Iso13400Error> {
+        // the first address of diagnostic message is the source address.
+        self.send(Payload::Diagnostic(Diagnostic::new(self.config.address, target, data))).await?;
+
+        let deadline = Instant::now() + Duration::from_millis(A_DOIP_DIAGNOSTIC_MESSAGE);
+        loop {
+            match self.receive_until(deadline, "diagnostic acknowledgement").await?.payload {
+                Payload::RespDiagPositive(v) if Self::is_from(*v.src_addr(), target) => break,
+                Payload::RespDiagNegative(v) if Self::is_from(v.src_addr(), target) =>
+                    return Err(Iso13400Error::DiagnosticNegative(v.code())),
+                payload => log::warn!("ISO 13400-2 - ignored {:?} when waiting acknowledgement", payload.payload_type()),
+            }
+        }
+
+        let mut deadline = Instant::now() + Duration::from_millis(self.config.response_timeout);
+        loop {
+            match self.receive_until(deadline, "diagnostic response").await?.payload {
+                // the first address of diagnostic message is the source address.
+                Payload::Diagnostic(v) if Self::is_from(v.dst_addr(), target) => match v.data.as_slice() {
+                    [UDS_NEGATIVE_RESPONSE, _, UDS_RESPONSE_PENDING] => {
+                        log::debug!("ISO 13400-2 - response pending: {}", hex::encode(&v.data));
+                        deadline = Instant::now() + Duration::from_millis(self.config.pending_timeout);
+                    },
+                    _ => return Ok(v.data),
+                },
+                payload => log::warn!("ISO 13400-2 - ignored {:?} when waiting response", payload.payload_type()),
+            }
+        }
+    }
+
+    /// Send the payload with the configured version.
+    pub async fn send(&mut self, payload: Payload) -> Result<(), Iso13400Error> {
+        let data: Vec<u8> = Message { version: self.config.version, payload }.into();
+        log::trace!("ISO 13400-2 - sending: {}", hex::encode(&data));
+        self.stream.write_all(&data).await?;
+
+        Ok(())
+    }
+
+    /// Receive the next message, the stream is framed by the payload length of generic header.
+    ///
+    /// The message with incorrect pattern, unknown payload type or too large payload
+    /// is rejected with header NACK.
+    pub async fn receive(&mut self) -> Result<Message, Iso13400Error> {
+        let framed = self.read_message().await?;
+        self.accept(framed).await
+    }
+
+    /// Shut down the connection.
+    pub async fn close(mut self) -> Result<(), Iso13400Error> {
+        self.stream.shutdown().await?;
+
+        Ok(())
+    }
+
+    /// Receive the next message before `deadline`, the alive check request is answered
+    /// and the header NACK is returned as error.
+    ///
+    /// Only reading is cancelled by the timeout, the partial message is kept for the next receiving.
+    async fn receive_until(&mut self, deadline: Instant, waiting: &'static str) -> Result<Message, Iso13400Error> {
+        loop {
+            let framed = timeout_at(deadline, self.read_message()).await
+                .map_err(|_| Iso13400Error::Timeout(waiting))??;
+            let msg = self.accept(framed).await?;
+            match &msg.payload {
+                Payload::ReqAliveCheck(_) => {
+                    let response = response::AliveCheck::new(self.config.address);
+                    self.send(Payload::RespAliveCheck(response)).await?;
+                },
+                Payload::RespHeaderNegative(v) => return Err(Iso13400Error::HeaderNegative(v.code())),
+                _ => return Ok(msg),
+            }
+        }
+    }
+
+    /// Read until a message is framed, it's cancel safe since the bytes read are kept in the buffer.
+    ///
+    /// # Returns
+    ///
+    /// The message or the header NACK code that it's rejected with.
+    async fn read_message(&mut self) -> Result<Result<Message, HeaderNegativeCode>, Iso13400Error> {
+        if self.closed {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotConnected).into());
+        }
+
+        loop {
+            if let Some(framed) = self.frame()? {
+                return Ok(framed);
+            }
+
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+    }
+
+    /// Take the next message from the buffer, `None` if more bytes are required.
+    fn frame(&mut self) -> Result<Option<Result<Message, HeaderNegativeCode>>, Iso13400Error> {
+        // the payload rejected is discarded so that the next message can be framed.
+        let size = self.discard.min(self.buffer.len());
+        self.buffer.drain(..size);
+        self.discard -= size;
+        if self.discard > 0 || self.buffer.len() < SIZE_OF_HEADER {
+            return Ok(None);
+        }
+
+        let length = u32::from_be_bytes(self.buffer[SIZE_OF_VERSION + SIZE_OF_DATA_TYPE..SIZE_OF_HEADER].try_into().unwrap()) as usize;
+        if length > self.config.max_payload {
+            self.buffer.drain(..SIZE_OF_HEADER);
+            self.discard = length;
+            return Ok(Some(Err(HeaderNegativeCode::MessageTooLarge)));
+        }
+        if self.buffer.len() < SIZE_OF_HEADER + length {
+            return Ok(None);
+        }
+
+        let data: Vec<_> = self.buffer.drain(..SIZE_OF_HEADER + length).collect();
+        match Message::try_from(data.as_slice()) {
+            Ok(msg) => Ok(Some(Ok(msg))),
+            Err(Iso13400Error::InvalidVersion { .. }) => Ok(Some(Err(HeaderNegativeCode::IncorrectPatternFormat))),
+            Err(Iso13400Error::InvalidPayloadType(_)) => Ok(Some(Err(HeaderNegativeCode::UnknownPayloadTYpe))),
+            // the header is complete, so the payload is shorter than its type requires.
+            Err(Iso13400Error::InvalidLength { .. }) => Ok(Some(Err(HeaderNegativeCode::InvalidPayloadLength))),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Return the message framed or reject it with header NACK.
+    async fn accept(&mut self, framed: Result<Message, HeaderNegativeCode>) -> Result<Message, Iso13400Error> {
+        match framed {
+            Ok(msg) => Ok(msg),
+            Err(code) => Err(self.reject(code).await),
+        }
+    }
+
+    /// Send the header NACK and return the error of it.
+    ///
+    /// The connection is closed after the incorrect pattern or the invalid payload length,
+    /// the stream can't be framed any more.
+    async fn reject(&mut self, code: HeaderNegativeCode) -> Iso13400Error {
+        log::warn!("ISO 13400-2 - rejected message: {:?}", code);
+        let result = self.send(Payload::RespHeaderNegative(response::HeaderNegative::new(code))).await;
+        if matches!(code, HeaderNegativeCode::IncorrectPatternFormat | HeaderNegativeCode::InvalidPayloadLength) {
+            log::warn!("ISO 13400-2 - connection closed after header NACK");
+            self.closed = true;
+            self.buffer.clear();
+            let _ = self.stream.shutdown().await;
+        }
+        if let Err(e) = result {
+            return e;
+        }
+
+        Iso13400Error::HeaderNegative(code)
+    }
+
+    /// Compare the address values, the variant of `target` may be chosen by the caller.
+    #[inline]
+    fn is_from(addr: LogicAddress, target: LogicAddress) -> bool {
+        let (addr, target): (u16, u16) = (addr.into(), target.into());
+        addr == target
+    }
+}
@@ -3,6 +3,15 @@ pub const TCP_SERVER_PORT: u16 = 13400;
 pub const TLS_TCP_SERVER_PORT: u16 = 3496;
 pub const UDP_SERVER_PORT: u16 = 13400;
 
+/// Table 12 — DoIP timing and communication parameters, in milliseconds.
+///
+/// The time waited for the response of control messages, e.g. routing activation.
+pub const A_DOIP_CTRL: u64 = 2000;
+/// The time waited for the diagnostic message acknowledgement(0x8002/0x8003).
+pub const A_DOIP_DIAGNOSTIC_MESSAGE: u64 = 2000;
+/// The time waited for the vehicle identification responses.
+pub const A_DOIP_ANNOUNCE_WAIT: u64 = 500;
+
 pub(crate) const HEADER_NEGATIVE: u16 = 0x0000;
 pub(crate) const UDP_REQ_VEHICLE_IDENTIFIER: u16 = 0x0001;
 pub(crate) const UDP_REQ_VEHICLE_ID_WITH_EID: u16 = 0x0002;
@@ -32,3 +41,5 @@ pub(crate) const SIZE_OF_ADDRESS: usize = 2;
 pub(crate) const SIZE_OF_VERSION: usize = 2;
 pub(crate) const SIZE_OF_DATA_TYPE: usize = 2;
 pub(crate) const SIZE_OF_LENGTH: usize = 4;
+/// The length of generic DoIP header.
+pub const SIZE_OF_HEADER: usize = SIZE_OF_VERSION + SIZE_OF_DATA_TYPE + SIZE_OF_LENGTH;
@@ -12,4 +12,15 @@ pub enum Iso13400Error {
     InvalidVersion { version: u8, reverse: u8 },
     #[error("Iso 13400-2 - invalid payload type: {0}")]
     InvalidPayloadType(u16),
+
+    #[error("ISO 13400-2 - io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("ISO 13400-2 - timeout when waiting {0}")]
+    Timeout(&'static str),
+    #[error("ISO 13400-2 - header negative: {0:?}")]
+    HeaderNegative(crate::HeaderNegativeCode),
+    #[error("ISO 13400-2 - routing activation failed: {0:?}")]
+    RoutingActivationFailed(crate::ActiveCode),
+    #[error("ISO 13400-2 - diagnostic negative: {0}")]
+    DiagnosticNegative(crate::DiagnosticNegativeCode),
 }
@@ -103,6 +103,10 @@ mod error;
 pub use error::*;
 pub mod request;
 pub mod response;
+#[cfg(feature = "async")]
+mod client;
+#[cfg(feature = "async")]
+pub use client::*;
 
 pub(crate) mod utils;
 
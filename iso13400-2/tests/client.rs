@@ -0,0 +1,233 @@
+use std::{net::SocketAddr, time::Duration};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream, UdpSocket}, task::JoinHandle, time::sleep};
+use iso13400_2::*;
+
+const ENTITY: u16 = 0x1001;
+
+async fn read_message(stream: &mut TcpStream) -> anyhow::Result<Message> {
+    let mut data = vec![0; SIZE_OF_HEADER];
+    stream.read_exact(&mut data).await?;
+    let length = u32::from_be_bytes(data[4..8].try_into()?) as usize;
+    data.resize(SIZE_OF_HEADER + length, 0);
+    stream.read_exact(&mut data[SIZE_OF_HEADER..]).await?;
+
+    Ok(Message::try_from(data.as_slice())?)
+}
+
+async fn write_message(stream: &mut TcpStream, payload: Payload) -> anyhow::Result<()> {
+    let data: Vec<u8> = Message { version: Version::ISO13400_2_2012, payload }.into();
+    stream.write_all(&data).await?;
+
+    Ok(())
+}
+
+/// The loopback DoIP entity, it sends an alive check request before each acknowledgement.
+///
+/// # Returns
+///
+/// The address of server and the task that returns the number of alive check responses received.
+async fn entity(active_code: ActiveCode) -> anyhow::Result<(SocketAddr, JoinHandle<anyhow::Result<usize>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let task = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        let mut alive = 0;
+        while let Ok(msg) = read_message(&mut stream).await {
+            match msg.payload {
+                Payload::ReqRoutingActive(v) => {
+                    let response = response::RoutingActive::new(v.src_addr(), LogicAddress::from(ENTITY), active_code, None);
+                    write_message(&mut stream, Payload::RespRoutingActive(response)).await?;
+                },
+                Payload::RespAliveCheck(_) => alive += 1,
+                // the first address is the source address.
+                Payload::Diagnostic(v) => {
+                    let tester = v.dst_addr();
+                    let target: u16 = v.src_addr().into();
+                    write_message(&mut stream, Payload::ReqAliveCheck(request::AliveCheck)).await?;
+                    if target != ENTITY {
+                        let nack = response::DiagnosticNegative::new(
+                            LogicAddress::from(target), tester, DiagnosticNegativeCode::UnknownTargetAddress, vec![],
+                        );
+                        write_message(&mut stream, Payload::RespDiagNegative(nack)).await?;
+                        continue;
+                    }
+
+                    let ack = response::DiagnosticPositive::new(
+                        LogicAddress::from(ENTITY), tester, DiagnosticPositiveCode::Confirm, vec![],
+                    );
+                    write_message(&mut stream, Payload::RespDiagPositive(ack)).await?;
+                    let mut data = v.data().clone();
+                    data[0] += 0x40;
+                    data.extend(b"VIN");
+                    let response = Diagnostic::new(LogicAddress::from(ENTITY), tester, data);
+                    write_message(&mut stream, Payload::Diagnostic(response)).await?;
+                },
+                _ => {},
+            }
+        }
+
+        Ok(alive)
+    });
+
+    Ok((addr, task))
+}
+
+#[tokio::test]
+async fn test_diagnostic() -> anyhow::Result<()> {
+    let (addr, server) = entity(ActiveCode::Success).await?;
+
+    let mut client = DoIpClient::connect(addr, DoIpConfig::default()).await?;
+    assert_eq!(client.entity(), Some(LogicAddress::from(ENTITY)));
+
+    let response = client.diagnostic(LogicAddress::from(ENTITY), hex::decode("22F190")?).await?;
+    assert_eq!(response, hex::decode("62F19056494E")?);
+
+    let ret = client.diagnostic(LogicAddress::from(0x2000), hex::decode("1001")?).await;
+    assert!(matches!(ret, Err(Iso13400Error::DiagnosticNegative(DiagnosticNegativeCode::UnknownTargetAddress))));
+
+    client.close().await?;
+    // every alive check request is answered.
+    assert_eq!(server.await??, 2);
+
+    Ok(())
+}
+
+/// The loopback DoIP entity that activates the routing and answers the first diagnostic request
+/// with the acknowledgement and the `responses` written in order, each one is delayed if the duration is not zero.
+async fn delayed_entity(responses: Vec<(Duration, Vec<u8>)>) -> anyhow::Result<(SocketAddr, JoinHandle<anyhow::Result<()>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let task = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        while let Ok(msg) = read_message(&mut stream).await {
+            match msg.payload {
+                Payload::ReqRoutingActive(v) => {
+                    let response = response::RoutingActive::new(v.src_addr(), LogicAddress::from(ENTITY), ActiveCode::Success, None);
+                    write_message(&mut stream, Payload::RespRoutingActive(response)).await?;
+                },
+                Payload::Diagnostic(v) => {
+                    let tester = v.dst_addr();
+                    let ack = response::DiagnosticPositive::new(
+                        LogicAddress::from(ENTITY), tester, DiagnosticPositiveCode::Confirm, vec![],
+                    );
+                    write_message(&mut stream, Payload::RespDiagPositive(ack)).await?;
+                    for (delay, data) in &responses {
+                        sleep(*delay).await;
+                        stream.write_all(data).await?;
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        Ok(())
+    });
+
+    Ok((addr, task))
+}
+
+fn diagnostic_response(data: &str) -> anyhow::Result<Vec<u8>> {
+    let response = Diagnostic::new(LogicAddress::from(ENTITY), LogicAddress::from(0x0E00), hex::decode(data)?);
+    Ok(Message { version: Version::ISO13400_2_2012, payload: Payload::Diagnostic(response) }.into())
+}
+
+#[tokio::test]
+async fn test_response_pending() -> anyhow::Result<()> {
+    // the pending responses are longer than P2 but shorter than P2*.
+    let (addr, _server) = delayed_entity(vec![
+        (Duration::ZERO, diagnostic_response("7F3178")?),
+        (Duration::from_millis(150), diagnostic_response("7F3178")?),
+        (Duration::from_millis(150), diagnostic_response("7101FF0001")?),
+    ]).await?;
+    let config = DoIpConfig { response_timeout: 100, pending_timeout: 1000, ..Default::default() };
+    let mut client = DoIpClient::connect(addr, config).await?;
+
+    let response = client.diagnostic(LogicAddress::from(ENTITY), hex::decode("3101FF00")?).await?;
+    assert_eq!(response, hex::decode("7101FF0001")?);
+
+    client.close().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_receive_timeout_resumed() -> anyhow::Result<()> {
+    // the response is split and the rest arrives after the timeout.
+    let response = diagnostic_response("62F19056494E")?;
+    let (addr, _server) = delayed_entity(vec![
+        (Duration::ZERO, response[..10].to_vec()),
+        (Duration::from_millis(200), response[10..].to_vec()),
+    ]).await?;
+    let config = DoIpConfig { response_timeout: 100, ..Default::default() };
+    let mut client = DoIpClient::connect(addr, config).await?;
+
+    let ret = client.diagnostic(LogicAddress::from(ENTITY), hex::decode("22F190")?).await;
+    assert!(matches!(ret, Err(Iso13400Error::Timeout(_))));
+    // the bytes read before the timeout are kept, the message is framed as usual.
+    match client.receive().await?.payload {
+        Payload::Diagnostic(v) => assert_eq!(v.data, hex::decode("62F19056494E")?),
+        payload => panic!("unexpected payload: {:?}", payload.payload_type()),
+    }
+
+    client.close().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_incorrect_pattern_closed() -> anyhow::Result<()> {
+    // the inverse version of response is wrong.
+    let mut response = diagnostic_response("62F19056494E")?;
+    response[1] = response[0];
+    let (addr, _server) = delayed_entity(vec![(Duration::ZERO, response)]).await?;
+    let mut client = DoIpClient::connect(addr, DoIpConfig::default()).await?;
+
+    let ret = client.diagnostic(LogicAddress::from(ENTITY), hex::decode("22F190")?).await;
+    assert!(matches!(ret, Err(Iso13400Error::HeaderNegative(HeaderNegativeCode::IncorrectPatternFormat))));
+    // the connection is closed after the header NACK.
+    assert!(matches!(client.receive().await, Err(Iso13400Error::IoError(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_routing_activation_failed() -> anyhow::Result<()> {
+    let (addr, _server) = entity(ActiveCode::SourceAddressUnknown).await?;
+
+    let ret = DoIpClient::connect(addr, DoIpConfig::default()).await;
+    assert!(matches!(ret, Err(Iso13400Error::RoutingActivationFailed(ActiveCode::SourceAddressUnknown))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_discover() -> anyhow::Result<()> {
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let addr = socket.local_addr()?;
+    let server = tokio::spawn(async move {
+        let mut buffer = [0; 64];
+        let (size, client) = socket.recv_from(&mut buffer).await?;
+        let msg = Message::try_from(&buffer[..size])?;
+        assert!(matches!(msg.payload, Payload::ReqVehicleId(_)));
+
+        let response = response::VehicleID::new(
+            "-".repeat(LENGTH_OF_VIN),
+            LogicAddress::from(ENTITY),
+            Eid::new(0x0011_2233_4455)?,
+            Gid::new(0x0011_2233_4455)?,
+            FurtherAction::NoAction,
+            None,
+        )?;
+        let data: Vec<u8> = Message { version: Version::ISO13400_2_2012, payload: Payload::RespVehicleId(response) }.into();
+        socket.send_to(&data, client).await?;
+
+        anyhow::Ok(())
+    });
+
+    let vehicles = DoIpClient::discover(addr, A_DOIP_ANNOUNCE_WAIT).await?;
+    server.await??;
+    assert_eq!(vehicles.len(), 1);
+    let (from, vehicle) = &vehicles[0];
+    assert_eq!(*from, addr);
+    assert_eq!(vehicle.address(), LogicAddress::from(ENTITY));
+
+    Ok(())
+}
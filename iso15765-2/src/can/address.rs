@@ -1,5 +1,14 @@
+/// 29bit CAN-ID prefix(priority 6) of physical normal fixed addressing.
+pub const NORMAL_FIXED_PHYSICAL: u32 = 0x18DA_0000;
+/// 29bit CAN-ID prefix(priority 6) of functional normal fixed addressing.
+pub const NORMAL_FIXED_FUNCTIONAL: u32 = 0x18DB_0000;
+/// 29bit CAN-ID prefix(priority 6) of physical mixed addressing.
+pub const MIXED_PHYSICAL: u32 = 0x18CE_0000;
+/// 29bit CAN-ID prefix(priority 6) of functional mixed addressing.
+pub const MIXED_FUNCTIONAL: u32 = 0x18CD_0000;
+
 /// ISO-TP address format.
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum AddressFormat {
     #[default]
     Normal = 0x01,      // 11bit CAN-ID
@@ -9,6 +18,17 @@ pub enum AddressFormat {
     Enhanced = 0x05,    // 11bit(Remote) and 29bot CAN-ID
 }
 
+impl AddressFormat {
+    /// The length of address information(N_TA or N_AE) placed before N_PCI.
+    #[inline]
+    pub fn ext_len(&self) -> usize {
+        match self {
+            Self::Extend | Self::ExtendMixed => 1,
+            _ => 0,
+        }
+    }
+}
+
 /// ISO-TP address type.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Default)]
 pub enum AddressType {
@@ -17,14 +37,212 @@ pub enum AddressType {
     Functional,
 }
 
+/// The role of ISO-TP node.
+///
+/// * `Client`: the tester, it receives on `rx_id` only and may write to `fid`.
+/// * `Server`: the ECU, it receives on `rx_id` and `fid` and replies on `tx_id`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Default)]
+pub enum IsoTpRole {
+    #[default]
+    Client,
+    Server,
+}
+
 /// ISO-TP address
 ///
 /// * `tx_id`: transmit identifier.
 /// * `rx_id`: receive identifier.
 /// * `fid`: functional address identifier.
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// * `format`: the address format.
+/// * `source`: N_SA, the address of this node(extended addressing).
+/// * `target`: N_TA, the physical address of remote node(extended addressing).
+/// * `func_target`: N_TA, the functional address of remote nodes(extended addressing).
+/// * `extension`: N_AE, the address extension(mixed addressing).
+///
+/// It's created by the constructor of each format(e.g. [`Address::normal`]), the struct expression
+/// is not allowed outside of this crate so that more address information can be added later.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[non_exhaustive]
 pub struct Address {
     pub tx_id: u32,
     pub rx_id: u32,
     pub fid: u32,
+    pub format: AddressFormat,
+    pub source: u8,
+    pub target: u8,
+    pub func_target: u8,
+    pub extension: u8,
+}
+
+impl Address {
+    /// Normal addressing, the N_AI is only carried by CAN-ID.
+    #[inline]
+    pub fn normal(tx_id: u32, rx_id: u32, fid: u32) -> Self {
+        Self {
+            tx_id,
+            rx_id,
+            fid,
+            format: AddressFormat::Normal,
+            source: Default::default(),
+            target: Default::default(),
+            func_target: Default::default(),
+            extension: Default::default(),
+        }
+    }
+
+    /// Normal fixed addressing, the CAN-ID is `0x18DA_TA_SA`(physical) or `0x18DB_TA_SA`(functional).
+    ///
+    /// It's the address of client(tester), use [`Address::normal_fixed_server`] for the server.
+    #[inline]
+    pub fn normal_fixed(source: u8, target: u8, func_target: u8) -> Self {
+        Self {
+            tx_id: NORMAL_FIXED_PHYSICAL | (target as u32) << 8 | source as u32,
+            rx_id: NORMAL_FIXED_PHYSICAL | (source as u32) << 8 | target as u32,
+            fid: NORMAL_FIXED_FUNCTIONAL | (func_target as u32) << 8 | source as u32,
+            format: AddressFormat::NormalFixed,
+            source,
+            target,
+            func_target,
+            extension: Default::default(),
+        }
+    }
+
+    /// Normal fixed addressing of server(ECU), the functional requests are received on `0x18DB_<func_target>_<target>`.
+    ///
+    /// * `source`: the address of the server.
+    /// * `target`: the address of the client(tester).
+    /// * `func_target`: the functional address that the server responds to.
+    #[inline]
+    pub fn normal_fixed_server(source: u8, target: u8, func_target: u8) -> Self {
+        Self {
+            fid: NORMAL_FIXED_FUNCTIONAL | (func_target as u32) << 8 | target as u32,
+            ..Self::normal_fixed(source, target, func_target)
+        }
+    }
+
+    /// Extended addressing, the N_TA is placed in the first data byte.
+    #[inline]
+    pub fn extended(tx_id: u32, rx_id: u32, fid: u32, source: u8, target: u8, func_target: u8) -> Self {
+        Self {
+            tx_id,
+            rx_id,
+            fid,
+            format: AddressFormat::Extend,
+            source,
+            target,
+            func_target,
+            extension: Default::default(),
+        }
+    }
+
+    /// 11bit mixed addressing, the N_AE is placed in the first data byte.
+    #[inline]
+    pub fn mixed(tx_id: u32, rx_id: u32, fid: u32, extension: u8) -> Self {
+        Self {
+            tx_id,
+            rx_id,
+            fid,
+            format: AddressFormat::ExtendMixed,
+            source: Default::default(),
+            target: Default::default(),
+            func_target: Default::default(),
+            extension,
+        }
+    }
+
+    /// 29bit mixed addressing, the CAN-ID is `0x18CE_TA_SA`(physical) or `0x18CD_TA_SA`(functional)
+    /// and the N_AE is placed in the first data byte.
+    ///
+    /// It's the address of client(tester), use [`Address::mixed_fixed_server`] for the server.
+    #[inline]
+    pub fn mixed_fixed(source: u8, target: u8, func_target: u8, extension: u8) -> Self {
+        Self {
+            tx_id: MIXED_PHYSICAL | (target as u32) << 8 | source as u32,
+            rx_id: MIXED_PHYSICAL | (source as u32) << 8 | target as u32,
+            fid: MIXED_FUNCTIONAL | (func_target as u32) << 8 | source as u32,
+            format: AddressFormat::ExtendMixed,
+            source,
+            target,
+            func_target,
+            extension,
+        }
+    }
+
+    /// 29bit mixed addressing of server(ECU), the functional requests are received on `0x18CD_<func_target>_<target>`,
+    /// see [`Address::normal_fixed_server`].
+    #[inline]
+    pub fn mixed_fixed_server(source: u8, target: u8, func_target: u8, extension: u8) -> Self {
+        Self {
+            fid: MIXED_FUNCTIONAL | (func_target as u32) << 8 | target as u32,
+            ..Self::mixed_fixed(source, target, func_target, extension)
+        }
+    }
+
+    /// The CAN-ID used to transmit.
+    #[inline]
+    pub fn tx_can_id(&self, addr_type: AddressType) -> u32 {
+        match addr_type {
+            AddressType::Physical => self.tx_id,
+            AddressType::Functional => self.fid,
+        }
+    }
+
+    /// The address information placed before N_PCI of transmitted frames.
+    #[inline]
+    pub fn tx_ext(&self, addr_type: AddressType) -> Option<u8> {
+        match self.format {
+            AddressFormat::Extend => match addr_type {
+                AddressType::Physical => Some(self.target),
+                AddressType::Functional => Some(self.func_target),
+            },
+            AddressFormat::ExtendMixed => Some(self.extension),
+            _ => None,
+        }
+    }
+
+    /// The address information expected before N_PCI of received frames.
+    #[inline]
+    pub fn rx_ext(&self) -> Option<u8> {
+        match self.format {
+            AddressFormat::Extend => Some(self.source),
+            AddressFormat::ExtendMixed => Some(self.extension),
+            _ => None,
+        }
+    }
+
+    /// Check whether a received frame is addressed to this node.
+    #[inline]
+    pub fn is_rx_frame(&self, can_id: u32, data: &[u8]) -> bool {
+        if can_id != self.rx_id {
+            return false;
+        }
+
+        match self.rx_ext() {
+            Some(ext) => data.first() == Some(&ext),
+            None => true,
+        }
+    }
+
+    /// The address information expected before N_PCI of functional requests received by server.
+    #[inline]
+    pub fn func_rx_ext(&self) -> Option<u8> {
+        match self.format {
+            AddressFormat::Extend => Some(self.func_target),
+            AddressFormat::ExtendMixed => Some(self.extension),
+            _ => None,
+        }
+    }
+
+    /// Check whether a received frame is a functional request to this node.
+    #[inline]
+    pub fn is_func_rx_frame(&self, can_id: u32, data: &[u8]) -> bool {
+        if can_id != self.fid {
+            return false;
+        }
+
+        match self.func_rx_ext() {
+            Some(ext) => data.first() == Some(&ext),
+            None => true,
+        }
+    }
 }
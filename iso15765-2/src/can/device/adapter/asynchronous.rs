@@ -0,0 +1,333 @@
+use std::{fmt::Display, hash::Hash, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use rs_can::{CanDevice, CanFrame, CanListener};
+use tokio::{sync::{mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender}, watch}, task::JoinHandle};
+
+use crate::can::device::FrameSender;
+use crate::error::Error;
+
+use super::{sync::{BLOCKING_TIMEOUT, STOP_TIMEOUT}, ListenerFilter, Listeners};
+
+impl<F: Send> FrameSender<F> for UnboundedSender<F> {
+    #[inline]
+    fn send_frame(&self, frame: F) -> Result<(), Error> {
+        self.send(frame)
+            .map_err(|e| {
+                log::warn!("ISO-TP - transmit failed: {:?}", e);
+                Error::DeviceError
+            })
+    }
+}
+
+/// The adapter runs on tokio tasks instead of threads, the device is accessed on the blocking threads.
+pub struct AsyncCanAdapter<D, C, F> {
+    pub(crate) device: D,
+    pub(crate) sender: UnboundedSender<F>,
+    pub(crate) receiver: Arc<tokio::sync::Mutex<UnboundedReceiver<F>>>,
+    pub(crate) listeners: Listeners<C, F>,
+    pub(crate) stop_tx: watch::Sender<bool>,
+    pub(crate) tasks: Vec<(&'static str, JoinHandle<()>)>,
+    /// The first device error since started, returned by [`AsyncCanAdapter::pause`].
+    pub(crate) error: Arc<Mutex<Option<Error>>>,
+}
+
+impl<D, C, F> AsyncCanAdapter<D, C, F>
+where
+    D: CanDevice<Channel = C, Frame = F> + Clone + Send + Sync + 'static,
+    C: Clone + Display + Hash + Eq + Send + 'static,
+    F: CanFrame<Channel = C> + Clone + Send + Display + 'static,
+{
+    pub fn new(device: D) -> Self {
+        let (tx, rx) = unbounded_channel();
+        let (stop_tx, _) = watch::channel(false);
+        Self {
+            device,
+            sender: tx,
+            receiver: Arc::new(tokio::sync::Mutex::new(rx)),
+            listeners: Default::default(),
+            stop_tx,
+            tasks: Default::default(),
+            error: Default::default(),
+        }
+    }
+
+    /// Register the listener that accepts all frames with priority 0, see [`AsyncCanAdapter::register_listener_with`].
+    #[inline]
+    pub fn register_listener(&self, name: String, listener: Box<dyn CanListener<C, F>>) -> bool {
+        self.register_listener_with(name, listener, vec![], 0)
+    }
+
+    /// Register the listener with the acceptance filters and priority, see [`crate::CanAdapter::register_listener_with`].
+    pub fn register_listener_with(
+        &self,
+        name: String,
+        listener: Box<dyn CanListener<C, F>>,
+        filters: Vec<ListenerFilter>,
+        priority: i32,
+    ) -> bool {
+        log::trace!("AsyncISO-TP - register listener {}", name);
+        match self.listeners.lock() {
+            Ok(mut listeners) => {
+                listeners.insert(name, listener, filters, priority);
+                true
+            },
+            Err(e) => {
+                log::warn!("AsyncISO-TP - listener error {} when registering listener {}", e, name);
+                false
+            },
+        }
+    }
+
+    pub fn unregister_listener(&self, name: &str) -> bool {
+        log::trace!("AsyncISO-TP - unregister listener {}", name);
+        match self.listeners.lock() {
+            Ok(mut listeners) => {
+                listeners.remove(name);
+                true
+            }
+            Err(e) => {
+                log::warn!("AsyncISO-TP - listener error {} when unregistering listener {}", e, name);
+                false
+            }
+        }
+    }
+
+    pub fn unregister_all_listeners(&self) -> bool {
+        match self.listeners.lock() {
+            Ok(mut listeners) => {
+                listeners.clear();
+                true
+            },
+            Err(e) => {
+                log::warn!("AsyncISO-TP - listener error {} when unregistering all listeners", e);
+                false
+            }
+        }
+    }
+
+    pub fn listener_names(&self) -> Vec<String> {
+        match self.listeners.lock() {
+            Ok(v) => v.names(),
+            Err(e) => {
+                log::warn!("AsyncISO-TP - listener error {} when get all listener names", e);
+                vec![]
+            },
+        }
+    }
+
+    #[inline]
+    pub fn sender(&self) -> UnboundedSender<F> {
+        self.sender.clone()
+    }
+
+    /// Whether the worker tasks are running.
+    pub fn is_running(&self) -> bool {
+        self.tasks.iter()
+            .any(|(_, task)| !task.is_finished())
+    }
+
+    /// Spawn the transmit and receive tasks, must be called in a tokio runtime.
+    ///
+    /// Both are plain tokio tasks, the device is only accessed on the blocking threads
+    /// ([`tokio::task::spawn_blocking`]) for each call because [`CanDevice`] is not async.
+    /// The transmit task waits for the queued frames, and the receive task blocks on the device
+    /// when only one channel is opened, then notifies the listeners. The device is polled every `interval_us`
+    /// if it returns without waiting(can't block) or more channels are opened.
+    ///
+    /// # Returns
+    ///
+    /// [`Error::AdapterError`] if the adapter is running, including the task of last run
+    /// that didn't exit after [`AsyncCanAdapter::pause`].
+    pub fn start(&mut self, interval_us: u64) -> Result<(), Error> {
+        // the tasks exited after the last pause are dropped.
+        self.tasks.retain(|(_, task)| !task.is_finished());
+        if !self.tasks.is_empty() {
+            return Err(Error::AdapterError("the adapter is running".into()));
+        }
+
+        self.stop_tx.send_replace(false);
+        if let Ok(mut error) = self.error.lock() {
+            error.take();
+        }
+
+        let device = self.device.clone();
+        let receiver = Arc::clone(&self.receiver);
+        let listeners = Arc::clone(&self.listeners);
+        let error = Arc::clone(&self.error);
+        let mut stopper = self.stop_tx.subscribe();
+        self.tasks.push(("transmit", tokio::spawn(async move {
+            let mut receiver = receiver.lock().await;
+            loop {
+                tokio::select! {
+                    msg = receiver.recv() => match msg {
+                        Some(msg) => Self::transmit_callback(&device, &listeners, &error, msg).await,
+                        None => break,
+                    },
+                    _ = stopper.changed() => break,
+                }
+            }
+            log::info!("AsyncISO-TP - transmit task stopped");
+        })));
+
+        let device = self.device.clone();
+        let listeners = Arc::clone(&self.listeners);
+        let error = Arc::clone(&self.error);
+        let mut stopper = self.stop_tx.subscribe();
+        self.tasks.push(("receive", tokio::spawn(async move {
+            loop {
+                if device.is_closed() {
+                    log::info!("AsyncISO-TP - device closed");
+                    break;
+                }
+
+                let busy = Self::receive_callback(&device, &listeners, &error).await;
+
+                if *stopper.borrow() {
+                    break;
+                }
+
+                if !busy {
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_micros(interval_us)) => {},
+                        _ = stopper.changed() => break,
+                    }
+                }
+            }
+            log::info!("AsyncISO-TP - receive task stopped");
+        })));
+
+        Ok(())
+    }
+
+    /// Signal both tasks to stop and wait for them, the device is kept open so that the adapter can be restarted.
+    ///
+    /// The task that doesn't exit in [`STOP_TIMEOUT`] is kept, [`AsyncCanAdapter::start`] is refused
+    /// until it exits, call `pause` again to wait for it.
+    ///
+    /// # Returns
+    ///
+    /// The first device error occurred since started, or [`Error::AdapterError`]
+    /// if a task panicked or didn't exit in time.
+    pub async fn pause(&mut self) -> Result<(), Error> {
+        log::info!("AsyncISO-TP - pausing adapter");
+        self.stop_tx.send_replace(true);
+
+        let deadline = tokio::time::Instant::now() + STOP_TIMEOUT;
+        let mut result = Ok(());
+        for (name, mut task) in std::mem::take(&mut self.tasks) {
+            match tokio::time::timeout_at(deadline, &mut task).await {
+                Ok(Ok(())) => {},
+                Ok(Err(e)) => {
+                    log::warn!("AsyncISO-TP - error {} when waiting {} task", e, name);
+                    result = result.and(Err(Error::AdapterError(format!("the {} task panicked", name))));
+                },
+                Err(_) => {
+                    log::warn!("AsyncISO-TP - {} task is running after stop signal", name);
+                    result = result.and(Err(Error::AdapterError(format!("the {} task didn't stop in time", name))));
+                    self.tasks.push((name, task));
+                },
+            }
+        }
+
+        let error = self.error.lock()
+            .ok()
+            .and_then(|mut e| e.take());
+        match error {
+            Some(e) => result.and(Err(e)),
+            None => result,
+        }
+    }
+
+    /// Stop the adapter and shut down the device, see [`AsyncCanAdapter::pause`] for the result.
+    ///
+    /// Use [`AsyncCanAdapter::pause`] if the adapter will be restarted.
+    pub async fn stop(&mut self) -> Result<(), Error> {
+        log::info!("AsyncISO-TP - stopping adapter");
+        let result = self.pause().await;
+        self.device.shutdown();
+        result
+    }
+
+    /// Keep the first device error, the later ones are logged only.
+    fn device_error(error: &Arc<Mutex<Option<Error>>>, message: String) {
+        log::warn!("AsyncISO-TP - {}", message);
+        if let Ok(mut error) = error.lock() {
+            error.get_or_insert(Error::AdapterError(message));
+        }
+    }
+
+    async fn transmit_callback(device: &D, listeners: &Listeners<C, F>, error: &Arc<Mutex<Option<Error>>>, msg: F) {
+        log::trace!("AsyncISO-TP - transmitting: {}", msg);
+        let id = msg.id();
+        let chl = msg.channel();
+        match listeners.lock() {
+            Ok(listeners) => listeners.on_frame_transmitting(chl.clone(), &msg),
+            Err(e) => {
+                log::warn!("AsyncISO-TP - listener error {} when notify transmitting listeners", e);
+            }
+        }
+
+        let device = device.clone();
+        match tokio::task::spawn_blocking(move || device.transmit(msg, None)).await {
+            Ok(Ok(_)) => match listeners.lock() {
+                Ok(listeners) => listeners.on_frame_transmitted(chl.clone(), id),
+                Err(e) => {
+                    log::warn!("AsyncISO-TP - listener error {:?} when notify transmitted listeners", e);
+                }
+            },
+            Ok(Err(e)) => Self::device_error(error, format!("error {} when transmitting message", e)),
+            Err(e) => Self::device_error(error, format!("error {} when transmitting message", e)),
+        }
+    }
+
+    /// The registry is notified even if no message is received, it ticks the listeners
+    /// so that the receive timers(N_Ar, N_Cr) of ISO-TP can be checked.
+    ///
+    /// The device is waited at most [`BLOCKING_TIMEOUT`] on a blocking thread if only one channel is opened,
+    /// so the frames are handled as soon as they are received.
+    ///
+    /// # Returns
+    ///
+    /// `false` if no frame is received and the device returns without waiting, the caller polls it later.
+    async fn receive_callback(device: &D, listeners: &Listeners<C, F>, error: &Arc<Mutex<Option<Error>>>) -> bool {
+        let device = device.clone();
+        let start = Instant::now();
+        let (timeout, received) = match tokio::task::spawn_blocking(move || {
+            let channels = device.opened_channels();
+            let timeout = match channels.len() {
+                1 => Some(BLOCKING_TIMEOUT),
+                _ => None,
+            };
+            let received = channels.into_iter()
+                .map(|c| {
+                    let messages = device.receive(c.clone(), timeout);
+                    (c, messages)
+                })
+                .collect::<Vec<_>>();
+            (timeout, received)
+        }).await {
+            Ok(v) => v,
+            Err(e) => {
+                Self::device_error(error, format!("error {} when receiving", e));
+                return false;
+            },
+        };
+
+        let mut busy = false;
+        received.into_iter()
+            .for_each(|(c, messages)| {
+                let messages = messages.unwrap_or_else(|e| {
+                    Self::device_error(error, format!("error {} when receiving from {}", e, c));
+                    Default::default()
+                });
+                busy |= !messages.is_empty();
+                match listeners.lock() {
+                    Ok(mut listeners) => listeners.on_frame_received(c, &messages),
+                    Err(e) => {
+                        log::warn!("AsyncISO-TP - listener error {:?} when notify received listeners", e);
+                    }
+                }
+            });
+
+        busy || timeout.is_some_and(|t| start.elapsed() >= Duration::from_millis(t as u64))
+    }
+}
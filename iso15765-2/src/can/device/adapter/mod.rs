@@ -1,2 +1,207 @@
 mod sync;
 pub use sync::CanAdapter;
+#[cfg(feature = "async")]
+mod asynchronous;
+#[cfg(feature = "async")]
+pub use asynchronous::AsyncCanAdapter;
+
+use std::{collections::{BTreeMap, HashMap}, hash::Hash, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use rs_can::{CanFrame, CanId, CanListener};
+
+/// The mask of 29bit CAN-ID, the filter with it accepts one CAN-ID only.
+pub const EXACT_MASK: u32 = 0x1FFF_FFFF;
+/// The minimum interval of notifying every listener of a channel without frames,
+/// so that the timers of listeners can be checked, see [`ListenerRegistry::on_frame_received`].
+pub(crate) const TICK_INTERVAL: Duration = Duration::from_millis(1);
+
+pub(crate) type Listeners<C, F> = Arc<Mutex<ListenerRegistry<C, F>>>;
+
+/// The acceptance filter of listener, the frame is accepted if `frame_id & mask == id & mask`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ListenerFilter {
+    pub id: u32,
+    pub mask: u32,
+}
+
+impl ListenerFilter {
+    #[inline]
+    pub fn new(id: u32, mask: u32) -> Self {
+        Self { id, mask }
+    }
+
+    /// Accept the frames of `id` only.
+    #[inline]
+    pub fn exact(id: u32) -> Self {
+        Self::new(id, EXACT_MASK)
+    }
+
+    #[inline]
+    pub fn accepts(&self, id: u32) -> bool {
+        (id ^ self.id) & self.mask == 0
+    }
+
+    #[inline]
+    fn is_exact(&self) -> bool {
+        self.mask & EXACT_MASK == EXACT_MASK
+    }
+}
+
+struct ListenerEntry<C, F> {
+    name: String,
+    listener: Box<dyn CanListener<C, F>>,
+    /// Empty means all frames are accepted.
+    filters: Vec<ListenerFilter>,
+    priority: i32,
+}
+
+impl<C, F> ListenerEntry<C, F> {
+    #[inline]
+    fn accepts(&self, id: u32) -> bool {
+        self.filters.iter().any(|f| f.accepts(id))
+    }
+}
+
+/// The listeners of adapter in priority order, the received frames are routed by CAN-ID.
+pub(crate) struct ListenerRegistry<C, F> {
+    /// Sorted by priority(high first) and registration order.
+    entries: Vec<ListenerEntry<C, F>>,
+    /// The indexes of entries whose filters are all exact, keyed by CAN-ID.
+    exact: HashMap<u32, Vec<usize>>,
+    /// The indexes of entries with masked filters.
+    masked: Vec<usize>,
+    /// The indexes of entries without filters.
+    unfiltered: Vec<usize>,
+    /// The time when the listeners are notified without frames last time, keyed by channel.
+    ticked: HashMap<C, Instant>,
+}
+
+impl<C, F> Default for ListenerRegistry<C, F> {
+    fn default() -> Self {
+        Self {
+            entries: Default::default(),
+            exact: Default::default(),
+            masked: Default::default(),
+            unfiltered: Default::default(),
+            ticked: Default::default(),
+        }
+    }
+}
+
+impl<C: Clone + Hash + Eq, F: CanFrame<Channel = C>> ListenerRegistry<C, F> {
+    /// Insert the listener after the ones with same priority, the one with same name is replaced.
+    pub(crate) fn insert(&mut self, name: String, listener: Box<dyn CanListener<C, F>>, filters: Vec<ListenerFilter>, priority: i32) {
+        self.entries.retain(|e| e.name != name);
+        let index = self.entries
+            .partition_point(|e| e.priority >= priority);
+        self.entries.insert(index, ListenerEntry { name, listener, filters, priority });
+        self.reindex();
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|e| e.name != name);
+        self.reindex();
+        self.entries.len() != len
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.reindex();
+    }
+
+    pub(crate) fn names(&self) -> Vec<String> {
+        self.entries.iter()
+            .map(|e| e.name.clone())
+            .collect()
+    }
+
+    pub(crate) fn callback(&self, name: &str, callback: impl FnOnce(&Box<dyn CanListener<C, F>>)) {
+        if let Some(entry) = self.entries.iter().find(|e| e.name == name) {
+            callback(&entry.listener);
+        }
+    }
+
+    pub(crate) fn on_frame_transmitting(&self, channel: C, frame: &F) {
+        self.entries.iter()
+            .for_each(|e| e.listener.on_frame_transmitting(channel.clone(), frame));
+    }
+
+    pub(crate) fn on_frame_transmitted(&self, channel: C, id: CanId) {
+        self.entries.iter()
+            .for_each(|e| e.listener.on_frame_transmitted(channel.clone(), id));
+    }
+
+    /// The listeners are notified in priority order, the ones without filters with all frames,
+    /// and the ones with filters with the frames they accept only(the sub-slices of `frames` in order),
+    /// the others are not called. The frames are looked up by CAN-ID, O(1) for the exact filters
+    /// and O(masked listeners) for the masked ones.
+    ///
+    /// Every listener is also notified without frames at most every [`TICK_INTERVAL`] of each channel,
+    /// so that its timers can be checked(e.g. N_Br of [`crate::CanIsoTp`]) even if no frame is accepted.
+    pub(crate) fn on_frame_received(&mut self, channel: C, frames: &[F]) {
+        // the indexes of frames accepted, keyed by the index of entry.
+        let mut accepted: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (index, frame) in frames.iter().enumerate() {
+            let id = frame.id().into_bits();
+            let exact = self.exact.get(&id)
+                .into_iter()
+                .flatten();
+            let masked = self.masked.iter()
+                .filter(|i| self.entries[**i].accepts(id));
+            exact.chain(masked)
+                .for_each(|i| accepted.entry(*i).or_default().push(index));
+        }
+        if !frames.is_empty() {
+            self.unfiltered.iter()
+                .for_each(|i| { accepted.insert(*i, Vec::new()); });
+        }
+
+        let now = Instant::now();
+        let tick = self.ticked.get(&channel)
+            .is_none_or(|t| now.duration_since(*t) >= TICK_INTERVAL);
+        if tick {
+            self.ticked.insert(channel.clone(), now);
+            (0..self.entries.len())
+                .for_each(|i| { accepted.entry(i).or_default(); });
+        }
+
+        for (i, indexes) in accepted {
+            let entry = &self.entries[i];
+            if entry.filters.is_empty() {
+                entry.listener.on_frame_received(channel.clone(), frames);
+            }
+            else if indexes.is_empty() {
+                entry.listener.on_frame_received(channel.clone(), &[]);
+            }
+            else {
+                indexes.chunk_by(|a, b| a + 1 == *b)
+                    .for_each(|run| entry.listener.on_frame_received(channel.clone(), &frames[run[0]..=run[run.len() - 1]]));
+            }
+        }
+    }
+
+    fn reindex(&mut self) {
+        self.exact.clear();
+        self.masked.clear();
+        self.unfiltered.clear();
+        for (index, entry) in self.entries.iter().enumerate() {
+            if entry.filters.is_empty() {
+                self.unfiltered.push(index);
+                continue;
+            }
+
+            if entry.filters.iter().all(|f| f.is_exact()) {
+                let mut ids = entry.filters.iter()
+                    .map(|f| f.id & EXACT_MASK)
+                    .collect::<Vec<_>>();
+                ids.sort_unstable();
+                ids.dedup();
+                ids.into_iter()
+                    .for_each(|id| self.exact.entry(id).or_default().push(index));
+            }
+            else {
+                self.masked.push(index);
+            }
+        }
+    }
+}
@@ -1,48 +1,82 @@
-use std::{collections::HashMap, fmt::Display, thread, sync::{Arc, Mutex, MutexGuard, Weak, mpsc::{channel, Sender, Receiver}}};
-use std::time::Duration;
+use std::{fmt::Display, hash::Hash, thread, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}, mpsc::{channel, Sender, Receiver, RecvTimeoutError}}};
+use std::time::{Duration, Instant};
 use rs_can::{CanDevice, CanFrame, CanListener};
 
+use crate::error::Error;
+
+use super::{ListenerFilter, Listeners};
+
+/// The time waited for each worker thread to exit after the stop signal.
+pub(crate) const STOP_TIMEOUT: Duration = Duration::from_secs(1);
+/// The maximum time in milliseconds blocked in the device or the transmit queue,
+/// the loops check the stop signal at least this often.
+pub(crate) const BLOCKING_TIMEOUT: u32 = 10;
+
+/// The adapter runs the transmit and receive loops on worker threads.
+///
+/// The adapter can be restarted after [`CanAdapter::pause`], the clones share the same workers.
 #[derive(Clone)]
 pub struct CanAdapter<D, C, F> {
     pub(crate) device: D,
     pub(crate) sender: Sender<F>,
     pub(crate) receiver: Arc<Mutex<Receiver<F>>>,
-    pub(crate) listeners: Arc<Mutex<HashMap<String, Box<dyn CanListener<C, F>>>>>,
-    pub(crate) stop_tx: Sender<()>,
-    pub(crate) stop_rx: Arc<Mutex<Receiver<()>>>,
-    pub(crate) send_task: Weak<thread::JoinHandle<()>>,
-    pub(crate) receive_task: Weak<thread::JoinHandle<()>>,
-    pub(crate) interval: Option<u64>,
+    pub(crate) listeners: Listeners<C, F>,
+    /// The stop signal observed by both loops.
+    pub(crate) stopped: Arc<AtomicBool>,
+    /// The worker threads, they are joined when stopping.
+    /// The ones didn't exit in time are kept, so the adapter isn't restarted while they are running.
+    pub(crate) tasks: Arc<Mutex<Vec<(&'static str, thread::JoinHandle<()>)>>>,
+    /// The first device error occurred since started, it's returned by [`CanAdapter::pause`].
+    pub(crate) error: Arc<Mutex<Option<Error>>>,
 }
 
 impl<D, C, F> CanAdapter<D, C, F>
 where
     D: CanDevice<Channel = C, Frame = F> + Clone + Send + 'static,
-    C: Clone + Display + 'static,
+    C: Clone + Display + Hash + Eq + Send + 'static,
     F: CanFrame<Channel = C> + Clone + Send + Display + 'static,
 {
     pub fn new(device: D) -> Self {
         let (tx, rx) = channel();
-        let (stop_tx, stop_rx) = channel();
         Self {
             device,
             sender: tx,
             receiver: Arc::new(Mutex::new(rx)),
-            listeners: Arc::new(Mutex::new(HashMap::new())),
-            stop_tx,
-            stop_rx: Arc::new(Mutex::new(stop_rx)),
-            send_task: Default::default(),
-            receive_task: Default::default(),
-            interval: Default::default(),
+            listeners: Default::default(),
+            stopped: Arc::new(AtomicBool::new(true)),
+            tasks: Default::default(),
+            error: Default::default(),
         }
     }
 
+    /// Register the listener that accepts all frames with priority 0, see [`CanAdapter::register_listener_with`].
     #[inline]
     pub fn register_listener(&self, name: String, listener: Box<dyn CanListener<C, F>>) -> bool {
+        self.register_listener_with(name, listener, vec![], 0)
+    }
+
+    /// Register the listener, the one with same name is replaced.
+    ///
+    /// It can be called while the adapter is running, the frames received are delivered
+    /// to the listeners registered before.
+    ///
+    /// # Parameters
+    ///
+    /// * `filters` - the received frames accepted by any filter are delivered, empty means all frames.
+    ///   The transmitting notifications are not filtered.
+    /// * `priority` - the listeners with higher priority are notified first,
+    ///   the ones with same priority are notified in registration order.
+    pub fn register_listener_with(
+        &self,
+        name: String,
+        listener: Box<dyn CanListener<C, F>>,
+        filters: Vec<ListenerFilter>,
+        priority: i32,
+    ) -> bool {
         log::trace!("SyncISO-TP - register listener {}", name);
         match self.listeners.lock() {
             Ok(mut listeners) => {
-                listeners.insert(name, listener);
+                listeners.insert(name, listener, filters, priority);
                 true
             },
             Err(e) => {
@@ -79,14 +113,10 @@ where
         }
     }
 
+    /// The names of listeners in priority order.
     pub fn listener_names(&self) -> Vec<String> {
         match self.listeners.lock() {
-            Ok(v) => {
-                v.keys()
-                    .into_iter()
-                    .map(|f| f.clone())
-                    .collect()
-            },
+            Ok(v) => v.names(),
             Err(e) => {
                 log::warn!("SyncISO-TP - listener error {} when get all listener names", e);
                 vec![]
@@ -96,11 +126,7 @@ where
 
     pub fn listener_callback(&self, name: &str, callback: impl FnOnce(&Box<dyn CanListener<C, F>>)) {
         match self.listeners.lock() {
-            Ok(listeners) => {
-                if let Some(listener) = listeners.get(name) {
-                    callback(listener);
-                }
-            },
+            Ok(listeners) => listeners.callback(name, callback),
             Err(e) => {
                 log::warn!("SyncISO-TP - listener error {} when trying to callback", e);
             }
@@ -112,81 +138,210 @@ where
         self.sender.clone()
     }
 
-    pub fn start(&mut self, interval_us: u64) {
-        self.interval = Some(interval_us);
+    /// Whether the worker threads are running.
+    pub fn is_running(&self) -> bool {
+        self.tasks.lock()
+            .is_ok_and(|tasks| tasks.iter().any(|(_, task)| !task.is_finished()))
+    }
 
-        let self_arc = Arc::new(Mutex::new(self.clone()));
-        let stop_rx = Arc::clone(&self.stop_rx);
-        let tx_task = thread::spawn(move || {
-            if let Ok(self_clone) = self_arc.lock() {
-                Self::transmit_loop(self_clone, interval_us, Arc::clone(&stop_rx));
-            }
+    /// Spawn the transmit and receive threads.
+    ///
+    /// The frames are received by blocking on the device when only one channel is opened,
+    /// the device is polled every `interval_us` if it returns without waiting(can't block) or more channels are opened.
+    ///
+    /// # Returns
+    ///
+    /// [`Error::AdapterError`] if the adapter is running, including the worker of last run
+    /// that didn't exit after [`CanAdapter::pause`], or the thread can't be spawned.
+    pub fn start(&mut self, interval_us: u64) -> Result<(), Error> {
+        let mut tasks = self.tasks.lock()
+            .map_err(|e| {
+                log::warn!("SyncISO-TP - tasks error {} when starting", e);
+                Error::DeviceError
+            })?;
+        // the workers exited after the last pause are joined.
+        let _ = Self::join_finished(&mut tasks);
+        if !tasks.is_empty() {
+            return Err(Error::AdapterError("the adapter is running".into()));
+        }
+
+        self.stopped.store(false, Ordering::Release);
+        if let Ok(mut error) = self.error.lock() {
+            error.take();
+        }
+
+        let device = self.device.clone();
+        let receiver = Arc::clone(&self.receiver);
+        let listeners = Arc::clone(&self.listeners);
+        let error = Arc::clone(&self.error);
+        let tx_task = Self::spawn("iso-tp-transmit", &self.device, &self.stopped, interval_us, move || {
+            Self::transmit_callback(&receiver, &device, &listeners, &error, None)
         });
 
-        let self_arc = Arc::new(Mutex::new(self.clone()));
-        let stop_rx = Arc::clone(&self.stop_rx);
-        let rx_task = thread::spawn(move || {
-            if let Ok(self_clone) = self_arc.lock() {
-                Self::receive_loop(self_clone, interval_us, Arc::clone(&stop_rx));
-            }
+        let device = self.device.clone();
+        let listeners = Arc::clone(&self.listeners);
+        let error = Arc::clone(&self.error);
+        let rx_task = Self::spawn("iso-tp-receive", &self.device, &self.stopped, interval_us, move || {
+            Self::receive_callback(&device, &listeners, &error)
         });
 
-        self.send_task = Arc::downgrade(&Arc::new(tx_task));
-        self.receive_task = Arc::downgrade(&Arc::new(rx_task));
+        match (tx_task, rx_task) {
+            (Ok(tx_task), Ok(rx_task)) => {
+                tasks.push(("transmit", tx_task));
+                tasks.push(("receive", rx_task));
+                Ok(())
+            },
+            (tx_task, rx_task) => {
+                self.stopped.store(true, Ordering::Release);
+                let mut error = None;
+                for task in [tx_task, rx_task] {
+                    match task {
+                        Ok(task) => { let _ = task.join(); },
+                        Err(e) => { error.get_or_insert(e); },
+                    }
+                }
+                Err(error.unwrap_or(Error::DeviceError))
+            },
+        }
     }
 
-    pub fn stop(&mut self) {
-        log::info!("SyncISO-TP - stopping adapter");
-        if let Err(e) = self.stop_tx.send(()) {
-            log::warn!("SyncISO-TP - error {} when stopping transmit", e);
+    /// Signal both loops to stop and join them, the device is kept open so that the adapter can be restarted.
+    ///
+    /// The worker that doesn't exit in [`STOP_TIMEOUT`] is kept, [`CanAdapter::start`] is refused
+    /// until it exits, call `pause` again to join it.
+    ///
+    /// # Returns
+    ///
+    /// The first device error occurred since started, or [`Error::AdapterError`]
+    /// if a worker thread panicked or didn't exit in time.
+    pub fn pause(&mut self) -> Result<(), Error> {
+        log::info!("SyncISO-TP - pausing adapter");
+        self.stopped.store(true, Ordering::Release);
+
+        let mut tasks = match self.tasks.lock() {
+            Ok(tasks) => tasks,
+            Err(e) => {
+                log::warn!("SyncISO-TP - tasks error {} when stopping", e);
+                return Err(Error::DeviceError);
+            },
+        };
+
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while tasks.iter().any(|(_, task)| !task.is_finished()) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
         }
 
-        thread::sleep(Duration::from_micros(2 * self.interval.unwrap_or(50 * 1000)));
+        let mut result = Self::join_finished(&mut tasks);
+        for (name, _) in tasks.iter() {
+            log::warn!("SyncISO-TP - {} task is running after stop signal", name);
+            result = result.and(Err(Error::AdapterError(format!("the {} task didn't stop in time", name))));
+        }
 
-        if let Some(task) = self.send_task.upgrade() {
-            if !task.is_finished() {
-                log::warn!("SyncISO-TP - transmit task is running after stop signal");
-            }
+        let error = self.error.lock()
+            .ok()
+            .and_then(|mut e| e.take());
+        match error {
+            Some(e) => result.and(Err(e)),
+            None => result,
         }
+    }
+
+    /// Stop the adapter and shut down the device, see [`CanAdapter::pause`] for the result.
+    ///
+    /// Use [`CanAdapter::pause`] if the adapter will be restarted.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        log::info!("SyncISO-TP - stopping adapter");
+        let result = self.pause();
+        self.device.shutdown();
+        result
+    }
 
-        if let Some(task) = self.receive_task.upgrade() {
-            if !task.is_finished() {
-                log::warn!("SyncISO-TP - receive task is running after stop signal");
+    /// Join the finished workers and remove them, the running ones are kept.
+    ///
+    /// # Returns
+    ///
+    /// [`Error::AdapterError`] if any worker panicked.
+    fn join_finished(tasks: &mut Vec<(&'static str, thread::JoinHandle<()>)>) -> Result<(), Error> {
+        let mut result = Ok(());
+        let (finished, running) = std::mem::take(tasks).into_iter()
+            .partition::<Vec<_>, _>(|(_, task)| task.is_finished());
+        *tasks = running;
+        for (name, task) in finished {
+            if task.join().is_err() {
+                log::warn!("SyncISO-TP - {} task panicked", name);
+                result = result.and(Err(Error::AdapterError(format!("the {} task panicked", name))));
             }
         }
 
-        self.device.shutdown();
+        result
     }
 
-    fn transmit_loop(device: MutexGuard<Self>, interval_us: u64, stopper: Arc<Mutex<Receiver<()>>>) {
-        Self::loop_util(
-            device,
-            interval_us,
-            stopper,
-            |d| Self::transmit_callback(&d.receiver, &d.device, &d.listeners, None)
-        );
+    /// Spawn the thread that calls `callback` until stopped or the device is closed,
+    /// it sleeps `interval_us` after the `callback` returns `false`(nothing done without waiting).
+    fn spawn(
+        name: &str,
+        device: &D,
+        stopped: &Arc<AtomicBool>,
+        interval_us: u64,
+        callback: impl Fn() -> bool + Send + 'static,
+    ) -> Result<thread::JoinHandle<()>, Error> {
+        let device = device.clone();
+        let stopped = Arc::clone(stopped);
+        thread::Builder::new()
+            .name(name.into())
+            .spawn(move || {
+                loop {
+                    if device.is_closed() {
+                        log::info!("SyncISO-TP - device closed");
+                        break;
+                    }
+
+                    let busy = callback();
+
+                    if stopped.load(Ordering::Acquire) {
+                        log::info!("SyncISO-TP - stop sync");
+                        break;
+                    }
+
+                    if !busy {
+                        thread::sleep(Duration::from_micros(interval_us));
+                    }
+                }
+            })
+            .map_err(|e| {
+                log::warn!("SyncISO-TP - error {} when spawning {} task", e, name);
+                Error::AdapterError(e.to_string())
+            })
     }
 
-    fn receive_loop(device: MutexGuard<Self>, interval_us: u64, stopper: Arc<Mutex<Receiver<()>>>) {
-        Self::loop_util(
-            device,
-            interval_us,
-            stopper,
-            |d| Self::receive_callback(&d.device, &d.listeners, None)
-        )
+    /// Keep the first device error, the later ones are logged only.
+    fn device_error(error: &Arc<Mutex<Option<Error>>>, message: String) {
+        log::warn!("SyncISO-TP - {}", message);
+        if let Ok(mut error) = error.lock() {
+            error.get_or_insert(Error::AdapterError(message));
+        }
     }
 
-    fn transmit_callback(receiver: &Arc<Mutex<Receiver<F>>>, device: &D, listeners: &Arc<Mutex<HashMap<String, Box<dyn CanListener<C, F>>>>>, timeout: Option<u32>) {
-        if let Ok(receiver) = receiver.lock() {
-            if let Ok(msg) = receiver.try_recv() {
+    /// Wait for the frame queued and transmit it.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the transmit queue can't be waited.
+    fn transmit_callback(
+        receiver: &Arc<Mutex<Receiver<F>>>,
+        device: &D,
+        listeners: &Listeners<C, F>,
+        error: &Arc<Mutex<Option<Error>>>,
+        timeout: Option<u32>,
+    ) -> bool {
+        let Ok(receiver) = receiver.lock() else { return false };
+        match receiver.recv_timeout(Duration::from_millis(BLOCKING_TIMEOUT as u64)) {
+            Ok(msg) => {
                 log::trace!("SyncISO-TP - transmitting: {}", msg);
                 let id = msg.id();
                 let chl = msg.channel();
                 match listeners.lock() {
-                    Ok(listeners) => {
-                        listeners.values()
-                            .for_each(|l| l.on_frame_transmitting(chl.clone(), &msg));
-                    },
+                    Ok(listeners) => listeners.on_frame_transmitting(chl.clone(), &msg),
                     Err(e) => {
                         log::warn!("SyncISO-TP - listener error {} when notify transmitting listeners", e);
                     }
@@ -194,59 +349,57 @@ where
 
                 match device.transmit(msg, timeout) {
                     Ok(_) => match listeners.lock() {
-                        Ok(listeners) => {
-                            listeners.values()
-                                .for_each(|l| l.on_frame_transmitted(chl.clone(), id));
-                        },
+                        Ok(listeners) => listeners.on_frame_transmitted(chl.clone(), id),
                         Err(e) => {
                             log::warn!("SyncISO-TP - listener error {:?} when notify transmitted listeners", e);
                         }
                     },
-                    Err(e) => {
-                        log::warn!("SyncISO-TP - error {} when transmitting message", e);
-                    }
+                    Err(e) => Self::device_error(error, format!("error {} when transmitting message", e)),
                 }
-            }
+                true
+            },
+            Err(RecvTimeoutError::Timeout) => true,
+            Err(RecvTimeoutError::Disconnected) => false,
         }
     }
 
-    fn receive_callback(device: &D, listeners: &Arc<Mutex<HashMap<String, Box<dyn CanListener<C, F>>>>>, timeout: Option<u32>) {
+    /// The registry is notified even if no message is received, it ticks the listeners
+    /// so that the receive timers(N_Ar, N_Cr) of ISO-TP can be checked.
+    ///
+    /// The device is waited at most [`BLOCKING_TIMEOUT`] if only one channel is opened,
+    /// so the frames are handled as soon as they are received.
+    ///
+    /// # Returns
+    ///
+    /// `false` if no frame is received and the device returns without waiting, the caller polls it later.
+    fn receive_callback(
+        device: &D,
+        listeners: &Listeners<C, F>,
+        error: &Arc<Mutex<Option<Error>>>,
+    ) -> bool {
         let channels = device.opened_channels();
+        let timeout = match channels.len() {
+            1 => Some(BLOCKING_TIMEOUT),
+            _ => None,
+        };
+        let start = Instant::now();
+        let mut received = false;
         channels.into_iter()
             .for_each(|c| {
-                if let Ok(messages) = device.receive(c.clone(), timeout) {
-                    if !messages.is_empty() {
-                        match listeners.lock() {
-                            Ok(listeners) => {
-                                listeners.values()
-                                    .for_each(|l| l.on_frame_received(c.clone(), &messages));
-                            }
-                            Err(e) => {
-                                log::warn!("SyncISO-TP - listener error {:?} when notify received listeners", e);
-                            }
-                        }
+                let messages = device.receive(c.clone(), timeout)
+                    .unwrap_or_else(|e| {
+                        Self::device_error(error, format!("error {} when receiving from {}", e, c));
+                        Default::default()
+                    });
+                received |= !messages.is_empty();
+                match listeners.lock() {
+                    Ok(mut listeners) => listeners.on_frame_received(c.clone(), &messages),
+                    Err(e) => {
+                        log::warn!("SyncISO-TP - listener error {:?} when notify received listeners", e);
                     }
                 }
             });
-    }
 
-    fn loop_util(device: MutexGuard<Self>, interval: u64, stopper: Arc<Mutex<Receiver<()>>>, callback: fn(&MutexGuard<Self>)) {
-        loop {
-            if device.device.is_closed() {
-                log::info!("SyncISO-TP - device closed");
-                break;
-            }
-
-            callback(&device);
-
-            if let Ok(stopper) = stopper.try_lock() {
-                if let Ok(()) = stopper.try_recv() {
-                    log::info!("SyncISO-TP - stop sync");
-                    break;
-                }
-            }
-
-            thread::sleep(Duration::from_micros(interval));
-        }
+        received || timeout.is_some_and(|t| start.elapsed() >= Duration::from_millis(t as u64))
     }
 }
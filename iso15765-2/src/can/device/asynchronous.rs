@@ -0,0 +1,85 @@
+use std::{pin::Pin, task::{Context, Poll}};
+use rs_can::CanFrame;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_stream::Stream;
+
+use crate::can::address::AddressType;
+use crate::core::Event;
+use crate::error::Error;
+
+use super::CanIsoTp;
+
+pub(crate) type StreamSender = UnboundedSender<Result<Vec<u8>, Error>>;
+
+/// The stream of complete PDUs received by [`CanIsoTp`].
+#[derive(Debug)]
+pub struct IsoTpStream {
+    receiver: UnboundedReceiver<Result<Vec<u8>, Error>>,
+}
+
+impl IsoTpStream {
+    /// Receive the next complete PDU or error.
+    #[inline]
+    pub async fn recv(&mut self) -> Option<Result<Vec<u8>, Error>> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for IsoTpStream {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl<C: Clone, F: CanFrame<Channel = C>> CanIsoTp<C, F> {
+    /// The async version of [`CanIsoTp::write`].
+    ///
+    /// The writer sleeps until the next deadline of the machine(N_As, N_Bs or STmin),
+    /// or it's woken by the change of writing(confirmation, flow control).
+    pub async fn write_async(&self, addr_type: AddressType, data: Vec<u8>) -> Result<(), Error> {
+        self.write_start(addr_type, data)?;
+        loop {
+            // created before checking, so that the result set after checking is not missed.
+            let notified = self.notify.notified();
+            if let Some(result) = self.write_result() {
+                return result;
+            }
+
+            match self.check_timers() {
+                Some(wait) => { let _ = tokio::time::timeout(wait, notified).await; },
+                None => notified.await,
+            }
+        }
+    }
+
+    /// Create the stream of complete PDUs, the previous stream is closed.
+    pub fn stream(&self) -> IsoTpStream {
+        let (tx, rx) = unbounded_channel();
+        match self.stream.lock() {
+            Ok(mut stream) => {
+                stream.replace(tx);
+            },
+            Err(e) => log::warn!("CanIsoTp::stream: {}", e),
+        }
+
+        IsoTpStream { receiver: rx }
+    }
+
+    pub(crate) fn stream_event(&self, event: &Event) {
+        let item = match event {
+            Event::DataReceived(data) => Ok(data.clone()),
+            Event::ReceiveAborted(e) => Err(e.clone()),
+            _ => return,
+        };
+
+        if let Ok(mut stream) = self.stream.lock() {
+            let closed = stream.as_ref()
+                .is_some_and(|s| s.send(item).is_err());
+            if closed {
+                stream.take();
+            }
+        }
+    }
+}
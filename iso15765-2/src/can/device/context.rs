@@ -1,5 +1,7 @@
-use crate::constants::{CONSECUTIVE_SEQUENCE_START, P2_MAX, P2_STAR_MAX};
-use crate::core::{Event, FlowControlContext};
+use std::{collections::VecDeque, sync::{Condvar, Mutex}, time::{Duration, Instant}};
+
+use crate::can::address::IsoTpRole;
+use crate::constants::{P2_MAX, P2_STAR_MAX};
 use crate::error::Error;
 
 #[derive(Debug, Clone)]
@@ -41,85 +43,98 @@ impl P2 {
     }
 }
 
-#[derive(Debug, Default, Clone)]
-pub(crate) struct FlowCtrl {
-    pub(crate) st_min: u32,    // μs
-    pub(crate) block_size: u8,
-}
-
-/// Consecutive frame data context.
-#[derive(Debug, Default, Clone, Eq, PartialEq)]
-pub(crate) struct Consecutive {
-    pub(crate) sequence: Option<u8>,
-    pub(crate) length: Option<u32>,
-    pub(crate) buffer: Vec<u8>,
-}
-
 #[derive(Debug, Default, Clone)]
 pub struct Context {
     pub(crate) p2: P2,
-    pub(crate) flow_ctrl: Option<FlowCtrl>,
-    pub(crate) consecutive: Consecutive,
+    /// The role of node, it's kept when the context is reset.
+    pub(crate) role: IsoTpRole,
+    /// The CAN-ID of the PDU writing.
+    pub(crate) tx_can_id: u32,
+    /// The result of the PDU writing, see [`crate::IsoTpMachine::write`].
+    pub(crate) tx_result: Option<Result<(), Error>>,
+    /// The data of the frame transmitting, used to confirm the transmission.
+    pub(crate) transmitting: Option<Vec<u8>>,
+    /// The time when the PDU writing is started and its length, see [`crate::IsoTpObserver`].
+    pub(crate) tx_started: Option<(Instant, usize)>,
+    /// The time when the first frame is received.
+    pub(crate) rx_started: Option<Instant>,
 }
 
 impl Context {
-    /// reset st_min/consecutive/block_size
+    /// reset p2/result of writing
     #[inline]
     pub(crate) fn reset(&mut self) {
         self.p2 = Default::default();
-        self.clear_flow_ctrl();
-        self.clear_consecutive();
-    }
-    #[inline]
-    pub(crate) fn clear_flow_ctrl(&mut self) {
-        self.flow_ctrl = Default::default();
+        self.tx_result = Default::default();
     }
-    #[inline]
-    pub(crate) fn update_flow_ctrl(&mut self, ctx: FlowControlContext) {
-        self.flow_ctrl = Some(FlowCtrl {
-            st_min: ctx.st_min_us(),
-            block_size: ctx.block_size(),
-        });
+}
+
+/// The maximum number of PDUs buffered for [`crate::CanIsoTp::read`].
+pub(crate) const MAX_BUFFERED_PDU: usize = 0x40;
+
+/// Received PDUs and errors buffered for blocking read.
+#[derive(Debug, Default)]
+pub(crate) struct PduBuffer {
+    pub(crate) data: Mutex<VecDeque<Result<Vec<u8>, Error>>>,
+    pub(crate) condvar: Condvar,
+}
+
+impl PduBuffer {
+    pub(crate) fn push(&self, item: Result<Vec<u8>, Error>) {
+        if let Ok(mut data) = self.data.lock() {
+            if data.len() >= MAX_BUFFERED_PDU {
+                log::warn!("ISO-TP - the read buffer is full, the oldest PDU is dropped");
+                data.pop_front();
+            }
+            data.push_back(item);
+            self.condvar.notify_all();
+        }
     }
-    #[inline]
-    pub(crate) fn clear_consecutive(&mut self) {
-        self.consecutive.sequence = Default::default();
-        self.consecutive.length = Default::default();
-        self.consecutive.buffer.clear();
+
+    pub(crate) fn clear(&self) {
+        if let Ok(mut data) = self.data.lock() {
+            data.clear();
+        }
     }
-    #[inline]
-    pub(crate) fn update_consecutive(&mut self, length: u32, mut data: Vec<u8>) {
-        self.consecutive.length = Some(length);
-        self.consecutive.buffer.append(&mut data);
+
+    /// Wait for the next item until timeout.
+    pub(crate) fn pop(&self, timeout: Duration) -> Option<Result<Vec<u8>, Error>> {
+        let data = self.data.lock().ok()?;
+        let (mut data, _) = self.condvar
+            .wait_timeout_while(data, timeout, |data| data.is_empty())
+            .ok()?;
+
+        data.pop_front()
     }
-    pub(crate) fn append_consecutive(&mut self, sequence: u8, mut data: Vec<u8>) -> Result<Event, Error> {
-        if self.consecutive.length.is_none() {
-            return Err(Error::MixFramesError);
-        }
+}
 
-        let target = match self.consecutive.sequence {
-            Some(v) => match v {
-                ..=0x0E => v + 1,
-                _ => 0,
-            },
-            None => CONSECUTIVE_SEQUENCE_START
-        };
-        self.consecutive.sequence = Some(target);
-        if sequence != target {
-            return Err(Error::InvalidSequence { expect: target, actual: sequence });
-        }
+/// Wake the writer when the adapter changes the state of writing,
+/// e.g. the frame is confirmed or the flow control frame is received.
+#[derive(Debug, Default)]
+pub(crate) struct WriteSignal {
+    pub(crate) changed: Mutex<bool>,
+    pub(crate) condvar: Condvar,
+}
 
-        self.consecutive.buffer.append(&mut data);
+impl WriteSignal {
+    pub(crate) fn notify(&self) {
+        if let Ok(mut changed) = self.changed.lock() {
+            *changed = true;
+            self.condvar.notify_all();
+        }
+    }
 
-        let buff_len = self.consecutive.buffer.len();
-        let target_len = self.consecutive.length.unwrap() as usize;
-        if buff_len >= target_len {
-            self.consecutive.buffer.resize(target_len, 0);
-            let data = self.consecutive.buffer.clone();
-            Ok(Event::DataReceived(data))
+    /// Clear the signal before checking the state of writing, so the change after checking is not missed.
+    pub(crate) fn reset(&self) {
+        if let Ok(mut changed) = self.changed.lock() {
+            *changed = false;
         }
-        else {
-            Ok(Event::Wait)
+    }
+
+    /// Wait until notified or timeout.
+    pub(crate) fn wait(&self, timeout: Duration) {
+        if let Ok(changed) = self.changed.lock() {
+            let _ = self.condvar.wait_timeout_while(changed, timeout, |changed| !*changed);
         }
     }
 }
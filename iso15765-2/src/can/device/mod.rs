@@ -1,23 +1,77 @@
 pub(crate) mod adapter;
 pub(crate) mod context;
+mod mux;
+pub use mux::{ConnectionKey, IsoTpMux};
+pub(crate) mod observer;
+pub use observer::IsoTpObserver;
+#[cfg(feature = "async")]
+mod asynchronous;
+#[cfg(feature = "async")]
+pub use asynchronous::IsoTpStream;
+
+use std::{any::Any, fmt::Display, io::{ErrorKind, Read}, sync::{Arc, Mutex, mpsc::Sender}, time::{Duration, Instant}, thread};
+use rs_can::{CanFrame, CanId, CanListener, MAX_FRAME_SIZE};
+
+use crate::can::address::{Address, AddressType, IsoTpRole};
+use crate::core::{Event, EventListener, FlowControlConfig, State, Statistics, TimingConfig, Version};
+use crate::error::Error;
+use crate::frame::{Frame, FrameRef, FrameType};
+use crate::machine::{Action, Actions, IsoTpMachine};
+use crate::standard::MAX_FD_FRAME_SIZE;
+
+/// The interval of checking the timers when the machine has no deadline, and the maximum wait of async writing.
+pub(crate) const WRITE_POLL_INTERVAL: Duration = Duration::from_millis(1);
+/// The time before the deadline spent on spinning instead of sleeping,
+/// the sleep of OS is not accurate enough for STmin of 100-900µs.
+pub(crate) const SPIN_THRESHOLD: Duration = Duration::from_micros(200);
+
+/// The sender that transmits CAN frames of [`CanIsoTp`] to the adapter.
+pub trait FrameSender<F>: Send + Sync {
+    fn send_frame(&self, frame: F) -> Result<(), Error>;
+}
 
-use std::{any::Any, fmt::Display, sync::{Arc, Mutex, mpsc::Sender}, time::{Duration, Instant}, thread};
-use rs_can::{CanFrame, CanId, CanListener};
+impl<F: Send> FrameSender<F> for Sender<F> {
+    #[inline]
+    fn send_frame(&self, frame: F) -> Result<(), Error> {
+        self.send(frame)
+            .map_err(|e| {
+                log::warn!("ISO-TP - transmit failed: {:?}", e);
+                Error::DeviceError
+            })
+    }
+}
 
-use crate::can::address::{Address, AddressType};
-use crate::constants::{DEFAULT_P2_START_MS, TIMEOUT_AS_ISO15765_2, TIMEOUT_CR_ISO15765_2};
-use crate::core::{Event, EventListener, FlowControlContext, FlowControlState, State};
-use crate::error::Error;
-use crate::frame::Frame;
+/// The PDU transfer API shared by the userspace [`CanIsoTp`] and the kernel ISO-TP socket,
+/// the callers can switch between them without code changes.
+pub trait IsoTpTransport {
+    /// Write the PDU and wait until it is transmitted.
+    fn write(&self, addr_type: AddressType, data: Vec<u8>) -> Result<(), Error>;
+    /// Read the next complete PDU received, `timeout` in milliseconds.
+    fn read(&self, timeout: u64) -> Result<Vec<u8>, Error>;
+    /// Write the data and read the response.
+    fn transceive(&self, addr_type: AddressType, data: Vec<u8>, timeout: u64) -> Result<Vec<u8>, Error> {
+        self.write(addr_type, data)?;
+        self.read(timeout)
+    }
+}
 
 #[derive(Clone)]
 pub struct CanIsoTp<C, F> {
     pub(crate) channel: C,
     pub(crate) address: Arc<Mutex<Address>>,
-    pub(crate) sender: Sender<F>,
+    pub(crate) sender: Arc<dyn FrameSender<F>>,
     pub(crate) context: Arc<Mutex<context::Context>>,
-    pub(crate) state: Arc<Mutex<State>>,
+    pub(crate) machine: Arc<Mutex<IsoTpMachine>>,
+    /// The time zero of the machine.
+    pub(crate) epoch: Instant,
     pub(crate) listener: Arc<Mutex<Box<dyn EventListener>>>,
+    pub(crate) buffer: Arc<context::PduBuffer>,
+    pub(crate) signal: Arc<context::WriteSignal>,
+    pub(crate) observer: Arc<Mutex<Option<Arc<dyn IsoTpObserver>>>>,
+    #[cfg(feature = "async")]
+    pub(crate) notify: Arc<tokio::sync::Notify>,
+    #[cfg(feature = "async")]
+    pub(crate) stream: Arc<Mutex<Option<asynchronous::StreamSender>>>,
 }
 
 unsafe impl<C, F> Send for CanIsoTp<C, F> {}
@@ -26,16 +80,35 @@ impl<C: Clone, F: CanFrame<Channel = C>> CanIsoTp<C, F> {
     pub fn new(
         channel: C,
         address: Address,
-        sender: Sender<F>,
+        sender: impl FrameSender<F> + 'static,
         listener: Box<dyn EventListener>,
     ) -> Self {
+        Self::with_sender(channel, address, Arc::new(sender), listener)
+    }
+
+    /// Create the instance that shares the sender with others, see [`IsoTpMux`].
+    pub(crate) fn with_sender(
+        channel: C,
+        address: Address,
+        sender: Arc<dyn FrameSender<F>>,
+        listener: Box<dyn EventListener>,
+    ) -> Self {
+        let machine = IsoTpMachine::new(address.format.ext_len(), address.tx_ext(AddressType::Physical));
         Self {
             channel,
             address: Arc::new(Mutex::new(address)),
             sender,
             context: Default::default(),
-            state: Default::default(),
+            machine: Arc::new(Mutex::new(machine)),
+            epoch: Instant::now(),
             listener: Arc::new(Mutex::new(listener)),
+            buffer: Default::default(),
+            signal: Default::default(),
+            observer: Default::default(),
+            #[cfg(feature = "async")]
+            notify: Default::default(),
+            #[cfg(feature = "async")]
+            stream: Default::default(),
         }
     }
 
@@ -49,127 +122,284 @@ impl<C: Clone, F: CanFrame<Channel = C>> CanIsoTp<C, F> {
         }
     }
 
-    #[inline]
-    pub fn update_address(&self, address: Address) {
-        if let Ok(mut addr) = self.address.lock() {
-            *addr = address;
+    /// Set the role of node, see [`IsoTpRole`].
+    ///
+    /// The server receives the functional requests on `fid` too, only the single frame is allowed
+    /// to be addressed functionally, and replies on the physical `tx_id`.
+    pub fn set_role(&self, role: IsoTpRole) {
+        match self.context.lock() {
+            Ok(mut ctx) => {
+                ctx.role = role;
+            },
+            Err(e) =>
+                log::warn!("CanIsoTp::set_role: {}", e),
         }
     }
 
-    pub fn write(&self, addr_type: AddressType, data: Vec<u8>) -> Result<(), Error> {
-        self.state_append(State::Idle);
-        self.context_reset();
-        log::trace!("ISO-TP - Sending: {}", hex::encode(&data));
+    pub fn role(&self) -> Result<IsoTpRole, Error> {
+        match self.context.lock() {
+            Ok(ctx) => Ok(ctx.role),
+            Err(_) => {
+                log::warn!("can't get `context`");
+                Err(Error::DeviceError)
+            }
+        }
+    }
 
-        let frames = Frame::from_data(data)?;
-        let frame_len = frames.len();
+    /// Set the observer of frames and transfers, see [`observer::IsoTpMetrics`].
+    pub fn set_observer(&self, observer: impl IsoTpObserver + 'static) {
+        match self.observer.lock() {
+            Ok(mut v) => {
+                v.replace(Arc::new(observer));
+            },
+            Err(e) =>
+                log::warn!("CanIsoTp::set_observer: {}", e),
+        }
+    }
 
-        let can_id = match self.address.lock() {
-            Ok(address) => match addr_type {
-                AddressType::Physical => Ok(address.tx_id),
-                AddressType::Functional => Ok(address.fid)
+    pub fn set_timing(&self, timing: TimingConfig) {
+        match self.machine.lock() {
+            Ok(mut machine) => {
+                machine.set_timing(timing);
             },
-            Err(_) => {
-                log::warn!("can't get address context");
+            Err(e) =>
+                log::warn!("CanIsoTp::set_timing: {}", e),
+        }
+    }
+
+    pub fn set_flow_ctrl(&self, config: FlowControlConfig) -> Result<(), Error> {
+        match self.machine.lock() {
+            Ok(mut machine) => machine.set_flow_ctrl(config),
+            Err(e) => {
+                log::warn!("CanIsoTp::set_flow_ctrl: {}", e);
                 Err(Error::DeviceError)
             },
-        }?;
-        let mut need_flow_ctrl = frame_len > 1;
-        let mut index = 0;
-        for iso_tp_frame in frames {
-            let data = iso_tp_frame.encode(None);
-            let mut frame = F::new(CanId::from_bits(can_id, None), data.as_slice())
-                .ok_or({
-                    log::warn!("fail to convert iso-tp frame to can frame");
-                    Error::DeviceError
-                })?;
-            frame.set_channel(self.channel.clone());
-
-            if need_flow_ctrl {
-                need_flow_ctrl = false;
-                self.state_append(State::Sending | State::WaitFlowCtrl);
+        }
+    }
+
+    pub fn set_version(&self, version: Version) {
+        match self.machine.lock() {
+            Ok(mut machine) => {
+                machine.set_version(version);
+            },
+            Err(e) =>
+                log::warn!("CanIsoTp::set_version: {}", e),
+        }
+    }
+
+    /// Set TX_DL, the data length of transmitted CAN frame.
+    ///
+    /// The value must be 8(classic CAN), 12, 16, 20, 24, 32, 48 or 64(CAN FD).
+    pub fn set_tx_dl(&self, tx_dl: usize) -> Result<(), Error> {
+        match self.machine.lock() {
+            Ok(mut machine) => machine.set_tx_dl(tx_dl),
+            Err(e) => {
+                log::warn!("CanIsoTp::set_tx_dl: {}", e);
+                Err(Error::DeviceError)
+            },
+        }
+    }
+
+    /// Set the value of bytes padded to the transmitted frames, `None` means the default value.
+    pub fn set_padding(&self, padding: Option<u8>) {
+        match self.machine.lock() {
+            Ok(mut machine) => {
+                machine.set_padding(padding);
+            },
+            Err(e) =>
+                log::warn!("CanIsoTp::set_padding: {}", e),
+        }
+    }
+
+    pub fn version(&self) -> Result<Version, Error> {
+        match self.machine.lock() {
+            Ok(machine) => Ok(machine.version()),
+            Err(_) => {
+                log::warn!("can't get `machine`");
+                Err(Error::DeviceError)
             }
-            else {
-                self.write_waiting(&mut index)?;
-                self.state_append(State::Sending);
+        }
+    }
+
+    pub fn timing(&self) -> Result<TimingConfig, Error> {
+        match self.machine.lock() {
+            Ok(machine) => Ok(machine.timing()),
+            Err(_) => {
+                log::warn!("can't get `machine`");
+                Err(Error::DeviceError)
             }
-            self.sender.send(frame)
-                .map_err(|e| {
-                    log::warn!("ISO-TP - transmit failed: {:?}", e);
-                    Error::DeviceError
-                })?;
         }
+    }
 
-        Ok(())
+    /// The statistics of the consecutive frames written, see [`IsoTpMachine::statistics`].
+    pub fn statistics(&self) -> Result<Statistics, Error> {
+        match self.machine.lock() {
+            Ok(machine) => Ok(machine.statistics()),
+            Err(_) => {
+                log::warn!("can't get `machine`");
+                Err(Error::DeviceError)
+            }
+        }
     }
 
-    #[inline]
-    pub(crate) fn on_single_frame(&self, data: Vec<u8>) {
-        self.iso_tp_event(Event::DataReceived(data));
+    pub fn reset_statistics(&self) {
+        match self.machine.lock() {
+            Ok(mut machine) => {
+                machine.reset_statistics();
+            },
+            Err(e) =>
+                log::warn!("CanIsoTp::reset_statistics: {}", e),
+        }
     }
 
-    #[inline]
-    pub(crate) fn on_first_frame(&self, tx_id: u32, length: u32, data: Vec<u8>) {
-        self.update_consecutive(length, data);
-
-        let iso_tp_frame = Frame::default_flow_ctrl_frame();
-        let data = iso_tp_frame.encode(None);
-        match F::new(CanId::from_bits(tx_id, None), data.as_slice()) {
-            Some(mut frame) => {
-                frame.set_channel(self.channel.clone());
-
-                self.state_append(State::Sending);
-                match self.sender.send(frame) {
-                    Ok(_) => {
-                        self.iso_tp_event(Event::FirstFrameReceived);
-                    },
-                    Err(e) => {
-                        log::warn!("ISO-TP - transmit failed: {:?}", e);
-                        self.state_append(State::Error);
+    /// The state of writing, see [`IsoTpMachine::tx_state`].
+    pub fn tx_state(&self) -> Result<State, Error> {
+        match self.machine.lock() {
+            Ok(machine) => Ok(machine.tx_state()),
+            Err(_) => {
+                log::warn!("can't get `machine`");
+                Err(Error::DeviceError)
+            }
+        }
+    }
 
-                        self.iso_tp_event(Event::ErrorOccurred(Error::DeviceError));
-                    },
-                }
-            },
-            None => log::error!("ISO-TP - convert `iso-tp frame` to `can-frame` error"),
+    /// The state of reading, see [`IsoTpMachine::rx_state`].
+    pub fn rx_state(&self) -> Result<State, Error> {
+        match self.machine.lock() {
+            Ok(machine) => Ok(machine.rx_state()),
+            Err(_) => {
+                log::warn!("can't get `machine`");
+                Err(Error::DeviceError)
+            }
         }
     }
 
     #[inline]
-    pub(crate) fn on_consecutive_frame(&self, sequence: u8, data: Vec<u8>) {
-        match self.append_consecutive(sequence, data) {
-            Ok(event) => self.iso_tp_event(event),
-            Err(e) => {
-                self.state_append(State::Error);
-                self.iso_tp_event(Event::ErrorOccurred(e));
+    pub fn update_address(&self, address: Address) {
+        if let Ok(mut addr) = self.address.lock() {
+            *addr = address;
+        }
+        if let Ok(mut machine) = self.machine.lock() {
+            machine.set_address(address.format.ext_len(), address.tx_ext(AddressType::Physical));
+        }
+    }
+
+    pub fn write(&self, addr_type: AddressType, data: Vec<u8>) -> Result<(), Error> {
+        self.write_start(addr_type, data)?;
+        loop {
+            self.signal.reset();
+            if let Some(result) = self.write_result() {
+                return result;
             }
+
+            self.wait_writing()?;
         }
     }
 
-    #[inline]
-    pub(crate) fn on_flow_ctrl_frame(&self, ctx: FlowControlContext) {
-        match ctx.state() {
-            FlowControlState::Continues => {
-                self.state_remove(State::WaitBusy | State::WaitFlowCtrl);
-            },
-            FlowControlState::Wait => {
-                self.state_append(State::WaitBusy);
-                self.iso_tp_event(Event::Wait);
-                return;
+    /// Write the PDU read from `reader`, the data is read frame by frame when the flow control allows,
+    /// so the memory used is bounded however large the PDU is.
+    ///
+    /// # Parameters
+    ///
+    /// * `length` - the length of PDU, it's declared by the first frame.
+    /// * `reader` - the source of PDU, [`Error::InvalidDataLength`] if it ends before `length` bytes.
+    pub fn write_from_reader(&self, addr_type: AddressType, length: usize, mut reader: impl Read) -> Result<(), Error> {
+        log::trace!("ISO-TP - Sending {} bytes from reader", length);
+
+        let address = self.write_address(addr_type)?;
+        self.with_machine(|machine| machine.write_stream(length, address.tx_ext(addr_type)))??;
+        self.write_context(&address, addr_type, length)?;
+
+        let mut buffer = [0; MAX_FD_FRAME_SIZE];
+        let mut written = 0;
+        loop {
+            self.signal.reset();
+            if let Some(result) = self.write_result() {
+                return result;
             }
-            FlowControlState::Overload => {
-                self.state_append(State::Error);
-                self.iso_tp_event(Event::ErrorOccurred(Error::OverloadFlow));
-                return;
+
+            let required = self.with_machine(|machine| machine.data_required())?;
+            if required > 0 {
+                let actions = match reader.read(&mut buffer[..required]) {
+                    Ok(0) => {
+                        let error = Error::InvalidDataLength { actual: written, expect: length };
+                        self.with_machine(|machine| machine.abort_write(error))?
+                    },
+                    Ok(n) => {
+                        written += n;
+                        let now = self.now();
+                        self.with_machine(|machine| machine.feed(&buffer[..n], now))??
+                    },
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        let error = Error::SourceError(e.to_string());
+                        self.with_machine(|machine| machine.abort_write(error))?
+                    },
+                };
+                self.dispatch(actions);
+                continue;
             }
+
+            self.wait_writing()?;
         }
+    }
 
-        if let Ok(mut context) = self.context.lock() {
-            context.update_flow_ctrl(ctx);
-        };
+    /// Read the next complete PDU received.
+    ///
+    /// # Parameters
+    ///
+    /// * `timeout` - the time in milliseconds to wait for the single frame or the first frame,
+    ///   the consecutive frames of a started PDU are waited until N_Cr is timeout.
+    ///
+    /// # Returns
+    ///
+    /// The PDU or the reason why the PDU receiving is aborted, the invalid frames are ignored.
+    pub fn read(&self, timeout: u64) -> Result<Vec<u8>, Error> {
+        let deadline = Instant::now() + Duration::from_millis(timeout);
+        loop {
+            // the timers(e.g. N_Cr) are checked by the reader too, not only when the adapter notifying.
+            let next = self.check_timers();
+            let remain = deadline.saturating_duration_since(Instant::now());
+            let receiving = self.receiving();
+            if remain.is_zero() && !receiving {
+                return self.buffer.pop(Duration::ZERO)
+                    .unwrap_or(Err(Error::ReadTimeout { value: timeout, unit: "ms" }));
+            }
+
+            // the reception in progress is waited after timeout until it's completed or aborted.
+            let wait = match next {
+                Some(next) if remain.is_zero() => next,
+                Some(next) => next.min(remain),
+                None if remain.is_zero() => WRITE_POLL_INTERVAL,
+                None => remain,
+            };
+            if let Some(item) = self.buffer.pop(wait) {
+                return item;
+            }
+        }
+    }
+
+    /// Write the data and read the response, the PDUs received before writing are discarded.
+    ///
+    /// See [`CanIsoTp::write`] and [`CanIsoTp::read`].
+    pub fn transceive(&self, addr_type: AddressType, data: Vec<u8>, timeout: u64) -> Result<Vec<u8>, Error> {
+        self.buffer.clear();
+        self.write(addr_type, data)?;
+        self.read(timeout)
     }
 
     fn iso_tp_event(&self, event: Event) {
+        // the observer is called before the PDU is readable, the metrics are up to date when `read` returns.
+        self.observe_event(&event);
+        #[cfg(feature = "async")]
+        self.stream_event(&event);
+
+        match &event {
+            Event::DataReceived(data) => self.buffer.push(Ok(data.clone())),
+            Event::ReceiveAborted(e) => self.buffer.push(Err(e.clone())),
+            _ => {},
+        }
+
         match self.listener.lock() {
             Ok(mut listener) => {
                 // println!("ISO-TP - Sending iso-tp event: {:?}", event);
@@ -177,7 +407,9 @@ impl<C: Clone, F: CanFrame<Channel = C>> CanIsoTp<C, F> {
                     Event::DataReceived(data) => {
                         log::debug!("ISO-TP - Received: {}", hex::encode(data));
                     },
-                    Event::ErrorOccurred(_) =>
+                    Event::ErrorOccurred(_) |
+                    Event::TransmitAborted(_) |
+                    Event::ReceiveAborted(_) =>
                         log::warn!("ISO-TP - Sending iso-tp event: {:?}", event),
                     _ => log::trace!("ISO-TP - Sending iso-tp event: {:?}", event),
                 }
@@ -187,69 +419,96 @@ impl<C: Clone, F: CanFrame<Channel = C>> CanIsoTp<C, F> {
         }
     }
 
-    fn write_waiting(&self, index: &mut usize) -> Result<(), Error> {
-        match self.context.lock() {
-            Ok(ctx) => {
-                if let Some(ctx) = &ctx.flow_ctrl {
-                    if ctx.block_size != 0 {
-                        if (*index + 1) == ctx.block_size as usize {
-                            *index = 0;
-                            self.state_append(State::WaitFlowCtrl);
-                        }
-                        else {
-                            *index += 1;
-                        }
-                    }
-                    thread::sleep(Duration::from_micros(ctx.st_min as u64));
+    fn observe_event(&self, event: &Event) {
+        let rx_started = match event {
+            Event::FirstFrameReceived => {
+                if let Ok(mut ctx) = self.context.lock() {
+                    ctx.rx_started = Some(Instant::now());
                 }
-
-                Ok(())
+                return;
             },
+            Event::DataReceived(_) |
+            Event::ReceiveAborted(_) => self.context.lock()
+                .ok()
+                .and_then(|mut ctx| ctx.rx_started.take()),
+            _ => None,
+        };
+
+        self.observe(|observer, address| match event {
+            Event::DataReceived(data) => observer.on_transfer_completed(address, &observer::TransferRecord {
+                direction: observer::TransferDirection::Receive,
+                bytes: data.len(),
+                duration: rx_started.map(|t| t.elapsed()).unwrap_or_default(),
+            }),
+            Event::TransmitAborted(Error::Timeout { timer, .. }) |
+            Event::ReceiveAborted(Error::Timeout { timer, .. }) => observer.on_timeout(address, *timer),
+            Event::ErrorOccurred(e) |
+            Event::TransmitAborted(e) |
+            Event::ReceiveAborted(e) => observer.on_error(address, e),
+            _ => {},
+        });
+    }
+
+    /// Call the observer if it's set.
+    fn observe(&self, callback: impl FnOnce(&dyn IsoTpObserver, &Address)) {
+        let Some(observer) = self.observer.lock()
+            .ok()
+            .and_then(|v| v.clone()) else { return };
+        if let Ok(address) = self.address.lock() {
+            callback(observer.as_ref(), &address);
+        }
+    }
+
+    /// Start writing the data by the machine, the context is reset after the machine accepts it.
+    pub(crate) fn write_start(&self, addr_type: AddressType, data: Vec<u8>) -> Result<(), Error> {
+        log::trace!("ISO-TP - Sending: {}", hex::encode(&data));
+
+        let address = self.write_address(addr_type)?;
+        let actions = match self.machine.lock() {
+            Ok(mut machine) => machine.write(&data, address.tx_ext(addr_type), self.now()),
             Err(_) => {
-                log::warn!("can't get `context`");
+                log::warn!("can't get `machine`");
                 Err(Error::DeviceError)
             }
         }?;
+        // the frames are dispatched after the context is set, they are transmitted on its CAN-ID.
+        self.write_context(&address, addr_type, data.len())?;
+        self.dispatch(actions);
 
-        let start = Instant::now();
-        loop {
-            if self.state_contains(State::Error) {
-                return Err(Error::DeviceError);
-            }
+        Ok(())
+    }
 
-            if self.state_contains(State::Sending) {
-                if start.elapsed() > Duration::from_millis(TIMEOUT_AS_ISO15765_2 as u64) {
-                    return Err(Error::Timeout { value: TIMEOUT_AS_ISO15765_2 as u64, unit: "ms" });
-                }
-            }
-            else if self.state_contains(State::WaitBusy) {
-                let p2_star = match self.context.lock() {
-                    Ok(ctx) => {
-                        ctx.p2.p2_star_ms()
-                    },
-                    Err(_) => DEFAULT_P2_START_MS,
-                };
-                if start.elapsed() > Duration::from_millis(p2_star) {
-                    return Err(Error::Timeout { value: p2_star, unit: "ms" });
-                }
-            }
-            else if self.state_contains(State::WaitFlowCtrl) {
-                if start.elapsed() > Duration::from_millis(TIMEOUT_CR_ISO15765_2 as u64) {
-                    return Err(Error::Timeout { value: TIMEOUT_CR_ISO15765_2 as u64, unit: "ms" });
+    /// The address to write with, the role is checked before the machine is changed.
+    fn write_address(&self, addr_type: AddressType) -> Result<Address, Error> {
+        match self.context.lock() {
+            Ok(ctx) => {
+                if ctx.role == IsoTpRole::Server && addr_type == AddressType::Functional {
+                    return Err(Error::InvalidParam("the server can't write functionally".into()));
                 }
-            }
-            else {
-                break;
+            },
+            Err(_) => {
+                log::warn!("can't get `context`");
+                return Err(Error::DeviceError);
             }
         }
-
-        Ok(())
+        match self.address.lock() {
+            Ok(address) => Ok(*address),
+            Err(_) => {
+                log::warn!("can't get address context");
+                Err(Error::DeviceError)
+            },
+        }
     }
 
-    fn append_consecutive(&self, sequence: u8, data: Vec<u8>) -> Result<Event, Error> {
+    /// Reset the context of writing, it's called only if the machine accepts the PDU,
+    /// so the transfer in progress isn't changed by the write rejected with [`Error::Busy`].
+    fn write_context(&self, address: &Address, addr_type: AddressType, length: usize) -> Result<(), Error> {
         match self.context.lock() {
-            Ok(mut context) => {
-                context.append_consecutive(sequence, data)
+            Ok(mut ctx) => {
+                ctx.reset();
+                ctx.tx_can_id = address.tx_can_id(addr_type);
+                ctx.tx_started = Some((Instant::now(), length));
+                Ok(())
             },
             Err(_) => {
                 log::warn!("can't get `context`");
@@ -258,61 +517,229 @@ impl<C: Clone, F: CanFrame<Channel = C>> CanIsoTp<C, F> {
         }
     }
 
-    fn update_consecutive(&self, length: u32, data: Vec<u8>) {
-        if let Ok(mut context) = self.context.lock() {
-            context.update_consecutive(length, data);
+    /// Take the result of writing if the PDU is transmitted or aborted.
+    pub(crate) fn write_result(&self) -> Option<Result<(), Error>> {
+        match self.context.lock() {
+            Ok(mut ctx) => ctx.tx_result.take(),
+            Err(_) => {
+                log::warn!("can't get `context`");
+                Some(Err(Error::DeviceError))
+            }
         }
     }
 
-    fn context_reset(&self) {
-        if let Ok(mut context) = self.context.lock() {
-            context.reset();
+    /// Check the timers of the machine.
+    ///
+    /// # Returns
+    ///
+    /// The time to wait until the next deadline of the machine, or [`WRITE_POLL_INTERVAL`] if there is no deadline.
+    pub(crate) fn poll_timeout(&self) -> Duration {
+        self.check_timers()
+            .unwrap_or(WRITE_POLL_INTERVAL)
+    }
+
+    /// Check the timers of the machine.
+    ///
+    /// # Returns
+    ///
+    /// The time to wait until the next deadline of the machine, `None` if there is no deadline.
+    fn check_timers(&self) -> Option<Duration> {
+        let now = self.now();
+        let (actions, deadline) = match self.machine.lock() {
+            Ok(mut machine) => (machine.poll_timeout(now), machine.next_deadline()),
+            Err(_) => return Some(WRITE_POLL_INTERVAL),
         };
+        self.dispatch(actions);
+
+        deadline.map(|d| d.saturating_sub(now))
     }
 
-    #[inline]
-    fn state_contains(&self, flags: State) -> bool {
-        match self.state.lock() {
-            Ok(v) => {
-                // log::debug!("ISO-TP - current state(state contains): {} contains: {}", *v, flags);
-                *v & flags != State::Idle
-            },
+    /// Check the timers and wait for the next deadline of the machine or the change of writing
+    /// by the adapter(confirmation, flow control), the writer is woken as soon as the frame is confirmed.
+    ///
+    /// Only the STmin of the next consecutive frame is waited by [`CanIsoTp::wait_until`].
+    fn wait_writing(&self) -> Result<(), Error> {
+        let wait = self.poll_timeout();
+        let now = self.now();
+        match self.with_machine(|machine| machine.next_frame())? {
+            Some(next) if next.saturating_sub(now) <= wait => Self::wait_until(self.epoch + next),
+            _ => self.signal.wait(wait),
+        }
+
+        Ok(())
+    }
+
+    /// Sleep until [`SPIN_THRESHOLD`] before the deadline, then spin until the deadline.
+    fn wait_until(deadline: Instant) {
+        if let Some(wait) = deadline.checked_duration_since(Instant::now())
+            .and_then(|wait| wait.checked_sub(SPIN_THRESHOLD)) {
+            thread::sleep(wait);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Call `f` with the machine locked.
+    fn with_machine<R>(&self, f: impl FnOnce(&mut IsoTpMachine) -> R) -> Result<R, Error> {
+        match self.machine.lock() {
+            Ok(mut machine) => Ok(f(&mut machine)),
             Err(_) => {
-                log::warn!("ISO-TP - state mutex is poisoned");
-                false
-            },
+                log::warn!("can't get `machine`");
+                Err(Error::DeviceError)
+            }
         }
     }
 
+    /// The time since [`CanIsoTp::epoch`], used as the time of the machine.
     #[inline]
-    fn state_append(&self, flags: State) {
-        match self.state.lock() {
-            Ok(mut v) => {
-                if flags == State::Idle {
-                    *v = State::Idle;
-                } else if flags.contains(State::Error) {
-                    *v = State::Error;
-                }
-                else {
-                    *v |= flags;
-                }
+    pub(crate) fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
 
-                log::trace!("ISO-TP - current state(state append): {}", *v);
+    /// Execute the actions of the machine.
+    pub(crate) fn dispatch(&self, actions: Actions) {
+        for action in actions {
+            match action {
+                Action::Transmit(data) => {
+                    let (can_id, can_fd) = self.tx_params(false);
+                    if let Err(e) = self.transmit(can_id, can_fd, data) {
+                        if let Ok(mut machine) = self.machine.lock() {
+                            machine.abort_transmit();
+                        }
+                        self.iso_tp_event(Event::TransmitAborted(e.clone()));
+                        self.write_finished(Err(e));
+                    }
+                },
+                Action::TransmitFlowCtrl(data) => {
+                    let (can_id, can_fd) = self.tx_params(true);
+                    if let Err(e) = self.transmit(can_id, can_fd, data) {
+                        if let Ok(mut machine) = self.machine.lock() {
+                            machine.abort_receive();
+                        }
+                        self.iso_tp_event(Event::ReceiveAborted(e));
+                    }
+                },
+                Action::Event(event) => self.iso_tp_event(event),
+                Action::TransmitFinished(result) => self.write_finished(result),
             }
-            Err(_) => log::warn!("ISO-TP - state mutex is poisoned when appending"),
         }
     }
 
+    /// Get the CAN-ID and whether CAN FD is used to transmit the PDU or the flow control frame.
+    fn tx_params(&self, flow_ctrl: bool) -> (u32, bool) {
+        let (dl, can_id) = match flow_ctrl {
+            true => (
+                self.machine.lock().map(|m| m.rx_dl()).unwrap_or(MAX_FRAME_SIZE),
+                self.address.lock().map(|a| a.tx_id).unwrap_or_default(),
+            ),
+            false => (
+                self.machine.lock().map(|m| m.tx_dl()).unwrap_or(MAX_FRAME_SIZE),
+                self.context.lock().map(|c| c.tx_can_id).unwrap_or_default(),
+            ),
+        };
+
+        (can_id, dl > MAX_FRAME_SIZE)
+    }
+
+    fn transmit(&self, can_id: u32, can_fd: bool, data: Vec<u8>) -> Result<(), Error> {
+        let mut frame = F::new(CanId::from_bits(can_id, None), data.as_slice())
+            .ok_or_else(|| {
+                log::warn!("fail to convert iso-tp frame to can frame");
+                Error::DeviceError
+            })?;
+        frame.set_channel(self.channel.clone())
+            .set_can_fd(can_fd);
+
+        self.sender.send_frame(frame)
+    }
+
+    fn write_finished(&self, result: Result<(), Error>) {
+        let tx_started = match self.context.lock() {
+            Ok(mut ctx) => {
+                let tx_started = ctx.tx_started.take().filter(|_| result.is_ok());
+                ctx.tx_result = Some(result);
+                tx_started
+            },
+            Err(_) => None,
+        };
+        if let Some((start, bytes)) = tx_started {
+            self.observe(|observer, address| observer.on_transfer_completed(address, &observer::TransferRecord {
+                direction: observer::TransferDirection::Transmit,
+                bytes,
+                duration: start.elapsed(),
+            }));
+        }
+        self.wake_writer();
+    }
+
+    /// Wake the writer waiting for the change of writing, see [`CanIsoTp::wait_writing`].
     #[inline]
-    fn state_remove(&self, flags: State) {
-        match self.state.lock() {
-            Ok(mut v) => {
-                v.remove(flags);
-                log::trace!("ISO-TP - current state(state remove): {}", *v);
+    fn wake_writer(&self) {
+        self.signal.notify();
+        #[cfg(feature = "async")]
+        self.notify.notify_waiters();
+    }
+
+    fn observe_frame(&self, data: &[u8], offset: usize) {
+        self.observe(|observer, address| {
+            let Some(record) = observer::FrameRecord::new(data, offset, Instant::now()) else { return };
+            observer.on_frame_received(address, &record);
+            if record.frame_type == FrameType::FlowControl {
+                if let Ok(Frame::FlowControlFrame(ctx)) = Frame::decode_with_offset(data, offset) {
+                    observer.on_flow_ctrl_received(address, &ctx);
+                }
+            }
+        });
+    }
+
+    /// Whether the frame is transmitted by this connection(physical or functional),
+    /// the server transmits on the physical `tx_id` only.
+    fn is_tx_id(&self, id: u32) -> bool {
+        let server = self.role().is_ok_and(|role| role == IsoTpRole::Server);
+        self.address.lock()
+            .is_ok_and(|address| id == address.tx_id || (!server && id == address.fid))
+    }
+
+    /// Handle the functional request received by server.
+    ///
+    /// The single frame is delivered without disturbing the physical receiving,
+    /// the other frames are rejected because the functional request can't be segmented.
+    fn on_functional_frame(&self, data: &[u8], offset: usize) {
+        match FrameRef::decode_with_offset(data, offset) {
+            Ok(FrameRef::SingleFrame { data }) =>
+                self.iso_tp_event(Event::DataReceived(data.to_vec())),
+            Ok(frame) => {
+                log::warn!("ISO-TP - {:?} is not allowed for functional request", frame.frame_type());
+                self.iso_tp_event(Event::ErrorOccurred(Error::UnexpectedFrame(frame.frame_type())));
             },
-            Err(_) =>log::warn!("ISO-TP - state mutex is poisoned when removing"),
+            Err(e) => log::warn!("ISO-TP - invalid functional request: {}", e),
         }
     }
+
+    /// Whether a multi-frame PDU is receiving.
+    fn receiving(&self) -> bool {
+        self.machine.lock()
+            .map(|machine| machine.is_receiving())
+            .unwrap_or_default()
+    }
+}
+
+impl<C: Clone, F: CanFrame<Channel = C>> IsoTpTransport for CanIsoTp<C, F> {
+    #[inline]
+    fn write(&self, addr_type: AddressType, data: Vec<u8>) -> Result<(), Error> {
+        CanIsoTp::write(self, addr_type, data)
+    }
+
+    #[inline]
+    fn read(&self, timeout: u64) -> Result<Vec<u8>, Error> {
+        CanIsoTp::read(self, timeout)
+    }
+
+    #[inline]
+    fn transceive(&self, addr_type: AddressType, data: Vec<u8>, timeout: u64) -> Result<Vec<u8>, Error> {
+        CanIsoTp::transceive(self, addr_type, data, timeout)
+    }
 }
 
 impl<C, F> CanListener<C, F> for CanIsoTp<C, F>
@@ -325,67 +752,67 @@ where
         self
     }
 
-    fn on_frame_transmitting(&self, _: C, _: &F) {
+    fn on_frame_transmitting(&self, channel: C, frame: &F) {
+        if channel != self.channel || !self.is_tx_id(frame.id().into_bits()) {
+            return;
+        }
 
+        if let Ok(mut ctx) = self.context.lock() {
+            ctx.transmitting = Some(frame.data().to_vec());
+        }
     }
 
     fn on_frame_transmitted(&self, channel: C, id: CanId) {
         let id = id.into_bits();
         log::trace!("ISO-TP transmitted: {:04X} from {}", id, channel);
-        if channel != self.channel {
+        if channel != self.channel || !self.is_tx_id(id) {
             return;
         }
 
-        if let Ok(address) = self.address.lock() {
-            if id == address.tx_id ||
-                id == address.fid {
-                self.state_remove(State::Sending);
+        let data = self.context.lock()
+            .map(|mut ctx| ctx.transmitting.take().unwrap_or_default())
+            .unwrap_or_default();
+        self.observe(|observer, address| {
+            if let Some(record) = observer::FrameRecord::new(&data, address.format.ext_len(), Instant::now()) {
+                observer.on_frame_transmitted(address, &record);
             }
-        }
+        });
+        let actions = match self.machine.lock() {
+            Ok(mut machine) => machine.on_transmitted(&data, self.now()),
+            Err(_) => return,
+        };
+        self.dispatch(actions);
+        // the STmin of the next consecutive frame starts from the confirmation.
+        self.wake_writer();
     }
 
     fn on_frame_received(&self, channel: C, frames: &[F]) {
-        if channel != self.channel
-            || self.state_contains(State::Error) {
+        if channel != self.channel {
             return;
         }
 
-        let address_id = if let Ok(address) = self.address.lock() {
-            Some((address.tx_id, address.rx_id))
-        }
-        else {
-            None
-        };
+        self.poll_timeout();
 
-        if let Some(address) = address_id {
-            for frame in frames {
-                if frame.id().into_bits() == address.1 {
-                    log::debug!("ISO-TP received: {}", frame);
-
-                    match Frame::decode(frame.data()) {
-                        Ok(frame) => match frame {
-                            Frame::SingleFrame { data } => {
-                                self.on_single_frame(data);
-                            }
-                            Frame::FirstFrame { length, data } => {
-                                self.on_first_frame(address.0, length, data);
-                            }
-                            Frame::ConsecutiveFrame { sequence, data } => {
-                                self.on_consecutive_frame(sequence, data);
-                            },
-                            Frame::FlowControlFrame(ctx) => {
-                                self.on_flow_ctrl_frame(ctx);
-                            },
-                        },
-                        Err(e) => {
-                            log::warn!("ISO-TP - data convert to frame failed: {}", e);
-                            self.state_append(State::Error);
-                            self.iso_tp_event(Event::ErrorOccurred(e));
-
-                            break;
-                        }
-                    }
-                }
+        let address = match self.address.lock() {
+            Ok(address) => *address,
+            Err(_) => return,
+        };
+        let server = self.role().is_ok_and(|role| role == IsoTpRole::Server);
+        for frame in frames {
+            if address.is_rx_frame(frame.id().into_bits(), frame.data()) {
+                log::debug!("ISO-TP received: {}", frame);
+                self.observe_frame(frame.data(), address.format.ext_len());
+
+                let actions = match self.machine.lock() {
+                    Ok(mut machine) => machine.on_frame(frame.data(), self.now()),
+                    Err(_) => return,
+                };
+                self.dispatch(actions);
+                self.wake_writer();
+            } else if server && address.is_func_rx_frame(frame.id().into_bits(), frame.data()) {
+                log::debug!("ISO-TP received functional: {}", frame);
+                self.observe_frame(frame.data(), address.format.ext_len());
+                self.on_functional_frame(frame.data(), address.format.ext_len());
             }
         }
     }
@@ -0,0 +1,342 @@
+use std::{any::Any, collections::HashMap, fmt::Display, sync::{Arc, Mutex}, thread};
+use rs_can::{CanFrame, CanId, CanListener};
+
+use crate::can::address::{Address, AddressType, IsoTpRole};
+use crate::core::EventListener;
+use crate::error::Error;
+use crate::standard::{single_frame_size, MAX_FRAME_SIZE};
+
+use super::{CanIsoTp, FrameSender};
+
+/// The key of connection in [`IsoTpMux`], the rx_id and the address information(N_TA or N_AE)
+/// expected in the first data byte of received frames, see [`Address::rx_ext`].
+///
+/// The connections of extended or mixed addressing can share one rx_id.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ConnectionKey {
+    pub rx_id: u32,
+    pub rx_ext: Option<u8>,
+}
+
+impl ConnectionKey {
+    #[inline]
+    pub fn new(rx_id: u32, rx_ext: Option<u8>) -> Self {
+        Self { rx_id, rx_ext }
+    }
+}
+
+/// The key of connection without address extension(normal or normal fixed addressing).
+impl From<u32> for ConnectionKey {
+    #[inline]
+    fn from(rx_id: u32) -> Self {
+        Self::new(rx_id, None)
+    }
+}
+
+impl From<&Address> for ConnectionKey {
+    #[inline]
+    fn from(address: &Address) -> Self {
+        Self::new(address.rx_id, address.rx_ext())
+    }
+}
+
+/// The keys of connections and the address information of transmitted frames, keyed by CAN-ID.
+type TxIndex = HashMap<u32, Vec<(Option<u8>, ConnectionKey)>>;
+
+#[derive(Clone)]
+pub(crate) struct Connection<C, F> {
+    address: Address,
+    iso_tp: CanIsoTp<C, F>,
+}
+
+/// The ISO-TP connections on one channel, the received frames are routed by CAN-ID
+/// and the address information of extended or mixed addressing.
+///
+/// Register the mux to adapter instead of registering each [`CanIsoTp`],
+/// the connections can be used in different threads to transfer concurrently.
+#[derive(Clone)]
+pub struct IsoTpMux<C, F> {
+    pub(crate) channel: C,
+    pub(crate) sender: Arc<dyn FrameSender<F>>,
+    /// The connections keyed by rx_id and rx_ext.
+    pub(crate) connections: Arc<Mutex<HashMap<ConnectionKey, Connection<C, F>>>>,
+    /// The connections and the address information of transmitted frames keyed by tx_id and fid,
+    /// used to route the transmitted frames.
+    pub(crate) tx_index: Arc<Mutex<TxIndex>>,
+    /// The connections notified of the frame transmitting, the confirmation of the CAN-ID is routed to them.
+    pub(crate) transmitting: Arc<Mutex<HashMap<u32, Vec<ConnectionKey>>>>,
+}
+
+impl<C: Clone, F: CanFrame<Channel = C> + Clone> IsoTpMux<C, F> {
+    pub fn new(channel: C, sender: impl FrameSender<F> + 'static) -> Self {
+        Self {
+            channel,
+            sender: Arc::new(sender),
+            connections: Default::default(),
+            tx_index: Default::default(),
+            transmitting: Default::default(),
+        }
+    }
+
+    /// Add a connection, the [`ConnectionKey`] of address must be unique in the mux.
+    ///
+    /// The connections sharing one rx_id must all carry the address information(extended or mixed addressing)
+    /// or none of them.
+    ///
+    /// # Returns
+    ///
+    /// The [`CanIsoTp`] of the connection, don't call [`CanIsoTp::update_address`] on it,
+    /// remove it and add a new one instead.
+    pub fn add(&self, address: Address, listener: Box<dyn EventListener>) -> Result<CanIsoTp<C, F>, Error> {
+        let mut connections = self.connections.lock()
+            .map_err(|e| {
+                log::warn!("IsoTpMux::add: {}", e);
+                Error::DeviceError
+            })?;
+        let key = ConnectionKey::from(&address);
+        if connections.contains_key(&key) {
+            return Err(Error::InvalidParam(format!("`rx_id`({:04X}) already exists", address.rx_id)));
+        }
+        if connections.keys().any(|k| k.rx_id == key.rx_id && k.rx_ext.is_some() != key.rx_ext.is_some()) {
+            return Err(Error::InvalidParam(format!("`rx_id`({:04X}) is used by other address format", address.rx_id)));
+        }
+
+        let iso_tp = CanIsoTp::with_sender(self.channel.clone(), address, Arc::clone(&self.sender), listener);
+        connections.insert(key, Connection { address, iso_tp: iso_tp.clone() });
+        if let Ok(mut index) = self.tx_index.lock() {
+            index.entry(address.tx_id).or_default().push((address.tx_ext(AddressType::Physical), key));
+            if address.fid != address.tx_id {
+                index.entry(address.fid).or_default().push((address.tx_ext(AddressType::Functional), key));
+            }
+        }
+
+        Ok(iso_tp)
+    }
+
+    /// Remove the connection by key, a rx_id is the key of the connection without address extension.
+    pub fn remove(&self, key: impl Into<ConnectionKey>) -> Option<CanIsoTp<C, F>> {
+        let key = key.into();
+        let connection = self.connections.lock()
+            .ok()?
+            .remove(&key)?;
+        if let Ok(mut index) = self.tx_index.lock() {
+            index.values_mut()
+                .for_each(|v| v.retain(|(_, k)| *k != key));
+            index.retain(|_, v| !v.is_empty());
+        }
+
+        Some(connection.iso_tp)
+    }
+
+    /// Get the connection by key, a rx_id is the key of the connection without address extension.
+    #[inline]
+    pub fn get(&self, key: impl Into<ConnectionKey>) -> Option<CanIsoTp<C, F>> {
+        self.connections.lock()
+            .ok()?
+            .get(&key.into())
+            .map(|c| c.iso_tp.clone())
+    }
+
+    pub fn keys(&self) -> Vec<ConnectionKey> {
+        match self.connections.lock() {
+            Ok(connections) => connections.keys().copied().collect(),
+            Err(e) => {
+                log::warn!("IsoTpMux::keys: {}", e);
+                vec![]
+            },
+        }
+    }
+
+    /// The rx_ids of connections, the shared rx_id is listed once.
+    pub fn rx_ids(&self) -> Vec<u32> {
+        let mut rx_ids = self.keys()
+            .into_iter()
+            .map(|k| k.rx_id)
+            .collect::<Vec<_>>();
+        rx_ids.sort_unstable();
+        rx_ids.dedup();
+        rx_ids
+    }
+
+    /// The connections transmitting by the CAN-ID(tx_id or fid) and the address information.
+    fn connections_of(&self, id: u32, ext: Option<u8>) -> Vec<(ConnectionKey, CanIsoTp<C, F>)> {
+        let keys = match self.tx_index.lock() {
+            Ok(index) => index.get(&id)
+                .map(|v| v.iter()
+                    .filter(|(tx_ext, _)| tx_ext.is_none() || *tx_ext == ext)
+                    .map(|(_, k)| *k)
+                    .collect::<Vec<_>>())
+                .unwrap_or_default(),
+            Err(_) => return vec![],
+        };
+        keys.into_iter()
+            .filter_map(|k| self.get(k).map(|c| (k, c)))
+            .collect()
+    }
+
+    /// The key of connection that receives the frame, `None` if the frame is not addressed physically.
+    fn route(connections: &HashMap<ConnectionKey, Connection<C, F>>, id: u32, data: &[u8]) -> Option<ConnectionKey> {
+        let key = ConnectionKey::from(id);
+        if connections.contains_key(&key) {
+            return Some(key);
+        }
+
+        let key = ConnectionKey::new(id, Some(*data.first()?));
+        connections.contains_key(&key)
+            .then_some(key)
+    }
+
+    /// Send the single frame request to the functional address and collect the responses
+    /// from every connection whose fid is `fid`.
+    ///
+    /// # Parameters
+    ///
+    /// * `fid` - the functional address identifier.
+    /// * `data` - the request.
+    /// * `timeout` - the time in milliseconds to wait for each response, see [`CanIsoTp::read`].
+    ///
+    /// # Returns
+    ///
+    /// The responses keyed by connection, the connection that does not respond is [`Error::ReadTimeout`].
+    /// [`Error::InvalidDataLength`] if the request does not fit in one single frame.
+    pub fn broadcast(&self, fid: u32, data: Vec<u8>, timeout: u64) -> Result<HashMap<ConnectionKey, Result<Vec<u8>, Error>>, Error> {
+        let targets: Vec<_> = match self.connections.lock() {
+            Ok(connections) => connections.values()
+                .filter(|c| c.address.fid == fid)
+                .cloned()
+                .collect(),
+            Err(e) => {
+                log::warn!("IsoTpMux::broadcast: {}", e);
+                return Err(Error::DeviceError);
+            },
+        };
+        let first = targets.first()
+            .ok_or_else(|| Error::InvalidParam(format!("`fid`({:04X}) is not found", fid)))?;
+        let tx_dl = first.iso_tp.machine.lock()
+            .map(|m| m.tx_dl())
+            .unwrap_or(MAX_FRAME_SIZE);
+        let ext_len = first.address.tx_ext(AddressType::Functional)
+            .map_or(0, |_| 1);
+        let capacity = single_frame_size(tx_dl) - ext_len;
+        if data.len() > capacity {
+            return Err(Error::InvalidDataLength { actual: data.len(), expect: capacity });
+        }
+
+        targets.iter()
+            .for_each(|c| c.iso_tp.buffer.clear());
+        first.iso_tp.write(AddressType::Functional, data)?;
+
+        let results = thread::scope(|s| {
+            let handles: Vec<_> = targets.iter()
+                .map(|c| {
+                    let iso_tp = c.iso_tp.clone();
+                    (ConnectionKey::from(&c.address), s.spawn(move || iso_tp.read(timeout)))
+                })
+                .collect();
+            handles.into_iter()
+                .map(|(key, handle)| (key, handle.join().unwrap_or(Err(Error::DeviceError))))
+                .collect()
+        });
+
+        Ok(results)
+    }
+}
+
+impl<C, F> CanListener<C, F> for IsoTpMux<C, F>
+where
+    C: Clone + Eq + Display + Send + 'static,
+    F: CanFrame<Channel = C> + Clone + Display + 'static
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn on_frame_transmitting(&self, channel: C, frame: &F) {
+        if channel != self.channel {
+            return;
+        }
+
+        let id = frame.id().into_bits();
+        let connections = self.connections_of(id, frame.data().first().copied());
+        if let Ok(mut transmitting) = self.transmitting.lock() {
+            transmitting.insert(id, connections.iter().map(|(k, _)| *k).collect());
+        }
+        connections.into_iter()
+            .for_each(|(_, c)| c.on_frame_transmitting(channel.clone(), frame));
+    }
+
+    /// The confirmation is routed to the connections notified of the frame transmitting,
+    /// because the connections of extended or mixed addressing can share one tx_id.
+    fn on_frame_transmitted(&self, channel: C, id: CanId) {
+        if channel != self.channel {
+            return;
+        }
+
+        let keys = self.transmitting.lock()
+            .ok()
+            .and_then(|mut v| v.remove(&id.into_bits()))
+            .unwrap_or_default();
+        keys.into_iter()
+            .filter_map(|k| self.get(k))
+            .for_each(|c| c.on_frame_transmitted(channel.clone(), id));
+    }
+
+    /// Each frame is routed to its connection by one or two lookups, the functional requests are
+    /// shared by all servers on the fid. Only the connections receiving frames are notified,
+    /// with the runs of their frames in order.
+    ///
+    /// Every connection is notified without frames when `frames` is empty(the tick of adapter),
+    /// so that its timers are checked even if no frame is received.
+    fn on_frame_received(&self, channel: C, frames: &[F]) {
+        if channel != self.channel {
+            return;
+        }
+
+        let connections = match self.connections.lock() {
+            Ok(connections) => {
+                if frames.is_empty() {
+                    connections.values()
+                        .map(|c| (Vec::new(), c.iso_tp.clone()))
+                        .collect::<Vec<_>>()
+                }
+                else {
+                    // the indexes of frames received by each connection.
+                    let mut routed: HashMap<ConnectionKey, Vec<usize>> = HashMap::new();
+                    let mut functional: HashMap<u32, Vec<usize>> = HashMap::new();
+                    for (index, frame) in frames.iter().enumerate() {
+                        let id = frame.id().into_bits();
+                        match Self::route(&connections, id, frame.data()) {
+                            Some(key) => routed.entry(key).or_default().push(index),
+                            None => functional.entry(id).or_default().push(index),
+                        }
+                    }
+                    if !functional.is_empty() {
+                        connections.iter()
+                            .filter(|(_, c)| c.iso_tp.role().is_ok_and(|role| role == IsoTpRole::Server))
+                            .for_each(|(key, c)| if let Some(requests) = functional.get(&c.address.fid) {
+                                let indexes = routed.entry(*key).or_default();
+                                indexes.extend(requests);
+                                indexes.sort_unstable();
+                            });
+                    }
+
+                    routed.into_iter()
+                        .filter_map(|(key, indexes)| connections.get(&key).map(|c| (indexes, c.iso_tp.clone())))
+                        .collect::<Vec<_>>()
+                }
+            },
+            Err(e) => {
+                log::warn!("IsoTpMux - connections error: {}", e);
+                return;
+            },
+        };
+        connections.into_iter()
+            .for_each(|(indexes, c)| if indexes.is_empty() {
+                c.on_frame_received(channel.clone(), &[]);
+            }
+            else {
+                indexes.chunk_by(|a, b| a + 1 == *b)
+                    .for_each(|run| c.on_frame_received(channel.clone(), &frames[run[0]..=run[run.len() - 1]]));
+            });
+    }
+}
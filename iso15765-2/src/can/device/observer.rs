@@ -0,0 +1,177 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use crate::can::address::Address;
+use crate::core::{FlowControlContext, Timer};
+use crate::error::Error;
+use crate::frame::FrameType;
+
+/// The frame transmitted or received by [`crate::CanIsoTp`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRecord {
+    pub frame_type: FrameType,
+    /// The sequence of consecutive frame.
+    pub sequence: Option<u8>,
+    /// The data length of CAN frame.
+    pub length: usize,
+    pub timestamp: Instant,
+}
+
+impl FrameRecord {
+    /// Build the record from the frame data with address information.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the N_PCI is invalid.
+    pub(crate) fn new(data: &[u8], offset: usize, timestamp: Instant) -> Option<Self> {
+        let pci = *data.get(offset)?;
+        let frame_type = FrameType::try_from(pci).ok()?;
+        Some(Self {
+            frame_type,
+            sequence: (frame_type == FrameType::Consecutive).then_some(pci & 0x0F),
+            length: data.len(),
+            timestamp,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferDirection {
+    Transmit,
+    Receive,
+}
+
+/// The PDU transferred completely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferRecord {
+    pub direction: TransferDirection,
+    /// The length of PDU.
+    pub bytes: usize,
+    /// The time from the single frame or first frame to the confirmation or reception of the last frame.
+    pub duration: Duration,
+}
+
+/// The observer of [`crate::CanIsoTp`], all callbacks are called in the thread of adapter or writer,
+/// so they should return quickly.
+///
+/// Set by [`crate::CanIsoTp::set_observer`], see [`IsoTpMetrics`] for the default implementation.
+#[allow(unused_variables)]
+pub trait IsoTpObserver: Send + Sync {
+    /// A frame is transmitted(confirmed).
+    fn on_frame_transmitted(&self, address: &Address, frame: &FrameRecord) {}
+    fn on_frame_received(&self, address: &Address, frame: &FrameRecord) {}
+    fn on_flow_ctrl_received(&self, address: &Address, ctx: &FlowControlContext) {}
+    /// The transmission or reception is aborted by the timer.
+    fn on_timeout(&self, address: &Address, timer: Timer) {}
+    /// An invalid frame is received or the transfer is aborted by other errors.
+    fn on_error(&self, address: &Address, error: &Error) {}
+    fn on_transfer_completed(&self, address: &Address, transfer: &TransferRecord) {}
+}
+
+/// The counters of one address aggregated by [`IsoTpMetrics`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AddressMetrics {
+    pub frames_transmitted: u64,
+    pub frames_received: u64,
+    pub flow_ctrl_received: u64,
+    pub pdus_transmitted: u64,
+    pub pdus_received: u64,
+    pub bytes_transmitted: u64,
+    pub bytes_received: u64,
+    /// The sum of duration of the transfers completed.
+    pub transfer_time: Duration,
+    pub timeouts: u64,
+    pub errors: u64,
+}
+
+impl AddressMetrics {
+    /// The bytes per second of the transfers completed.
+    pub fn throughput(&self) -> Option<f64> {
+        let seconds = self.transfer_time.as_secs_f64();
+        if seconds > 0. {
+            Some((self.bytes_transmitted + self.bytes_received) as f64 / seconds)
+        }
+        else {
+            None
+        }
+    }
+}
+
+/// The [`IsoTpObserver`] that aggregates the throughput and error counters per [`Address`].
+///
+/// It's cheap to clone, the clones share the counters, so one instance can observe several connections.
+#[derive(Debug, Default, Clone)]
+pub struct IsoTpMetrics {
+    metrics: Arc<Mutex<HashMap<Address, AddressMetrics>>>,
+}
+
+impl IsoTpMetrics {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[inline]
+    pub fn get(&self, address: &Address) -> Option<AddressMetrics> {
+        self.metrics.lock()
+            .ok()?
+            .get(address)
+            .copied()
+    }
+
+    #[inline]
+    pub fn all(&self) -> HashMap<Address, AddressMetrics> {
+        self.metrics.lock()
+            .map(|m| m.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn reset(&self) {
+        if let Ok(mut metrics) = self.metrics.lock() {
+            metrics.clear();
+        }
+    }
+
+    fn update(&self, address: &Address, callback: impl FnOnce(&mut AddressMetrics)) {
+        match self.metrics.lock() {
+            Ok(mut metrics) => callback(metrics.entry(*address).or_default()),
+            Err(e) => log::warn!("IsoTpMetrics::update: {}", e),
+        }
+    }
+}
+
+impl IsoTpObserver for IsoTpMetrics {
+    fn on_frame_transmitted(&self, address: &Address, _: &FrameRecord) {
+        self.update(address, |m| m.frames_transmitted += 1);
+    }
+
+    fn on_frame_received(&self, address: &Address, _: &FrameRecord) {
+        self.update(address, |m| m.frames_received += 1);
+    }
+
+    fn on_flow_ctrl_received(&self, address: &Address, _: &FlowControlContext) {
+        self.update(address, |m| m.flow_ctrl_received += 1);
+    }
+
+    fn on_timeout(&self, address: &Address, _: Timer) {
+        self.update(address, |m| m.timeouts += 1);
+    }
+
+    fn on_error(&self, address: &Address, _: &Error) {
+        self.update(address, |m| m.errors += 1);
+    }
+
+    fn on_transfer_completed(&self, address: &Address, transfer: &TransferRecord) {
+        self.update(address, |m| {
+            match transfer.direction {
+                TransferDirection::Transmit => {
+                    m.pdus_transmitted += 1;
+                    m.bytes_transmitted += transfer.bytes as u64;
+                },
+                TransferDirection::Receive => {
+                    m.pdus_received += 1;
+                    m.bytes_received += transfer.bytes as u64;
+                },
+            }
+            m.transfer_time += transfer.duration;
+        });
+    }
+}
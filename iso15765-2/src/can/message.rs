@@ -0,0 +1,212 @@
+use std::fmt::{Display, Formatter};
+use rs_can::{CanDirect, CanFrame, CanId};
+
+use crate::standard::{CAN_DATA_LENGTHS, MAX_FD_FRAME_SIZE, MAX_FRAME_SIZE};
+
+/// The clock that stamped the received frame.
+///
+/// * `Hardware`: the hardware timestamp of the interface.
+/// * `Kernel`: the software timestamp of kernel when the frame is received.
+/// * `System`: the system time when the frame is read by userspace, or the frame is not received.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum TimestampSource {
+    Hardware,
+    Kernel,
+    #[default]
+    System,
+}
+
+/// The CAN frame of the devices in this crate, see [`crate::SocketCan`] and [`crate::VirtualCanBus`].
+#[derive(Debug, Default, Clone)]
+pub struct CanMessage {
+    /// The time in microseconds.
+    pub(crate) timestamp: u64,
+    pub(crate) timestamp_source: TimestampSource,
+    pub(crate) id: u32,
+    pub(crate) extended: bool,
+    pub(crate) direct: CanDirect,
+    pub(crate) can_fd: bool,
+    pub(crate) remote: bool,
+    pub(crate) error_frame: bool,
+    pub(crate) bitrate_switch: bool,
+    pub(crate) esi: bool,
+    pub(crate) channel: String,
+    pub(crate) data: Vec<u8>,
+}
+
+impl Display for CanMessage {
+    /// The candump format: `<channel> <id>#<data>` or `<id>##<flags><data>` of CAN FD.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let id = if self.extended { format!("{:08X}", self.id) } else { format!("{:03X}", self.id) };
+        if self.can_fd {
+            let flags = (self.bitrate_switch as u8) | ((self.esi as u8) << 1);
+            write!(f, "{} {}##{:X}{}", self.channel, id, flags, hex::encode_upper(&self.data))
+        }
+        else if self.remote {
+            write!(f, "{} {}#R", self.channel, id)
+        }
+        else {
+            write!(f, "{} {}#{}", self.channel, id, hex::encode_upper(&self.data))
+        }
+    }
+}
+
+impl CanMessage {
+    #[inline]
+    pub fn timestamp_source(&self) -> TimestampSource {
+        self.timestamp_source
+    }
+}
+
+impl CanFrame for CanMessage {
+    type Channel = String;
+
+    fn new(id: impl Into<CanId>, data: &[u8]) -> Option<Self> {
+        if data.len() > MAX_FD_FRAME_SIZE {
+            return None;
+        }
+
+        let id = id.into();
+        Some(Self {
+            id: id.into_bits(),
+            extended: id.is_extended(),
+            can_fd: data.len() > MAX_FRAME_SIZE,
+            data: data.to_vec(),
+            ..Default::default()
+        })
+    }
+
+    fn new_remote(id: impl Into<CanId>, len: usize) -> Option<Self> {
+        if len > MAX_FRAME_SIZE {
+            return None;
+        }
+
+        let id = id.into();
+        Some(Self {
+            id: id.into_bits(),
+            extended: id.is_extended(),
+            remote: true,
+            data: vec![0; len],
+            ..Default::default()
+        })
+    }
+
+    #[inline]
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    #[inline]
+    fn set_timestamp(&mut self, value: Option<u64>) -> &mut Self {
+        self.timestamp = value.unwrap_or_default();
+        self
+    }
+
+    #[inline]
+    fn id(&self) -> CanId {
+        CanId::from_bits(self.id, Some(self.extended))
+    }
+
+    #[inline]
+    fn is_can_fd(&self) -> bool {
+        self.can_fd
+    }
+
+    #[inline]
+    fn set_can_fd(&mut self, value: bool) -> &mut Self {
+        // the classic frame can't carry more than 8 bytes.
+        self.can_fd = value || self.data.len() > MAX_FRAME_SIZE;
+        self
+    }
+
+    #[inline]
+    fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    #[inline]
+    fn is_extended(&self) -> bool {
+        self.extended
+    }
+
+    #[inline]
+    fn direct(&self) -> CanDirect {
+        self.direct
+    }
+
+    #[inline]
+    fn set_direct(&mut self, direct: CanDirect) -> &mut Self {
+        self.direct = direct;
+        self
+    }
+
+    #[inline]
+    fn is_bitrate_switch(&self) -> bool {
+        self.bitrate_switch
+    }
+
+    #[inline]
+    fn set_bitrate_switch(&mut self, value: bool) -> &mut Self {
+        self.bitrate_switch = value;
+        self
+    }
+
+    #[inline]
+    fn is_error_frame(&self) -> bool {
+        self.error_frame
+    }
+
+    #[inline]
+    fn set_error_frame(&mut self, value: bool) -> &mut Self {
+        self.error_frame = value;
+        self
+    }
+
+    #[inline]
+    fn is_esi(&self) -> bool {
+        self.esi
+    }
+
+    #[inline]
+    fn set_esi(&mut self, value: bool) -> &mut Self {
+        self.esi = value;
+        self
+    }
+
+    #[inline]
+    fn channel(&self) -> Self::Channel {
+        self.channel.clone()
+    }
+
+    #[inline]
+    fn set_channel(&mut self, value: Self::Channel) -> &mut Self {
+        self.channel = value;
+        self
+    }
+
+    #[inline]
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    #[inline]
+    fn dlc(&self) -> Option<usize> {
+        CAN_DATA_LENGTHS.iter()
+            .position(|v| *v >= self.data.len())
+            .map(|i| if i == 0 { self.data.len() } else { i + MAX_FRAME_SIZE })
+    }
+
+    /// The length on bus, CAN FD frame is padded to the next valid data length.
+    #[inline]
+    fn length(&self) -> usize {
+        if self.can_fd && self.data.len() > MAX_FRAME_SIZE {
+            CAN_DATA_LENGTHS.iter()
+                .copied()
+                .find(|v| *v >= self.data.len())
+                .unwrap_or(MAX_FD_FRAME_SIZE)
+        }
+        else {
+            self.data.len()
+        }
+    }
+}
@@ -1,8 +1,29 @@
 pub(crate) mod address;
-pub use address::{Address, AddressFormat, AddressType};
-pub(crate) mod constants;
+pub use address::{
+    Address, AddressFormat, AddressType, IsoTpRole,
+    NORMAL_FIXED_PHYSICAL, NORMAL_FIXED_FUNCTIONAL, MIXED_PHYSICAL, MIXED_FUNCTIONAL,
+};
 pub(crate) mod device;
-pub use device::adapter::CanAdapter;
-pub use device::CanIsoTp;
+pub use device::adapter::{CanAdapter, ListenerFilter, EXACT_MASK};
+#[cfg(feature = "async")]
+pub use device::adapter::AsyncCanAdapter;
+pub use device::{CanIsoTp, ConnectionKey, FrameSender, IsoTpMux, IsoTpObserver, IsoTpTransport};
+pub use device::observer::{AddressMetrics, FrameRecord, IsoTpMetrics, TransferDirection, TransferRecord};
+#[cfg(feature = "async")]
+pub use device::IsoTpStream;
 pub use device::context::P2;
-pub(crate) mod standard;
+mod message;
+pub use message::{CanMessage, TimestampSource};
+mod vbus;
+pub use vbus::{DEFAULT_HISTORY_CAPACITY, VirtualBusConfig, VirtualCanBus, VirtualCanDevice};
+mod obd;
+pub use obd::{
+    Obd15765_4, ObdBitrate, ObdDetection, ObdIdLength,
+    OBD_FUNCTIONAL_ADDRESS, OBD_FUNCTIONAL_ID, OBD_FUNCTIONAL_ID_EXT, OBD_PHYSICAL_ID, OBD_RESPONSE_ID, OBD_TESTER_ADDRESS,
+};
+mod replay;
+pub use replay::{AscReader, CandumpReader, Reassembler, ReplayEvent, ReplayPdu, ReplayViolation};
+#[cfg(all(feature = "socketcan", target_os = "linux"))]
+mod socketcan;
+#[cfg(all(feature = "socketcan", target_os = "linux"))]
+pub use socketcan::{KernelIsoTp, SocketCan, SocketCanFrame};
@@ -0,0 +1,262 @@
+use std::time::{Duration, Instant};
+use rs_can::{CanDevice, CanFrame, CanId, MAX_FRAME_SIZE};
+
+use crate::can::address::{Address, NORMAL_FIXED_PHYSICAL};
+use crate::can::device::CanIsoTp;
+use crate::constants::{BS_ISO15765_4, P2_ISO15765_4, ST_MIN_ISO15765_4, TIMEOUT_AR_ISO15765_4, TIMEOUT_AS_ISO15765_4, TIMEOUT_BR_ISO15765_4, TIMEOUT_BS_ISO15765_4, TIMEOUT_CR_ISO15765_4, TIMEOUT_CS_ISO15765_4};
+use crate::core::{FlowControlConfig, TimingConfig, Version};
+use crate::error::Error;
+use crate::frame::Frame;
+use crate::standard::DEFAULT_PADDING;
+
+/// The 11bit functional request CAN-ID of OBD.
+pub const OBD_FUNCTIONAL_ID: u32 = 0x7DF;
+/// The 11bit physical request CAN-ID of the first ECU, the ECU `n`(0-7) uses `0x7E0 + n`.
+pub const OBD_PHYSICAL_ID: u32 = 0x7E0;
+/// The 11bit response CAN-ID of the first ECU, the ECU `n`(0-7) uses `0x7E8 + n`.
+pub const OBD_RESPONSE_ID: u32 = 0x7E8;
+/// The 29bit functional request CAN-ID of OBD.
+pub const OBD_FUNCTIONAL_ID_EXT: u32 = 0x18DB_33F1;
+/// N_SA of the external test equipment.
+pub const OBD_TESTER_ADDRESS: u8 = 0xF1;
+/// N_TA of the functional request.
+pub const OBD_FUNCTIONAL_ADDRESS: u8 = 0x33;
+/// The request used to detect the ECUs, service 0x01 PID 0x00(supported PIDs).
+const OBD_DETECT_REQUEST: [u8; 2] = [0x01, 0x00];
+
+/// The bit rate of OBD on CAN, 500 kbit/s is tried before 250 kbit/s.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObdBitrate {
+    #[default]
+    Kbps500,
+    Kbps250,
+}
+
+impl ObdBitrate {
+    #[inline]
+    pub fn bitrate(&self) -> u32 {
+        match self {
+            Self::Kbps500 => 500_000,
+            Self::Kbps250 => 250_000,
+        }
+    }
+}
+
+/// The CAN-ID length of OBD on CAN.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObdIdLength {
+    /// 11bit CAN-ID, the normal addressing.
+    #[default]
+    Standard,
+    /// 29bit CAN-ID, the normal fixed addressing.
+    Extended,
+}
+
+/// ISO 15765-4(OBD on CAN) profile.
+///
+/// * `bitrate`: the bit rate of the device.
+/// * `id_length`: 11bit or 29bit CAN-ID.
+/// * `padding`: the frames are always 8 bytes, the unused bytes are filled with this value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Obd15765_4 {
+    pub bitrate: ObdBitrate,
+    pub id_length: ObdIdLength,
+    pub padding: u8,
+}
+
+impl Default for Obd15765_4 {
+    fn default() -> Self {
+        Self {
+            bitrate: Default::default(),
+            id_length: Default::default(),
+            padding: DEFAULT_PADDING,
+        }
+    }
+}
+
+/// The result of [`Obd15765_4::detect`].
+///
+/// * `profile`: the profile the vehicle answers on.
+/// * `addresses`: the addresses of the ECUs answered, ordered by the response CAN-ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObdDetection {
+    pub profile: Obd15765_4,
+    pub addresses: Vec<Address>,
+}
+
+impl Obd15765_4 {
+    /// The timing parameters of ISO 15765-4.
+    #[inline]
+    pub fn timing() -> TimingConfig {
+        TimingConfig {
+            n_as: TIMEOUT_AS_ISO15765_4,
+            n_ar: TIMEOUT_AR_ISO15765_4,
+            n_bs: TIMEOUT_BS_ISO15765_4,
+            n_br: TIMEOUT_BR_ISO15765_4,
+            n_cs: TIMEOUT_CS_ISO15765_4,
+            n_cr: TIMEOUT_CR_ISO15765_4,
+        }
+    }
+
+    /// The flow control of the external test equipment, the `Wait` flow control is not allowed.
+    #[inline]
+    pub fn flow_ctrl() -> FlowControlConfig {
+        FlowControlConfig {
+            block_size: BS_ISO15765_4,
+            st_min: ST_MIN_ISO15765_4,
+            wait_count: 0,
+            wft_max: 0,
+        }
+    }
+
+    /// The functional request CAN-ID.
+    #[inline]
+    pub fn functional_id(&self) -> u32 {
+        match self.id_length {
+            ObdIdLength::Standard => OBD_FUNCTIONAL_ID,
+            ObdIdLength::Extended => OBD_FUNCTIONAL_ID_EXT,
+        }
+    }
+
+    /// The address of the ECU.
+    ///
+    /// * `ecu`: the index(0-7) of ECU for 11bit CAN-ID, or N_SA of ECU for 29bit CAN-ID.
+    pub fn address(&self, ecu: u8) -> Address {
+        match self.id_length {
+            ObdIdLength::Standard => {
+                let index = (ecu & 0x07) as u32;
+                Address::normal(OBD_PHYSICAL_ID + index, OBD_RESPONSE_ID + index, OBD_FUNCTIONAL_ID)
+            },
+            ObdIdLength::Extended => Address::normal_fixed(OBD_TESTER_ADDRESS, ecu, OBD_FUNCTIONAL_ADDRESS),
+        }
+    }
+
+    /// Apply the ISO 15765-4 parameters: timing, flow control, classic CAN frames and the padding.
+    ///
+    /// P2CAN and P2*CAN are not applied because the response pending(NRC 0x78) is not known by ISO-TP,
+    /// the caller waits [`P2_ISO15765_4`] ms for the response(e.g. the `timeout` of [`CanIsoTp::read`])
+    /// and [`crate::P2_STAR_ISO15765_4`] ms after each response pending.
+    pub fn apply<C: Clone, F: CanFrame<Channel = C>>(&self, iso_tp: &CanIsoTp<C, F>) -> Result<(), Error> {
+        iso_tp.set_timing(Self::timing());
+        iso_tp.set_flow_ctrl(Self::flow_ctrl())?;
+        iso_tp.set_version(Version::Std2004);
+        iso_tp.set_tx_dl(MAX_FRAME_SIZE)?;
+        iso_tp.set_padding(Some(self.padding));
+
+        Ok(())
+    }
+
+    /// Detect the CAN-ID length the vehicle answers on, the bit rate of device must be `self.bitrate`.
+    ///
+    /// The functional request of supported PIDs is sent with 11bit CAN-ID and then 29bit CAN-ID,
+    /// the responses received in P2CAN are collected.
+    ///
+    /// # Returns
+    ///
+    /// `None` if there is no response.
+    pub fn detect<D, C, F>(&self, device: &D, channel: C) -> Result<Option<ObdDetection>, Error>
+    where
+        D: CanDevice<Channel = C, Frame = F>,
+        C: Clone,
+        F: CanFrame<Channel = C>,
+    {
+        for id_length in [ObdIdLength::Standard, ObdIdLength::Extended] {
+            let profile = Self { id_length, ..*self };
+            let addresses = profile.probe(device, channel.clone())?;
+            if !addresses.is_empty() {
+                log::debug!("ISO-TP(OBD) - detected {:?}: {:?}", profile, addresses);
+                return Ok(Some(ObdDetection { profile, addresses }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Detect the bit rate and the CAN-ID length the vehicle answers on, see [`Obd15765_4::detect`].
+    ///
+    /// * `open`: open the device with the bit rate, the device is shutdown if there is no response.
+    ///
+    /// # Returns
+    ///
+    /// The device opened with the bit rate detected, `None` if there is no response.
+    pub fn detect_bitrate<D, C, F>(
+        &self,
+        channel: C,
+        mut open: impl FnMut(ObdBitrate) -> Result<D, Error>,
+    ) -> Result<Option<(D, ObdDetection)>, Error>
+    where
+        D: CanDevice<Channel = C, Frame = F>,
+        C: Clone,
+        F: CanFrame<Channel = C>,
+    {
+        for bitrate in [ObdBitrate::Kbps500, ObdBitrate::Kbps250] {
+            let mut device = open(bitrate)?;
+            let profile = Self { bitrate, ..*self };
+            match profile.detect(&device, channel.clone()) {
+                Ok(Some(detection)) => return Ok(Some((device, detection))),
+                Ok(None) => device.shutdown(),
+                Err(e) => {
+                    device.shutdown();
+                    return Err(e);
+                },
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Send the functional request and collect the addresses of ECUs answered.
+    fn probe<D, C, F>(&self, device: &D, channel: C) -> Result<Vec<Address>, Error>
+    where
+        D: CanDevice<Channel = C, Frame = F>,
+        C: Clone,
+        F: CanFrame<Channel = C>,
+    {
+        let extended = self.id_length == ObdIdLength::Extended;
+        let data = Frame::SingleFrame { data: OBD_DETECT_REQUEST.to_vec() }.encode(Some(self.padding));
+        let mut frame = F::new(CanId::from_bits(self.functional_id(), Some(extended)), &data)
+            .ok_or(Error::InvalidPdu(data))?;
+        frame.set_channel(channel.clone());
+        device.transmit(frame, Some(P2_ISO15765_4 as u32))
+            .map_err(|e| {
+                log::warn!("ISO-TP(OBD) - transmit failed: {:?}", e);
+                Error::DeviceError
+            })?;
+
+        let deadline = Instant::now() + Duration::from_millis(P2_ISO15765_4 as u64);
+        let mut addresses = Vec::new();
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            let timeout = (deadline - now).as_millis().max(1) as u32;
+            let frames = device.receive(channel.clone(), Some(timeout))
+                .map_err(|e| {
+                    log::warn!("ISO-TP(OBD) - receive failed: {:?}", e);
+                    Error::DeviceError
+                })?;
+            frames.iter()
+                .filter(|f| !f.is_error_frame() && f.is_extended() == extended)
+                .filter_map(|f| self.response_address(f.id().into_bits()))
+                .for_each(|address| if !addresses.contains(&address) {
+                    addresses.push(address);
+                });
+        }
+        addresses.sort_by_key(|a: &Address| a.rx_id);
+
+        Ok(addresses)
+    }
+
+    /// The address of ECU which responds by the CAN-ID.
+    fn response_address(&self, can_id: u32) -> Option<Address> {
+        match self.id_length {
+            ObdIdLength::Standard => (OBD_RESPONSE_ID..OBD_RESPONSE_ID + 8)
+                .contains(&can_id)
+                .then(|| self.address((can_id - OBD_RESPONSE_ID) as u8)),
+            ObdIdLength::Extended => (can_id & 0xFFFF_FF00 == NORMAL_FIXED_PHYSICAL | (OBD_TESTER_ADDRESS as u32) << 8)
+                .then(|| self.address(can_id as u8)),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::io::{BufRead, Lines};
+use rs_can::{CanDirect, CanFrame, CanId};
+
+use crate::can::message::CanMessage;
+use crate::error::Error;
+use super::candump::parse_seconds;
+
+/// The reader of Vector ASC traces, each frame line is parsed to a [`CanMessage`].
+///
+/// * `0.100000 1 7E0 Tx d 8 03 22 F1 90 00 00 00 00` - the classic frame, `x` after the identifier
+///   means extended and `r` instead of `d` means remote.
+/// * `0.100000 CANFD 1 Rx 7E8 1 0 f 64 <bytes>` - the CAN FD frame, the symbolic name after
+///   the identifier is optional.
+///
+/// The identifiers are hexadecimal unless the header `base dec` is present.
+/// The other lines(header, error frames, events and comments) are skipped.
+///
+/// The binary BLF format is not supported, convert it to ASC first.
+pub struct AscReader<R> {
+    lines: Lines<R>,
+    line: usize,
+    radix: u32,
+}
+
+impl<R: BufRead> AscReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { lines: reader.lines(), line: Default::default(), radix: 16 }
+    }
+
+    /// Parse one line of trace.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the line is not a frame.
+    fn parse_line(&mut self, line: &str) -> Option<Result<CanMessage, Error>> {
+        let tokens: Vec<_> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["base", base, ..] => {
+                self.radix = if base.eq_ignore_ascii_case("dec") { 10 } else { 16 };
+                None
+            },
+            [time, "CANFD", channel, direct, rest @ ..] => {
+                let timestamp = parse_seconds(time)?;
+                Some(self.parse_fd(rest)
+                    .map(|msg| Self::finish(msg, timestamp, channel, direct))
+                    .ok_or_else(|| self.error("invalid CAN FD frame")))
+            },
+            [time, channel, id, direct, kind, rest @ ..]
+            if matches!(*direct, "Rx" | "Tx") && matches!(*kind, "d" | "r") => {
+                let timestamp = parse_seconds(time)?;
+                Some(self.parse_classic(id, *kind == "r", rest)
+                    .map(|msg| Self::finish(msg, timestamp, channel, direct))
+                    .ok_or_else(|| self.error("invalid CAN frame")))
+            },
+            _ => None,
+        }
+    }
+
+    /// `<id> r [<dlc>]` or `<id> d <dlc> <bytes...>`.
+    fn parse_classic(&self, id: &str, remote: bool, rest: &[&str]) -> Option<CanMessage> {
+        let id = self.parse_id(id)?;
+        let dlc: usize = match rest.first() {
+            Some(v) => usize::from_str_radix(v, 16).ok()?,
+            None if remote => 0,
+            None => return None,
+        };
+        if remote {
+            return CanMessage::new_remote(id, dlc);
+        }
+
+        let data = Self::parse_bytes(rest.get(1..)?, dlc)?;
+        CanMessage::new(id, &data)
+    }
+
+    /// `<id> [<name>] <brs> <esi> <dlc> <len> <bytes...>`.
+    fn parse_fd(&self, rest: &[&str]) -> Option<CanMessage> {
+        let (id, rest) = rest.split_first()?;
+        let id = self.parse_id(id)?;
+        // skip the symbolic name, `brs` is always 0 or 1.
+        let rest = match rest.first() {
+            Some(&"0") | Some(&"1") => rest,
+            _ => rest.get(1..)?,
+        };
+        let [brs, esi, _dlc, len, bytes @ ..] = rest else { return None };
+        let len: usize = len.parse().ok()?;
+        let data = Self::parse_bytes(bytes, len)?;
+
+        let mut msg = CanMessage::new(id, &data)?;
+        msg.set_can_fd(true)
+            .set_bitrate_switch(*brs == "1")
+            .set_esi(*esi == "1");
+        Some(msg)
+    }
+
+    fn parse_id(&self, id: &str) -> Option<CanId> {
+        let (id, extended) = match id.strip_suffix('x') {
+            Some(v) => (v, true),
+            None => (id, false),
+        };
+        let bits = u32::from_str_radix(id, self.radix).ok()?;
+        Some(CanId::from_bits(bits, Some(extended)))
+    }
+
+    fn parse_bytes(bytes: &[&str], len: usize) -> Option<Vec<u8>> {
+        if bytes.len() < len {
+            return None;
+        }
+
+        bytes.iter()
+            .take(len)
+            .map(|b| u8::from_str_radix(b, 16).ok())
+            .collect()
+    }
+
+    fn finish(mut msg: CanMessage, timestamp: u64, channel: &str, direct: &str) -> CanMessage {
+        msg.timestamp = timestamp;
+        msg.channel = channel.into();
+        msg.direct = if direct == "Tx" { CanDirect::Transmit } else { CanDirect::Receive };
+        msg
+    }
+
+    #[inline]
+    fn error(&self, reason: &str) -> Error {
+        Error::InvalidTrace { line: self.line, reason: reason.into() }
+    }
+}
+
+impl<R: BufRead> Iterator for AscReader<R> {
+    type Item = Result<CanMessage, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line += 1;
+            let line = match self.lines.next()? {
+                Ok(v) => v,
+                Err(e) => return Some(Err(self.error(&e.to_string()))),
+            };
+            if let Some(ret) = self.parse_line(&line) {
+                return Some(ret);
+            }
+        }
+    }
+}
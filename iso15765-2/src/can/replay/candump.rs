@@ -0,0 +1,147 @@
+use std::io::{BufRead, Lines};
+use rs_can::{CanFrame, CanId};
+
+use crate::can::message::CanMessage;
+use crate::error::Error;
+
+/// The reader of candump(can-utils) traces, each line is parsed to a [`CanMessage`].
+///
+/// * `(1436509052.249713) vcan0 7E0#0322F190` - the log format(`candump -l`).
+/// * `(1436509052.249713) vcan0 7E0##1<data>` - the log format of CAN FD, the digit after `##` is the flags.
+/// * `(1436509052.249713) vcan0 7E0 [4] 03 22 F1 90` - the default format,
+///   the timestamp(`-t a`) and the direction(`-x`) are optional.
+///
+/// The empty lines are skipped, the timestamp is 0 if it's not present.
+pub struct CandumpReader<R> {
+    lines: Lines<R>,
+    line: usize,
+}
+
+impl<R: BufRead> CandumpReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { lines: reader.lines(), line: Default::default() }
+    }
+
+    /// Parse one line of trace.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the line is empty.
+    pub fn parse_line(line: &str, number: usize) -> Option<Result<CanMessage, Error>> {
+        let error = |reason: &str| Error::InvalidTrace { line: number, reason: reason.into() };
+        let mut tokens = line.split_whitespace().peekable();
+        let first = *tokens.peek()?;
+
+        let timestamp = match first.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+            Some(v) => {
+                tokens.next();
+                match parse_seconds(v) {
+                    Some(v) => v,
+                    None => return Some(Err(error("invalid timestamp"))),
+                }
+            },
+            None => Default::default(),
+        };
+        let Some(channel) = tokens.next() else {
+            return Some(Err(error("channel is missing")));
+        };
+        // the direction columns of `-x`.
+        let mut tokens = tokens.skip_while(|t| matches!(*t, "TX" | "RX" | "-" | "B" | "E" | "BE"));
+        let Some(frame) = tokens.next() else {
+            return Some(Err(error("frame is missing")));
+        };
+
+        let ret = match frame.split_once('#') {
+            Some((id, data)) => Self::parse_log(id, data),
+            None => Self::parse_default(frame, tokens.collect()),
+        };
+        Some(ret.map(|mut msg| {
+            msg.timestamp = timestamp;
+            msg.channel = channel.into();
+            msg
+        })
+        .ok_or_else(|| error("invalid frame")))
+    }
+
+    /// `<id>#<data>`, `<id>#R<dlc>` or `<id>##<flags><data>`.
+    fn parse_log(id: &str, data: &str) -> Option<CanMessage> {
+        let id = parse_id(id)?;
+        if let Some(data) = data.strip_prefix('#') {
+            let flags = u8::from_str_radix(data.get(..1)?, 16).ok()?;
+            let data = hex::decode(data.get(1..)?).ok()?;
+            let mut msg = CanMessage::new(id, &data)?;
+            msg.set_can_fd(true)
+                .set_bitrate_switch(flags & 0x01 != 0)
+                .set_esi(flags & 0x02 != 0);
+            Some(msg)
+        }
+        else if let Some(dlc) = data.strip_prefix('R') {
+            CanMessage::new_remote(id, dlc.parse().unwrap_or_default())
+        }
+        else {
+            CanMessage::new(id, &hex::decode(data).ok()?)
+        }
+    }
+
+    /// `<id> [<len>] <bytes...>` or `<id> [<len>] remote request`.
+    fn parse_default(id: &str, tokens: Vec<&str>) -> Option<CanMessage> {
+        let id = parse_id(id)?;
+        let (len, bytes) = tokens.split_first()?;
+        let len: usize = len.strip_prefix('[')?.strip_suffix(']')?.parse().ok()?;
+        if bytes.first() == Some(&"remote") {
+            return CanMessage::new_remote(id, len);
+        }
+
+        let data = bytes.iter()
+            .take(len)
+            .map(|b| u8::from_str_radix(b, 16).ok())
+            .collect::<Option<Vec<_>>>()?;
+        if data.len() != len {
+            return None;
+        }
+        let mut msg = CanMessage::new(id, &data)?;
+        // the length of CAN FD is printed with 2 digits.
+        if tokens[0].len() == 4 {
+            msg.set_can_fd(true);
+        }
+        Some(msg)
+    }
+}
+
+impl<R: BufRead> Iterator for CandumpReader<R> {
+    type Item = Result<CanMessage, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line += 1;
+            let line = match self.lines.next()? {
+                Ok(v) => v,
+                Err(e) => return Some(Err(Error::InvalidTrace { line: self.line, reason: e.to_string() })),
+            };
+            if let Some(ret) = Self::parse_line(&line, self.line) {
+                return Some(ret);
+            }
+        }
+    }
+}
+
+/// The identifier of 3 digits is standard, otherwise extended.
+fn parse_id(id: &str) -> Option<CanId> {
+    let bits = u32::from_str_radix(id, 16).ok()?;
+    Some(CanId::from_bits(bits, Some(id.len() > 3)))
+}
+
+/// Parse `<seconds>.<fraction>` to microseconds, the digits after microsecond are truncated.
+pub(crate) fn parse_seconds(value: &str) -> Option<u64> {
+    let (secs, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let secs: u64 = secs.parse().ok()?;
+    let micros = fraction.chars()
+        .chain(std::iter::repeat('0'))
+        .take(6)
+        .fold(0, |acc, c| acc * 10 + (c as u64 - '0' as u64));
+
+    Some(secs * 1_000_000 + micros)
+}
@@ -0,0 +1,286 @@
+mod asc;
+pub use asc::AscReader;
+mod candump;
+pub use candump::CandumpReader;
+
+use std::collections::HashMap;
+use rs_can::CanFrame;
+
+use crate::can::address::{Address, AddressType};
+use crate::can::device::observer::TransferDirection;
+use crate::constants::CONSECUTIVE_SEQUENCE_START;
+use crate::core::{FlowControlState, Timer, TimingConfig};
+use crate::error::Error;
+use crate::frame::{Frame, FrameType};
+
+/// The PDU reassembled from the trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayPdu {
+    pub address: Address,
+    /// [`TransferDirection::Transmit`] if the PDU is sent by the node of address(tx_id or fid),
+    /// otherwise [`TransferDirection::Receive`](rx_id).
+    pub direction: TransferDirection,
+    pub addr_type: AddressType,
+    pub data: Vec<u8>,
+    /// The timestamp in microseconds of the single frame or first frame.
+    pub start: u64,
+    /// The timestamp in microseconds of the last frame.
+    pub end: u64,
+}
+
+/// The protocol violation found in the trace, the PDU in progress is discarded.
+#[derive(Debug, Clone)]
+pub struct ReplayViolation {
+    pub address: Address,
+    pub direction: TransferDirection,
+    pub can_id: u32,
+    /// The timestamp in microseconds of the frame that violates the protocol.
+    pub timestamp: u64,
+    pub error: Error,
+}
+
+#[derive(Debug, Clone)]
+pub enum ReplayEvent {
+    Pdu(ReplayPdu),
+    Violation(ReplayViolation),
+}
+
+#[derive(Debug, Clone)]
+struct Partial {
+    length: usize,
+    buffer: Vec<u8>,
+    sequence: u8,
+    /// The data length of the first frame, the consecutive frames must not be longer.
+    dl: usize,
+    start: u64,
+    last: u64,
+    /// The time when N_Cr starts, it's restarted by the flow control frame and each consecutive frame.
+    n_cr: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Stream {
+    address: Address,
+    direction: TransferDirection,
+    addr_type: AddressType,
+    can_id: u32,
+    ext: Option<u8>,
+    partial: Option<Partial>,
+}
+
+/// The offline ISO-TP reassembler of CAN traces, see [`CandumpReader`] and [`AscReader`].
+///
+/// The frames of the addresses are decoded by [`Frame::decode_with_offset`] and reassembled with
+/// the consecutive frame context, the other frames are ignored.
+/// The frames must be fed in order of timestamp.
+#[derive(Debug, Clone)]
+pub struct Reassembler {
+    timing: TimingConfig,
+    streams: Vec<Stream>,
+    /// The indexes of streams keyed by CAN-ID.
+    index: HashMap<u32, Vec<usize>>,
+}
+
+impl Reassembler {
+    pub fn new(addresses: impl IntoIterator<Item = Address>) -> Self {
+        let mut streams = Vec::new();
+        for address in addresses {
+            let mut add = |direction, addr_type, can_id, ext| streams.push(Stream {
+                address,
+                direction,
+                addr_type,
+                can_id,
+                ext,
+                partial: None,
+            });
+            add(TransferDirection::Transmit, AddressType::Physical, address.tx_id, address.tx_ext(AddressType::Physical));
+            add(TransferDirection::Transmit, AddressType::Functional, address.fid, address.tx_ext(AddressType::Functional));
+            add(TransferDirection::Receive, AddressType::Physical, address.rx_id, address.rx_ext());
+        }
+
+        let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+        streams.iter()
+            .enumerate()
+            .for_each(|(i, s)| index.entry(s.can_id).or_default().push(i));
+
+        Self { timing: Default::default(), streams, index }
+    }
+
+    /// Set the timing parameters, N_Cr is used to check the gap of consecutive frames,
+    /// it's measured from the flow control frame or the previous consecutive frame like the receiver does.
+    #[inline]
+    pub fn set_timing(&mut self, timing: TimingConfig) {
+        self.timing = timing;
+    }
+
+    /// Feed a frame of the trace.
+    ///
+    /// # Returns
+    ///
+    /// The PDUs completed and the violations found by the frame.
+    pub fn feed<F: CanFrame>(&mut self, frame: &F) -> Vec<ReplayEvent> {
+        let mut events = Vec::new();
+        let can_id = frame.id().into_bits();
+        let data = frame.data();
+        let Some(index) = self.stream_index(can_id, data) else { return events };
+
+        let timestamp = frame.timestamp();
+        let n_cr = self.timing.n_cr as u64 * 1_000;
+        let timing = self.timing;
+        let stream = &mut self.streams[index];
+        if let Some(partial) = &stream.partial {
+            if timestamp.saturating_sub(partial.n_cr) > n_cr {
+                stream.violation(&mut events, timestamp, timing.timeout(Timer::NCr));
+            }
+        }
+
+        let offset = stream.address.format.ext_len();
+        match Frame::decode_with_offset(data, offset) {
+            Ok(Frame::SingleFrame { data }) => {
+                stream.violation(&mut events, timestamp, Error::MixFramesError);
+                events.push(ReplayEvent::Pdu(ReplayPdu {
+                    address: stream.address,
+                    direction: stream.direction,
+                    addr_type: stream.addr_type,
+                    data,
+                    start: timestamp,
+                    end: timestamp,
+                }));
+            },
+            Ok(Frame::FirstFrame { length, data: payload }) => {
+                stream.violation(&mut events, timestamp, Error::MixFramesError);
+                stream.partial = Some(Partial {
+                    length: length as usize,
+                    buffer: payload,
+                    sequence: CONSECUTIVE_SEQUENCE_START - 1,
+                    dl: data.len(),
+                    start: timestamp,
+                    last: timestamp,
+                    n_cr: timestamp,
+                });
+            },
+            Ok(Frame::ConsecutiveFrame { sequence, data: payload }) => {
+                stream.on_consecutive_frame(&mut events, timestamp, sequence, payload, data.len());
+            },
+            Ok(Frame::FlowControlFrame(ctx)) => {
+                // the flow control frame is sent by the receiver of the opposite stream.
+                let partial = self.opposite(index)
+                    .and_then(|i| self.streams[i].partial.as_mut());
+                let receiving = partial.is_some();
+                if let Some(partial) = partial {
+                    // the receiver starts N_Cr when the flow control frame is sent.
+                    partial.n_cr = timestamp;
+                }
+                let stream = &mut self.streams[index];
+                if !receiving {
+                    stream.error(&mut events, timestamp, Error::UnexpectedFrame(FrameType::FlowControl));
+                }
+                else if ctx.state() == FlowControlState::Overload {
+                    stream.error(&mut events, timestamp, Error::OverloadFlow);
+                }
+            },
+            Err(e) => stream.error(&mut events, timestamp, e),
+        }
+
+        events
+    }
+
+    /// Finish the trace, the PDUs not completed are reported as violations.
+    pub fn finish(&mut self) -> Vec<ReplayEvent> {
+        let mut events = Vec::new();
+        for stream in self.streams.iter_mut() {
+            if let Some(partial) = &stream.partial {
+                let error = Error::InvalidDataLength { actual: partial.buffer.len(), expect: partial.length };
+                let timestamp = partial.last;
+                stream.violation(&mut events, timestamp, error);
+            }
+        }
+
+        events
+    }
+
+    /// Reassemble all frames of the trace.
+    pub fn reassemble<F: CanFrame>(&mut self, frames: impl IntoIterator<Item = F>) -> Vec<ReplayEvent> {
+        let mut events: Vec<_> = frames.into_iter()
+            .flat_map(|f| self.feed(&f))
+            .collect();
+        events.append(&mut self.finish());
+        events
+    }
+
+    fn stream_index(&self, can_id: u32, data: &[u8]) -> Option<usize> {
+        self.index.get(&can_id)?
+            .iter()
+            .copied()
+            .find(|i| match self.streams[*i].ext {
+                Some(ext) => data.first() == Some(&ext),
+                None => true,
+            })
+    }
+
+    /// The stream of the same address in the other direction.
+    fn opposite(&self, index: usize) -> Option<usize> {
+        let stream = &self.streams[index];
+        self.streams.iter()
+            .position(|s| s.address == stream.address
+                && s.direction != stream.direction
+                && s.addr_type == AddressType::Physical)
+    }
+}
+
+impl Stream {
+    fn on_consecutive_frame(&mut self, events: &mut Vec<ReplayEvent>, timestamp: u64, sequence: u8, mut payload: Vec<u8>, dl: usize) {
+        let Some(partial) = self.partial.as_mut() else {
+            self.error(events, timestamp, Error::UnexpectedFrame(FrameType::Consecutive));
+            return;
+        };
+
+        let expect = (partial.sequence + 1) & 0x0F;
+        if sequence != expect {
+            self.violation(events, timestamp, Error::InvalidSequence { expect, actual: sequence });
+            return;
+        }
+        if dl > partial.dl {
+            let error = Error::InvalidDataLength { actual: dl, expect: partial.dl };
+            self.violation(events, timestamp, error);
+            return;
+        }
+
+        partial.sequence = sequence;
+        partial.last = timestamp;
+        partial.n_cr = timestamp;
+        partial.buffer.append(&mut payload);
+        if partial.buffer.len() >= partial.length {
+            let mut data = std::mem::take(&mut partial.buffer);
+            data.truncate(partial.length);
+            let start = partial.start;
+            self.partial = None;
+            events.push(ReplayEvent::Pdu(ReplayPdu {
+                address: self.address,
+                direction: self.direction,
+                addr_type: self.addr_type,
+                data,
+                start,
+                end: timestamp,
+            }));
+        }
+    }
+
+    /// Discard the PDU in progress and report the violation.
+    fn violation(&mut self, events: &mut Vec<ReplayEvent>, timestamp: u64, error: Error) {
+        if self.partial.take().is_some() {
+            self.error(events, timestamp, error);
+        }
+    }
+
+    /// Report the violation without changing the PDU in progress.
+    fn error(&self, events: &mut Vec<ReplayEvent>, timestamp: u64, error: Error) {
+        events.push(ReplayEvent::Violation(ReplayViolation {
+            address: self.address,
+            direction: self.direction,
+            can_id: self.can_id,
+            timestamp,
+            error,
+        }));
+    }
+}
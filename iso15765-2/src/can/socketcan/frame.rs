@@ -0,0 +1,81 @@
+use rs_can::{CanDirect, CanFrame};
+
+use crate::can::message::{CanMessage, TimestampSource};
+use crate::standard::{MAX_FD_FRAME_SIZE, MAX_FRAME_SIZE};
+
+/// The CAN frame transmitted and received by [`super::SocketCan`],
+/// the timestamp is the hardware timestamp if the interface supports, see [`CanMessage::timestamp_source`].
+pub type SocketCanFrame = CanMessage;
+
+impl CanMessage {
+    /// Convert to the raw frame of kernel, the classic frame is the prefix of `canfd_frame`.
+    ///
+    /// # Returns
+    ///
+    /// The raw frame and the size to write(CAN_MTU or CANFD_MTU).
+    pub(crate) fn to_raw(&self) -> (libc::canfd_frame, usize) {
+        let mut can_id = self.id;
+        if self.extended {
+            can_id |= libc::CAN_EFF_FLAG;
+        }
+        if self.remote {
+            can_id |= libc::CAN_RTR_FLAG;
+        }
+        if self.error_frame {
+            can_id |= libc::CAN_ERR_FLAG;
+        }
+
+        // SAFETY: the raw frame is plain old data.
+        let mut raw: libc::canfd_frame = unsafe { std::mem::zeroed() };
+        raw.can_id = can_id;
+        raw.len = self.length() as u8;
+        raw.data[..self.data.len()].copy_from_slice(&self.data);
+        if self.can_fd {
+            if self.bitrate_switch {
+                raw.flags |= libc::CANFD_BRS as u8;
+            }
+            if self.esi {
+                raw.flags |= libc::CANFD_ESI as u8;
+            }
+
+            (raw, size_of::<libc::canfd_frame>())
+        }
+        else {
+            (raw, size_of::<libc::can_frame>())
+        }
+    }
+
+    /// Convert from the raw frame read from kernel.
+    pub(crate) fn from_raw(
+        raw: &libc::canfd_frame,
+        size: usize,
+        channel: String,
+        (timestamp, timestamp_source): (u64, TimestampSource),
+    ) -> Option<Self> {
+        let can_fd = match size {
+            v if v == size_of::<libc::can_frame>() => false,
+            v if v == size_of::<libc::canfd_frame>() => true,
+            _ => return None,
+        };
+        let max_len = if can_fd { MAX_FD_FRAME_SIZE } else { MAX_FRAME_SIZE };
+        let length = (raw.len as usize).min(max_len);
+        let extended = raw.can_id & libc::CAN_EFF_FLAG != 0;
+        let remote = raw.can_id & libc::CAN_RTR_FLAG != 0;
+
+        Some(Self {
+            timestamp,
+            timestamp_source,
+            id: if extended { raw.can_id & libc::CAN_EFF_MASK } else { raw.can_id & libc::CAN_SFF_MASK },
+            extended,
+            direct: CanDirect::Receive,
+            can_fd,
+            remote,
+            error_frame: raw.can_id & libc::CAN_ERR_FLAG != 0,
+            bitrate_switch: can_fd && raw.flags & libc::CANFD_BRS as u8 != 0,
+            esi: can_fd && raw.flags & libc::CANFD_ESI as u8 != 0,
+            channel,
+            data: if remote { vec![] } else { raw.data[..length].to_vec() },
+        })
+    }
+}
+
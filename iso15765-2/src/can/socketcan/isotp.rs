@@ -0,0 +1,304 @@
+use std::{io, os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd}, sync::Arc};
+
+use crate::can::address::{Address, AddressType, IsoTpRole};
+use crate::can::device::IsoTpTransport;
+use crate::core::FlowControlConfig;
+use crate::error::Error;
+use crate::standard::{CAN_DATA_LENGTHS, DEFAULT_PADDING, MAX_FRAME_SIZE};
+
+use super::SocketCan;
+
+/// The max PDU size of kernel ISO-TP socket.
+const MAX_PDU_SIZE: usize = 66_000;
+
+// linux/can/isotp.h
+const SOL_CAN_ISOTP: libc::c_int = 106;
+const CAN_ISOTP_OPTS: libc::c_int = 1;
+const CAN_ISOTP_RECV_FC: libc::c_int = 2;
+const CAN_ISOTP_LL_OPTS: libc::c_int = 5;
+
+const CAN_ISOTP_LISTEN_MODE: u32 = 0x001;
+const CAN_ISOTP_EXTEND_ADDR: u32 = 0x002;
+const CAN_ISOTP_TX_PADDING: u32 = 0x004;
+const CAN_ISOTP_RX_EXT_ADDR: u32 = 0x200;
+const CAN_ISOTP_WAIT_TX_DONE: u32 = 0x400;
+const CAN_ISOTP_SF_BROADCAST: u32 = 0x800;
+
+const CAN_MTU: u8 = 16;
+const CANFD_MTU: u8 = 72;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct IsoTpOptions {
+    flags: u32,
+    frame_txtime: u32,
+    ext_address: u8,
+    txpad_content: u8,
+    rxpad_content: u8,
+    rx_ext_address: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct IsoTpFcOptions {
+    bs: u8,
+    stmin: u8,
+    wftmax: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct IsoTpLlOptions {
+    mtu: u8,
+    tx_dl: u8,
+    tx_flags: u8,
+}
+
+/// The head of `sockaddr_can` with the transport protocol address.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct SockAddrCanTp {
+    can_family: libc::sa_family_t,
+    can_ifindex: libc::c_int,
+    rx_id: u32,
+    tx_id: u32,
+}
+
+/// The ISO-TP of Linux kernel(`can-isotp`), the segmentation, flow control and STmin are handled by kernel.
+///
+/// It has the same write/read API as [`crate::CanIsoTp`], see [`IsoTpTransport`].
+/// The functional request is transmitted by another socket of client, it must be a single frame.
+/// The server receives the functional requests on `fid` by a listen-only socket instead, see [`IsoTpRole`].
+#[derive(Debug, Clone)]
+pub struct KernelIsoTp {
+    channel: String,
+    address: Address,
+    role: IsoTpRole,
+    physical: Arc<OwnedFd>,
+    /// Transmit to `fid` for client, receive from `fid` for server.
+    functional: Arc<OwnedFd>,
+}
+
+impl KernelIsoTp {
+    /// Open the ISO-TP sockets of client with the default flow control and classic CAN.
+    pub fn new(channel: &str, address: Address) -> Result<Self, Error> {
+        Self::with_options(channel, address, IsoTpRole::Client, Default::default(), MAX_FRAME_SIZE)
+    }
+
+    /// Open the ISO-TP sockets.
+    ///
+    /// # Parameters
+    ///
+    /// * `channel` - the interface name.
+    /// * `address` - the ISO-TP address.
+    /// * `role` - the role of node, the server receives the functional requests too.
+    /// * `flow_ctrl` - the flow control sent to remote node, the `wait_count` must be 0(not supported by kernel).
+    /// * `tx_dl` - TX_DL, 8(classic CAN), 12, 16, 20, 24, 32, 48 or 64(CAN FD).
+    pub fn with_options(
+        channel: &str,
+        address: Address,
+        role: IsoTpRole,
+        flow_ctrl: FlowControlConfig,
+        tx_dl: usize,
+    ) -> Result<Self, Error> {
+        flow_ctrl.validate()?;
+        if flow_ctrl.wait_count != 0 {
+            return Err(Error::InvalidParam(format!("`wait_count`({}) is not supported by kernel", flow_ctrl.wait_count)));
+        }
+        if !CAN_DATA_LENGTHS.contains(&tx_dl) {
+            return Err(Error::InvalidParam(format!("`tx_dl`({})", tx_dl)));
+        }
+        let ifindex = SocketCan::ifindex(channel)
+            .map_err(|e| Self::device_error(channel, "interface is not found", e))?;
+
+        let physical = Self::open(ifindex, &address, AddressType::Physical, role, flow_ctrl, tx_dl)
+            .map_err(|e| Self::device_error(channel, "open physical socket failed", e))?;
+        let functional = Self::open(ifindex, &address, AddressType::Functional, role, flow_ctrl, tx_dl)
+            .map_err(|e| Self::device_error(channel, "open functional socket failed", e))?;
+
+        Ok(Self {
+            channel: channel.into(),
+            address,
+            role,
+            physical: Arc::new(physical),
+            functional: Arc::new(functional),
+        })
+    }
+
+    #[inline]
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    #[inline]
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    #[inline]
+    pub fn role(&self) -> IsoTpRole {
+        self.role
+    }
+
+    /// The functional socket of server listens on `fid` only, the flow control is never sent
+    /// because the functional request is a single frame.
+    fn open(
+        ifindex: libc::c_uint,
+        address: &Address,
+        addr_type: AddressType,
+        role: IsoTpRole,
+        flow_ctrl: FlowControlConfig,
+        tx_dl: usize,
+    ) -> io::Result<OwnedFd> {
+        // SAFETY: the returned descriptor is owned by `OwnedFd` at once.
+        let fd = unsafe { libc::socket(libc::PF_CAN, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, libc::CAN_ISOTP) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let raw = fd.as_raw_fd();
+
+        let mut options = IsoTpOptions {
+            flags: CAN_ISOTP_TX_PADDING | CAN_ISOTP_WAIT_TX_DONE,
+            txpad_content: DEFAULT_PADDING,
+            ..Default::default()
+        };
+        let listener = role == IsoTpRole::Server && addr_type == AddressType::Functional;
+        let (rx_id, rx_ext, tx_id, tx_ext) = match listener {
+            true => {
+                options.flags |= CAN_ISOTP_LISTEN_MODE;
+                (address.fid, address.func_rx_ext(), address.tx_id, address.tx_ext(AddressType::Physical))
+            },
+            false => {
+                if addr_type == AddressType::Functional {
+                    options.flags |= CAN_ISOTP_SF_BROADCAST;
+                }
+                (address.rx_id, address.rx_ext(), address.tx_can_id(addr_type), address.tx_ext(addr_type))
+            },
+        };
+        if let Some(ext) = tx_ext {
+            options.flags |= CAN_ISOTP_EXTEND_ADDR;
+            options.ext_address = ext;
+        }
+        if let Some(ext) = rx_ext {
+            options.flags |= CAN_ISOTP_RX_EXT_ADDR;
+            options.rx_ext_address = ext;
+        }
+        SocketCan::set_option(raw, SOL_CAN_ISOTP, CAN_ISOTP_OPTS, &options)?;
+
+        let fc_options = IsoTpFcOptions {
+            bs: flow_ctrl.block_size,
+            stmin: flow_ctrl.st_min,
+            wftmax: flow_ctrl.wft_max,
+        };
+        SocketCan::set_option(raw, SOL_CAN_ISOTP, CAN_ISOTP_RECV_FC, &fc_options)?;
+
+        let ll_options = IsoTpLlOptions {
+            mtu: if tx_dl > MAX_FRAME_SIZE { CANFD_MTU } else { CAN_MTU },
+            tx_dl: tx_dl as u8,
+            tx_flags: Default::default(),
+        };
+        SocketCan::set_option(raw, SOL_CAN_ISOTP, CAN_ISOTP_LL_OPTS, &ll_options)?;
+
+        let addr = SockAddrCanTp {
+            can_family: libc::AF_CAN as libc::sa_family_t,
+            can_ifindex: ifindex as libc::c_int,
+            rx_id: Self::can_id(rx_id),
+            tx_id: Self::can_id(tx_id),
+        };
+        let ret = unsafe {
+            libc::bind(
+                raw,
+                &addr as *const SockAddrCanTp as *const libc::sockaddr,
+                size_of::<SockAddrCanTp>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(fd)
+    }
+
+    /// The CAN-ID with `CAN_EFF_FLAG` of the 29bit identifier.
+    #[inline]
+    fn can_id(id: u32) -> u32 {
+        if id > libc::CAN_SFF_MASK { id | libc::CAN_EFF_FLAG } else { id }
+    }
+
+    fn socket(&self, addr_type: AddressType) -> RawFd {
+        match addr_type {
+            AddressType::Physical => self.physical.as_raw_fd(),
+            AddressType::Functional => self.functional.as_raw_fd(),
+        }
+    }
+
+    #[inline]
+    fn device_error(channel: &str, reason: &str, e: io::Error) -> Error {
+        log::warn!("ISO-TP(kernel) - {}: {}: {}", channel, reason, e);
+        Error::DeviceError
+    }
+
+    /// The sockets receiving PDUs, the functional one of server too.
+    fn rx_sockets(&self) -> Vec<RawFd> {
+        match self.role {
+            IsoTpRole::Client => vec![self.physical.as_raw_fd()],
+            IsoTpRole::Server => vec![self.physical.as_raw_fd(), self.functional.as_raw_fd()],
+        }
+    }
+
+    /// Discard the PDUs received before.
+    fn clear(&self) {
+        let mut buffer = vec![0; MAX_PDU_SIZE];
+        let fds = self.rx_sockets();
+        while let Ok(Some(fd)) = SocketCan::poll_any(&fds, libc::POLLIN, 0) {
+            let ret = unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+            if ret < 0 {
+                break;
+            }
+        }
+    }
+}
+
+impl IsoTpTransport for KernelIsoTp {
+    fn write(&self, addr_type: AddressType, data: Vec<u8>) -> Result<(), Error> {
+        log::trace!("ISO-TP(kernel) - Sending: {}", hex::encode(&data));
+        if self.role == IsoTpRole::Server && addr_type == AddressType::Functional {
+            return Err(Error::InvalidParam("the server can't write functionally".into()));
+        }
+
+        let fd = self.socket(addr_type);
+        // blocked until the PDU is transmitted(CAN_ISOTP_WAIT_TX_DONE).
+        let ret = unsafe { libc::write(fd, data.as_ptr() as *const libc::c_void, data.len()) };
+        if ret < 0 {
+            return Err(Self::device_error(&self.channel, "write failed", io::Error::last_os_error()));
+        }
+
+        Ok(())
+    }
+
+    fn read(&self, timeout: u64) -> Result<Vec<u8>, Error> {
+        let ready = SocketCan::poll_any(&self.rx_sockets(), libc::POLLIN, timeout.min(libc::c_int::MAX as u64) as libc::c_int)
+            .map_err(|e| Self::device_error(&self.channel, "read failed", e))?;
+        let Some(fd) = ready else {
+            return Err(Error::ReadTimeout { value: timeout, unit: "ms" });
+        };
+
+        let mut buffer = vec![0; MAX_PDU_SIZE];
+        let ret = unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+        if ret < 0 {
+            return Err(Self::device_error(&self.channel, "read failed", io::Error::last_os_error()));
+        }
+        buffer.truncate(ret as usize);
+        log::debug!("ISO-TP(kernel) - Received: {}", hex::encode(&buffer));
+
+        Ok(buffer)
+    }
+
+    /// The PDUs received before writing are discarded.
+    fn transceive(&self, addr_type: AddressType, data: Vec<u8>, timeout: u64) -> Result<Vec<u8>, Error> {
+        self.clear();
+        self.write(addr_type, data)?;
+        self.read(timeout)
+    }
+}
@@ -0,0 +1,294 @@
+mod frame;
+pub use frame::SocketCanFrame;
+mod isotp;
+pub use isotp::KernelIsoTp;
+
+use std::{collections::HashMap, ffi::CString, io, os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd}, sync::{Arc, Mutex}, time::{SystemTime, UNIX_EPOCH}};
+use rs_can::{CanDevice, CanError, CanFrame, CanResult};
+
+use crate::can::message::TimestampSource;
+
+/// The size of ancillary data buffer, enough for `SCM_TIMESTAMPING`(3 x timespec).
+const CONTROL_SIZE: usize = 128;
+
+#[derive(Debug)]
+struct Socket {
+    fd: OwnedFd,
+    can_fd: bool,
+}
+
+/// The Linux SocketCAN device, each channel is a raw CAN socket bound to the interface(`can0`, `vcan0`...).
+///
+/// The kernel timestamp is enabled when opening, the hardware timestamp is used if the interface supports,
+/// otherwise the software timestamp of kernel.
+#[derive(Debug, Default, Clone)]
+pub struct SocketCan {
+    sockets: Arc<Mutex<HashMap<String, Arc<Socket>>>>,
+}
+
+impl SocketCan {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Open the interface.
+    ///
+    /// # Parameters
+    ///
+    /// * `channel` - the interface name.
+    /// * `can_fd` - whether CAN FD frames are transmitted and received, the MTU of interface must be 72.
+    pub fn open(&self, channel: &str, can_fd: bool) -> CanResult<(), CanError> {
+        let ifindex = Self::ifindex(channel)
+            .map_err(|e| CanError::OperationError(format!("{}: interface is not found: {}", channel, e)))?;
+
+        // SAFETY: the returned descriptor is owned by `OwnedFd` at once.
+        let fd = unsafe { libc::socket(libc::PF_CAN, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::CAN_RAW) };
+        if fd < 0 {
+            return Err(Self::last_error(channel, "open socket failed"));
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        if can_fd {
+            Self::set_option(fd.as_raw_fd(), libc::SOL_CAN_RAW, libc::CAN_RAW_FD_FRAMES, &(1 as libc::c_int))
+                .map_err(|e| CanError::OperationError(format!("{}: enable CAN FD failed: {}", channel, e)))?;
+        }
+
+        let flags = libc::SOF_TIMESTAMPING_RX_HARDWARE
+            | libc::SOF_TIMESTAMPING_RAW_HARDWARE
+            | libc::SOF_TIMESTAMPING_RX_SOFTWARE
+            | libc::SOF_TIMESTAMPING_SOFTWARE;
+        if let Err(e) = Self::set_option(fd.as_raw_fd(), libc::SOL_SOCKET, libc::SO_TIMESTAMPING, &(flags as libc::c_int)) {
+            log::warn!("SocketCAN - {}: enable timestamp failed: {}", channel, e);
+        }
+
+        // SAFETY: the address is plain old data.
+        let mut addr: libc::sockaddr_can = unsafe { std::mem::zeroed() };
+        addr.can_family = libc::AF_CAN as libc::sa_family_t;
+        addr.can_ifindex = ifindex as libc::c_int;
+        let ret = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_can as *const libc::sockaddr,
+                size_of::<libc::sockaddr_can>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(Self::last_error(channel, "bind failed"));
+        }
+
+        match self.sockets.lock() {
+            Ok(mut sockets) => {
+                sockets.insert(channel.into(), Arc::new(Socket { fd, can_fd }));
+                Ok(())
+            },
+            Err(e) => Err(CanError::OperationError(e.to_string())),
+        }
+    }
+
+    /// Close the interface.
+    pub fn close(&self, channel: &str) {
+        if let Ok(mut sockets) = self.sockets.lock() {
+            sockets.remove(channel);
+        }
+    }
+
+    fn socket(&self, channel: &str) -> CanResult<Arc<Socket>, CanError> {
+        self.sockets.lock()
+            .map_err(|e| CanError::OperationError(e.to_string()))?
+            .get(channel)
+            .cloned()
+            .ok_or_else(|| CanError::OperationError(format!("{} is not opened", channel)))
+    }
+
+    fn ifindex(channel: &str) -> io::Result<libc::c_uint> {
+        let name = CString::new(channel)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: the name is a valid C string.
+        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 { Err(io::Error::last_os_error()) } else { Ok(ifindex) }
+    }
+
+    fn set_option<T>(fd: RawFd, level: libc::c_int, name: libc::c_int, value: &T) -> io::Result<()> {
+        let ret = unsafe {
+            libc::setsockopt(
+                fd,
+                level,
+                name,
+                value as *const T as *const libc::c_void,
+                size_of::<T>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+    }
+
+    /// Wait until the socket is ready.
+    ///
+    /// # Returns
+    ///
+    /// `false` if timeout.
+    #[inline]
+    fn poll(fd: RawFd, events: libc::c_short, timeout: libc::c_int) -> io::Result<bool> {
+        Self::poll_any(&[fd], events, timeout)
+            .map(|v| v.is_some())
+    }
+
+    /// Wait until any of the sockets is ready.
+    ///
+    /// # Returns
+    ///
+    /// The first socket ready, `None` if timeout.
+    fn poll_any(fds: &[RawFd], events: libc::c_short, timeout: libc::c_int) -> io::Result<Option<RawFd>> {
+        let mut pfds = fds.iter()
+            .map(|fd| libc::pollfd { fd: *fd, events, revents: 0 })
+            .collect::<Vec<_>>();
+        loop {
+            let ret = unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as libc::nfds_t, timeout) };
+            if ret >= 0 {
+                return Ok(pfds.iter().find(|p| p.revents != 0).map(|p| p.fd));
+            }
+
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+
+    /// Read one frame without blocking.
+    ///
+    /// # Returns
+    ///
+    /// `None` if no frame is available.
+    fn read_frame(socket: &Socket, channel: &str) -> io::Result<Option<SocketCanFrame>> {
+        // SAFETY: the buffers are plain old data and live until `recvmsg` returns.
+        let mut raw: libc::canfd_frame = unsafe { std::mem::zeroed() };
+        let mut iov = libc::iovec {
+            iov_base: &mut raw as *mut libc::canfd_frame as *mut libc::c_void,
+            iov_len: size_of::<libc::canfd_frame>(),
+        };
+        // u64 for the alignment of `cmsghdr`.
+        let mut control = [0u64; CONTROL_SIZE / 8];
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = CONTROL_SIZE as _;
+
+        let size = unsafe { libc::recvmsg(socket.fd.as_raw_fd(), &mut msg, libc::MSG_DONTWAIT) };
+        if size < 0 {
+            let e = io::Error::last_os_error();
+            return match e.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(None),
+                _ => Err(e),
+            };
+        }
+
+        let timestamp = Self::timestamp(&msg)
+            .unwrap_or_else(|| {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|v| v.as_micros() as u64)
+                    .unwrap_or_default();
+                (now, TimestampSource::System)
+            });
+        let frame = SocketCanFrame::from_raw(&raw, size as usize, channel.into(), timestamp);
+        if frame.is_none() {
+            log::warn!("SocketCAN - {}: invalid frame size {}", channel, size);
+        }
+
+        Ok(frame)
+    }
+
+    /// Get the timestamp in microseconds from `SCM_TIMESTAMPING`, the hardware timestamp is preferred.
+    fn timestamp(msg: &libc::msghdr) -> Option<(u64, TimestampSource)> {
+        // SAFETY: the control buffer is filled by `recvmsg` and iterated by the macros of kernel.
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_TIMESTAMPING {
+                    let ts = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const [libc::timespec; 3]);
+                    return [(ts[2], TimestampSource::Hardware), (ts[0], TimestampSource::Kernel)].into_iter()
+                        .find(|(t, _)| t.tv_sec != 0 || t.tv_nsec != 0)
+                        .map(|(t, source)| (t.tv_sec as u64 * 1_000_000 + t.tv_nsec as u64 / 1_000, source));
+                }
+                cmsg = libc::CMSG_NXTHDR(msg, cmsg);
+            }
+        }
+
+        None
+    }
+
+    #[inline]
+    fn last_error(channel: &str, reason: &str) -> CanError {
+        CanError::OperationError(format!("{}: {}: {}", channel, reason, io::Error::last_os_error()))
+    }
+}
+
+impl CanDevice for SocketCan {
+    type Channel = String;
+    type Frame = SocketCanFrame;
+
+    #[inline]
+    fn is_closed(&self) -> bool {
+        self.sockets.lock()
+            .map(|s| s.is_empty())
+            .unwrap_or(true)
+    }
+
+    #[inline]
+    fn opened_channels(&self) -> Vec<Self::Channel> {
+        self.sockets.lock()
+            .map(|s| s.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Transmit the frame, the `timeout` in milliseconds is used when the transmit queue is full.
+    fn transmit(&self, msg: Self::Frame, timeout: Option<u32>) -> CanResult<(), CanError> {
+        let channel = msg.channel();
+        let socket = self.socket(&channel)?;
+        if msg.is_can_fd() && !socket.can_fd {
+            return Err(CanError::OperationError(format!("{}: CAN FD is not enabled", channel)));
+        }
+
+        let fd = socket.fd.as_raw_fd();
+        if let Some(timeout) = timeout {
+            match Self::poll(fd, libc::POLLOUT, timeout as libc::c_int) {
+                Ok(true) => {},
+                Ok(false) => return Err(CanError::TimeoutError(format!("{}: transmit timeout", channel))),
+                Err(e) => return Err(CanError::OperationError(format!("{}: {}", channel, e))),
+            }
+        }
+
+        let (raw, size) = msg.to_raw();
+        let ret = unsafe { libc::write(fd, &raw as *const libc::canfd_frame as *const libc::c_void, size) };
+        if ret != size as isize {
+            return Err(Self::last_error(&channel, "transmit failed"));
+        }
+
+        Ok(())
+    }
+
+    /// Receive all frames available, the `timeout` in milliseconds is used to wait for the first frame.
+    fn receive(&self, channel: Self::Channel, timeout: Option<u32>) -> CanResult<Vec<Self::Frame>, CanError> {
+        let socket = self.socket(&channel)?;
+        let ready = Self::poll(socket.fd.as_raw_fd(), libc::POLLIN, timeout.unwrap_or_default() as libc::c_int)
+            .map_err(|e| CanError::OperationError(format!("{}: {}", channel, e)))?;
+        if !ready {
+            return Ok(vec![]);
+        }
+
+        let mut frames = Vec::new();
+        while let Some(frame) = Self::read_frame(&socket, &channel)
+            .map_err(|e| CanError::OperationError(format!("{}: {}", channel, e)))? {
+            frames.push(frame);
+        }
+
+        Ok(frames)
+    }
+
+    fn shutdown(&mut self) {
+        if let Ok(mut sockets) = self.sockets.lock() {
+            sockets.clear();
+        }
+    }
+}
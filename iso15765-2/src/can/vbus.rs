@@ -0,0 +1,285 @@
+use std::{collections::{HashMap, VecDeque}, sync::{Arc, Condvar, Mutex, MutexGuard}, time::{Duration, Instant}};
+use rs_can::{CanDevice, CanDirect, CanError, CanFrame, CanResult};
+
+use crate::can::message::CanMessage;
+
+/// The default number of frames kept in the history of [`VirtualCanBus`].
+pub const DEFAULT_HISTORY_CAPACITY: usize = 4096;
+
+/// The faults injected by [`VirtualCanBus`], the rates are the probabilities in `0.0..=1.0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VirtualBusConfig {
+    /// The time from transmission to reception.
+    pub latency: Duration,
+    /// The probability that a frame is lost, none of nodes receives it.
+    pub drop_rate: f64,
+    /// The probability that a frame is delayed by `reorder_delay`, so the later frames overtake it.
+    pub reorder_rate: f64,
+    pub reorder_delay: Duration,
+    /// The probability that a bit of the frame data is flipped and the corrupted frame is received.
+    ///
+    /// It is the payload corruption that passes the CRC check(e.g. by a faulty gateway),
+    /// not the bit error on a real bus, which is detected by all nodes and the frame is retransmitted.
+    pub bit_error_rate: f64,
+    /// The seed of the pseudo random generator, the same seed gives the same faults.
+    pub seed: u64,
+    /// The maximum number of frames kept in the history, the oldest ones are discarded, 0 disables the history.
+    pub history_capacity: usize,
+}
+
+impl Default for VirtualBusConfig {
+    fn default() -> Self {
+        Self {
+            latency: Default::default(),
+            drop_rate: Default::default(),
+            reorder_rate: Default::default(),
+            reorder_delay: Duration::from_millis(1),
+            bit_error_rate: Default::default(),
+            seed: 0x2545_F491_4F6C_DD1D,
+            history_capacity: DEFAULT_HISTORY_CAPACITY,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Pending {
+    deliver_at: Instant,
+    /// The order of transmission, used when the delivery time is same.
+    sequence: u64,
+    frame: CanMessage,
+}
+
+#[derive(Debug, Default)]
+struct Bus {
+    config: VirtualBusConfig,
+    rng: u64,
+    sequence: u64,
+    /// The frames not received yet, keyed by the node.
+    queues: HashMap<usize, Vec<Pending>>,
+    next_node: usize,
+    /// The latest frames put on bus after the faults are injected, at most `config.history_capacity`.
+    history: VecDeque<CanMessage>,
+}
+
+impl Bus {
+    /// xorshift64*, deterministic and good enough for fault injection.
+    fn random(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        (self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    #[inline]
+    fn happens(&mut self, rate: f64) -> bool {
+        rate > 0. && self.random() < rate
+    }
+}
+
+/// The in-memory CAN bus that hosts several [`VirtualCanDevice`]s on one channel.
+///
+/// The frame transmitted by a device is received by all other devices after the latency,
+/// the faults configured by [`VirtualBusConfig`] are injected on the way.
+#[derive(Debug, Clone)]
+pub struct VirtualCanBus {
+    channel: String,
+    epoch: Instant,
+    bus: Arc<(Mutex<Bus>, Condvar)>,
+}
+
+impl VirtualCanBus {
+    pub fn new(channel: &str) -> Self {
+        Self::with_config(channel, Default::default())
+    }
+
+    pub fn with_config(channel: &str, config: VirtualBusConfig) -> Self {
+        let bus = Bus { config, rng: config.seed.max(1), ..Default::default() };
+        Self {
+            channel: channel.into(),
+            epoch: Instant::now(),
+            bus: Arc::new((Mutex::new(bus), Condvar::new())),
+        }
+    }
+
+    #[inline]
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    /// Update the faults, the pseudo random generator is reset by the seed.
+    ///
+    /// The oldest frames of history are discarded if the `history_capacity` is reduced.
+    pub fn set_config(&self, config: VirtualBusConfig) {
+        if let Ok(mut bus) = self.lock() {
+            bus.config = config;
+            bus.rng = config.seed.max(1);
+            let excess = bus.history.len().saturating_sub(config.history_capacity);
+            bus.history.drain(..excess);
+        }
+    }
+
+    #[inline]
+    pub fn config(&self) -> VirtualBusConfig {
+        self.lock()
+            .map(|bus| bus.config)
+            .unwrap_or_default()
+    }
+
+    /// Attach a new node to the bus.
+    pub fn device(&self) -> VirtualCanDevice {
+        let node = match self.lock() {
+            Ok(mut bus) => {
+                let node = bus.next_node;
+                bus.next_node += 1;
+                bus.queues.insert(node, Default::default());
+                node
+            },
+            Err(e) => {
+                log::warn!("VirtualCanBus::device: {}", e);
+                usize::MAX
+            },
+        };
+
+        VirtualCanDevice { bus: self.clone(), node }
+    }
+
+    /// The latest frames put on bus in order of transmission, the dropped frames are not included,
+    /// see [`VirtualBusConfig::history_capacity`].
+    pub fn history(&self) -> Vec<CanMessage> {
+        self.lock()
+            .map(|bus| bus.history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn clear_history(&self) {
+        if let Ok(mut bus) = self.lock() {
+            bus.history.clear();
+        }
+    }
+
+    fn lock(&self) -> CanResult<MutexGuard<'_, Bus>, CanError> {
+        self.bus.0.lock()
+            .map_err(|e| CanError::OperationError(e.to_string()))
+    }
+
+    fn transmit(&self, node: usize, mut frame: CanMessage) -> CanResult<(), CanError> {
+        let mut bus = self.lock()?;
+        let config = bus.config;
+        if bus.happens(config.drop_rate) {
+            log::debug!("VirtualCanBus - dropped: {}", frame);
+            return Ok(());
+        }
+        if !frame.data.is_empty() && bus.happens(config.bit_error_rate) {
+            let bit = (bus.random() * (frame.data.len() * 8) as f64) as usize;
+            frame.data[bit / 8] ^= 1 << (bit % 8);
+            log::debug!("VirtualCanBus - bit error: {}", frame);
+        }
+        let mut deliver_at = Instant::now() + config.latency;
+        if bus.happens(config.reorder_rate) {
+            deliver_at += config.reorder_delay;
+        }
+
+        frame.timestamp = self.epoch.elapsed().as_micros() as u64;
+        bus.sequence += 1;
+        let sequence = bus.sequence;
+        if config.history_capacity > 0 {
+            if bus.history.len() >= config.history_capacity {
+                bus.history.pop_front();
+            }
+            bus.history.push_back(frame.clone());
+        }
+        bus.queues.iter_mut()
+            .filter(|(n, _)| **n != node)
+            .for_each(|(_, queue)| {
+                let mut frame = frame.clone();
+                frame.direct = CanDirect::Receive;
+                queue.push(Pending { deliver_at, sequence, frame });
+            });
+        self.bus.1.notify_all();
+
+        Ok(())
+    }
+
+    fn receive(&self, node: usize, timeout: Option<u32>) -> CanResult<Vec<CanMessage>, CanError> {
+        let deadline = Instant::now() + Duration::from_millis(timeout.unwrap_or_default() as u64);
+        let mut bus = self.lock()?;
+        loop {
+            let now = Instant::now();
+            let queue = bus.queues.get_mut(&node)
+                .ok_or_else(|| CanError::OperationError(format!("{}: device is closed", self.channel)))?;
+            let (mut ready, pending): (Vec<_>, Vec<_>) = queue.drain(..)
+                .partition(|p| p.deliver_at <= now);
+            *queue = pending;
+            if !ready.is_empty() {
+                ready.sort_by_key(|p| (p.deliver_at, p.sequence));
+                return Ok(ready.into_iter().map(|p| p.frame).collect());
+            }
+            if now >= deadline {
+                return Ok(vec![]);
+            }
+
+            // wake up when a frame is transmitted or the earliest frame is deliverable.
+            let wait = queue.iter()
+                .map(|p| p.deliver_at)
+                .min()
+                .unwrap_or(deadline)
+                .min(deadline)
+                .saturating_duration_since(now);
+            bus = self.bus.1.wait_timeout(bus, wait)
+                .map_err(|e| CanError::OperationError(e.to_string()))?
+                .0;
+        }
+    }
+}
+
+/// A node of [`VirtualCanBus`], it can be used by [`crate::CanAdapter`] as any other device.
+#[derive(Debug, Clone)]
+pub struct VirtualCanDevice {
+    bus: VirtualCanBus,
+    node: usize,
+}
+
+impl CanDevice for VirtualCanDevice {
+    type Channel = String;
+    type Frame = CanMessage;
+
+    #[inline]
+    fn is_closed(&self) -> bool {
+        self.bus.lock()
+            .map(|bus| !bus.queues.contains_key(&self.node))
+            .unwrap_or(true)
+    }
+
+    #[inline]
+    fn opened_channels(&self) -> Vec<Self::Channel> {
+        if self.is_closed() { vec![] } else { vec![self.bus.channel.clone()] }
+    }
+
+    fn transmit(&self, msg: Self::Frame, _: Option<u32>) -> CanResult<(), CanError> {
+        let channel = msg.channel();
+        if channel != self.bus.channel {
+            return Err(CanError::OperationError(format!("{} is not opened", channel)));
+        }
+        if self.is_closed() {
+            return Err(CanError::OperationError(format!("{}: device is closed", channel)));
+        }
+
+        self.bus.transmit(self.node, msg)
+    }
+
+    /// Receive all frames available, the `timeout` in milliseconds is used to wait for the first frame.
+    fn receive(&self, channel: Self::Channel, timeout: Option<u32>) -> CanResult<Vec<Self::Frame>, CanError> {
+        if channel != self.bus.channel {
+            return Err(CanError::OperationError(format!("{} is not opened", channel)));
+        }
+
+        self.bus.receive(self.node, timeout)
+    }
+
+    /// Detach from the bus.
+    fn shutdown(&mut self) {
+        if let Ok(mut bus) = self.bus.lock() {
+            bus.queues.remove(&self.node);
+        }
+    }
+}
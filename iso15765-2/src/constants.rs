@@ -2,6 +2,8 @@ pub const MAX_LENGTH_2004: usize = 0xFFF;
 pub const MAX_LENGTH_2016: usize = 0xFFFF_FFFF;
 pub const DEFAULT_BLOCK_SIZE: u8 = 0x00;
 pub const DEFAULT_ST_MIN: u8 = 0x0a;
+/// Default value for the maximum number of flow control wait frames(N_WFTmax).
+pub const DEFAULT_WFT_MAX: u8 = 0x0a;
 /// start sequence of consecutive.
 pub const CONSECUTIVE_SEQUENCE_START: u8 = 0x01;
 
@@ -22,6 +24,27 @@ pub const TIMEOUT_CR_ISO15765_2: u32 = 1000;
 /// Default value for Timeout Cs in ms
 pub const TIMEOUT_CS_ISO15765_2: u32 = 1000;
 
+/// Default value for Separation time of ISO 15765-4(OBD), the external test equipment must not delay the ECUs.
+pub const ST_MIN_ISO15765_4: u8 = 0;
+/// Default value for BlockSize of ISO 15765-4(OBD)
+pub const BS_ISO15765_4: u8 = 0;
+/// Value for Timeout Ar in ms of ISO 15765-4(OBD)
+pub const TIMEOUT_AR_ISO15765_4: u32 = 25;
+/// Value for Timeout As in ms of ISO 15765-4(OBD)
+pub const TIMEOUT_AS_ISO15765_4: u32 = 25;
+/// Value for Timeout Br in ms of ISO 15765-4(OBD), (N_Br + N_Ar) < 0.9 * N_Bs
+pub const TIMEOUT_BR_ISO15765_4: u32 = 42;
+/// Value for Timeout Bs in ms of ISO 15765-4(OBD)
+pub const TIMEOUT_BS_ISO15765_4: u32 = 75;
+/// Value for Timeout Cr in ms of ISO 15765-4(OBD)
+pub const TIMEOUT_CR_ISO15765_4: u32 = 150;
+/// Value for Timeout Cs in ms of ISO 15765-4(OBD), (N_Cs + N_As) < 0.9 * N_Cr
+pub const TIMEOUT_CS_ISO15765_4: u32 = 110;
+/// P2CAN in ms of ISO 15765-4(OBD), the maximum time of ECUs to respond.
+pub const P2_ISO15765_4: u16 = 50;
+/// P2*CAN in ms of ISO 15765-4(OBD), the maximum time of ECUs to respond after response pending.
+pub const P2_STAR_ISO15765_4: u32 = 5_000;
+
 pub const P2_MAX: u16 = 50;
 pub const P2_STAR_MAX: u16 = 500;
 pub const DEFAULT_P2_START_MS: u64 = 5_000;
@@ -1,7 +1,9 @@
 #![allow(deprecated)]
-use std::fmt::{Display, Formatter};
+use alloc::{format, vec::Vec};
+use ::core::{fmt::{Display, Formatter}, time::Duration};
 use bitflags::bitflags;
 
+use crate::constants::{DEFAULT_BLOCK_SIZE, DEFAULT_ST_MIN, DEFAULT_WFT_MAX, TIMEOUT_AR_ISO15765_2, TIMEOUT_AS_ISO15765_2, TIMEOUT_BR_ISO15765_2, TIMEOUT_BS_ISO15765_2, TIMEOUT_CR_ISO15765_2, TIMEOUT_CS_ISO15765_2};
 use crate::error::Error;
 
 bitflags! {
@@ -14,7 +16,6 @@ bitflags! {
         #[deprecated]
         const WaitFirst = 0b0000_0010;
         const WaitFlowCtrl = 0b0000_0100;
-        #[deprecated]
         const WaitData = 0b0000_1000;
         const WaitBusy = 0b0001_0000;
         #[deprecated]
@@ -38,7 +39,7 @@ pub enum ByteOrder {
 
 impl Display for State {
     #[allow(deprecated)]
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         let mut idle = true;
         let mut first = true;
         if self.contains(State::WaitSingle) {
@@ -93,7 +94,12 @@ pub enum Event {
     Wait,
     FirstFrameReceived,
     DataReceived(Vec<u8>),
+    /// The invalid frame is ignored, the state of transmission and reception is not changed.
     ErrorOccurred(Error),
+    /// The PDU transmitting is aborted, the transmission returns to idle.
+    TransmitAborted(Error),
+    /// The PDU receiving is aborted, the reception returns to idle.
+    ReceiveAborted(Error),
 }
 
 pub trait EventListener {
@@ -132,7 +138,7 @@ impl Into<u8> for FlowControlState {
 }
 
 /// Flow control frame context.
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub struct FlowControlContext {
     state: FlowControlState,
     block_size: u8,
@@ -187,3 +193,170 @@ impl FlowControlContext {
         }
     }
 }
+
+/// Flow control parameters of the receiver.
+///
+/// * `block_size`: the number of consecutive frames between two flow control frames, 0 means no limit.
+/// * `st_min`: the separation time requested to the sender, see [`FlowControlContext`].
+/// * `wait_count`: the number of `Wait` flow control frames sent before `Continues`.
+/// * `wft_max`: N_WFTmax, the maximum number of `Wait` flow control frames in a row,
+///   the sender aborts the transmission when it is exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowControlConfig {
+    pub block_size: u8,
+    pub st_min: u8,
+    pub wait_count: u8,
+    pub wft_max: u8,
+}
+
+impl Default for FlowControlConfig {
+    fn default() -> Self {
+        Self {
+            block_size: DEFAULT_BLOCK_SIZE,
+            st_min: DEFAULT_ST_MIN,
+            wait_count: Default::default(),
+            wft_max: DEFAULT_WFT_MAX,
+        }
+    }
+}
+
+impl FlowControlConfig {
+    /// Check the parameters are valid.
+    pub fn validate(&self) -> Result<(), Error> {
+        FlowControlContext::new(FlowControlState::Continues, self.block_size, self.st_min)?;
+        if self.wait_count > self.wft_max {
+            return Err(Error::InvalidParam(format!("`wait_count`({}) is greater than `wft_max`({})", self.wait_count, self.wft_max)));
+        }
+
+        Ok(())
+    }
+}
+
+/// ISO 15765-2 network layer timer define.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timer {
+    /// Time for transmission of the CAN frame on the sender side.
+    NAs,
+    /// Time for transmission of the CAN frame on the receiver side.
+    NAr,
+    /// Time until reception of the next flow control frame.
+    NBs,
+    /// Time until transmission of the next flow control frame.
+    NBr,
+    /// Time until transmission of the next consecutive frame.
+    NCs,
+    /// Time until reception of the next consecutive frame.
+    NCr,
+}
+
+impl Display for Timer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NAs => write!(f, "N_As"),
+            Self::NAr => write!(f, "N_Ar"),
+            Self::NBs => write!(f, "N_Bs"),
+            Self::NBr => write!(f, "N_Br"),
+            Self::NCs => write!(f, "N_Cs"),
+            Self::NCr => write!(f, "N_Cr"),
+        }
+    }
+}
+
+/// ISO 15765-2 network layer timing parameters, all values are in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimingConfig {
+    pub n_as: u32,
+    pub n_ar: u32,
+    pub n_bs: u32,
+    pub n_br: u32,
+    pub n_cs: u32,
+    pub n_cr: u32,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            n_as: TIMEOUT_AS_ISO15765_2,
+            n_ar: TIMEOUT_AR_ISO15765_2,
+            n_bs: TIMEOUT_BS_ISO15765_2,
+            n_br: TIMEOUT_BR_ISO15765_2,
+            n_cs: TIMEOUT_CS_ISO15765_2,
+            n_cr: TIMEOUT_CR_ISO15765_2,
+        }
+    }
+}
+
+impl TimingConfig {
+    /// Get the timeout value of the timer.
+    #[inline]
+    pub fn value(&self, timer: Timer) -> u32 {
+        match timer {
+            Timer::NAs => self.n_as,
+            Timer::NAr => self.n_ar,
+            Timer::NBs => self.n_bs,
+            Timer::NBr => self.n_br,
+            Timer::NCs => self.n_cs,
+            Timer::NCr => self.n_cr,
+        }
+    }
+
+    /// Build the timeout error of the timer.
+    #[inline]
+    pub fn timeout(&self, timer: Timer) -> Error {
+        Error::Timeout { timer, value: self.value(timer) as u64, unit: "ms" }
+    }
+}
+
+/// The statistics of the consecutive frames transmitted, used to verify the STmin compliance.
+///
+/// The gap is the time between the confirmations of two consecutive frames in one block.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Statistics {
+    /// The number of consecutive frames transmitted.
+    pub frames: u64,
+    /// The number of gaps measured.
+    pub gaps: u64,
+    /// The sum of gaps, see [`Statistics::mean_gap`].
+    pub total_gap: Duration,
+    pub min_gap: Option<Duration>,
+    pub max_gap: Option<Duration>,
+    /// The STmin requested by the last flow control frame.
+    pub st_min: Duration,
+    /// The number of gaps less than the STmin requested.
+    pub violations: u64,
+}
+
+impl Statistics {
+    #[inline]
+    pub fn mean_gap(&self) -> Option<Duration> {
+        match self.gaps {
+            0 => None,
+            n => Some(self.total_gap / n as u32),
+        }
+    }
+
+    pub(crate) fn record(&mut self, gap: Duration, st_min: Duration) {
+        self.gaps += 1;
+        self.total_gap += gap;
+        self.min_gap = Some(self.min_gap.map_or(gap, |v| v.min(gap)));
+        self.max_gap = Some(self.max_gap.map_or(gap, |v| v.max(gap)));
+        self.st_min = st_min;
+        if gap < st_min {
+            self.violations += 1;
+        }
+    }
+}
+
+/// ISO 15765-2 version used to segment the transmitted PDUs.
+///
+/// The received frames are decoded in both forms, the version only decides
+/// whether the escape sequences of 2016 are used when transmitting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Version {
+    /// ISO 15765-2:2004, the PDU length is limited to 4095 bytes.
+    #[default]
+    Std2004,
+    /// ISO 15765-2:2016, the escape sequences are used for the single frame of CAN FD
+    /// and the first frame of PDU larger than 4095 bytes.
+    Std2016,
+}
@@ -1,5 +1,9 @@
+use alloc::{string::String, vec::Vec};
 use thiserror::Error;
 
+use crate::core::Timer;
+use crate::frame::FrameType;
+
 #[derive(Debug, Clone, Error)]
 pub enum Error {
     #[error("ISO-TP - device error")]
@@ -29,9 +33,30 @@ pub enum Error {
     #[error("ISO-TP - mixed frames")]
     MixFramesError,
 
-    #[error("ISO-TP - timeout when time({value}{unit})")]
-    Timeout { value: u64, unit: &'static str },
+    #[error("ISO-TP - {timer} timeout when time({value}{unit})")]
+    Timeout { timer: Timer, value: u64, unit: &'static str },
+
+    #[error("ISO-TP - timeout when reading({value}{unit})")]
+    ReadTimeout { value: u64, unit: &'static str },
 
     #[error("ISO-TP - ECU has overload flow control response")]
     OverloadFlow,
+
+    #[error("ISO-TP - ECU has more than {0}(N_WFTmax) wait flow control responses")]
+    WaitFlowOverrun(u8),
+
+    #[error("ISO-TP - the previous pdu(protocol data unit) is transmitting")]
+    Busy,
+
+    #[error("ISO-TP - invalid trace at line {line}: {reason}")]
+    InvalidTrace { line: usize, reason: String },
+
+    #[error("ISO-TP - unexpected {0:?} frame")]
+    UnexpectedFrame(FrameType),
+
+    #[error("ISO-TP - adapter error: {0}")]
+    AdapterError(String),
+
+    #[error("ISO-TP - reading the source of pdu(protocol data unit) failed: {0}")]
+    SourceError(String),
 }
@@ -1,6 +1,9 @@
+use alloc::{format, vec::Vec};
+
 use crate::constants::{DEFAULT_BLOCK_SIZE, DEFAULT_ST_MIN};
-use crate::core::{FlowControlContext, FlowControlState};
+use crate::core::{FlowControlContext, FlowControlState, Version};
 use crate::error::Error;
+use crate::standard::Segmenter;
 
 /// ISO 15765-2 frame type define.
 #[repr(u8)]
@@ -80,37 +83,40 @@ impl Frame {
     /// # Return
     ///
     /// A struct that implements [`IsoTpFrame`] if parameters are valid.
+    #[inline]
     pub fn decode<T: AsRef<[u8]>>(data: T) -> Result<Self, Error> {
-        let data = data.as_ref();
-        let length = data.len();
-        match length {
-            0 => Err(Error::EmptyPdu),
-            1..=2 => Err(Error::InvalidPdu(data.to_vec())),
-            3.. => {
-                let byte0 = data[0];
-                match FrameType::try_from(byte0)? {
-                    FrameType::Single => {   // Single frame
-                        #[cfg(feature = "can")]
-                        crate::can::standard::decode_single(data, byte0, length)
-                    },
-                    FrameType::First => {   // First frame
-                        #[cfg(feature = "can")]
-                        crate::can::standard::decode_first(data, byte0, length)
-                    },
-                    FrameType::Consecutive => {
-                        let sequence = byte0 & 0x0F;
-                        Ok(Self::ConsecutiveFrame { sequence, data: Vec::from(&data[1..]) })
-                    },
-                    FrameType::FlowControl => {
-                        // let suppress_positive = (data1 & 0x80) == 0x80;
-                        let state = FlowControlState::try_from(byte0 & 0x0F)?;
-                        let fc = FlowControlContext::new(state, data[1], data[2])?;
-                        Ok(Self::FlowControlFrame(fc))
-                    },
-                }
-            }
-            // v => Err(IsoTpError::LengthOutOfRange(v)),
-        }
+        Self::decode_with_offset(data, 0)
+    }
+
+    /// Decode frame from origin data like `F1 02 10 01`.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - the source data.
+    /// * `offset` - the length of address information(N_TA or N_AE) before N_PCI.
+    ///
+    /// # Return
+    ///
+    /// A struct that implements [`IsoTpFrame`] if parameters are valid.
+    #[inline]
+    pub fn decode_with_offset<T: AsRef<[u8]>>(data: T, offset: usize) -> Result<Self, Error> {
+        FrameRef::decode_with_offset(data.as_ref(), offset)
+            .map(Into::into)
+    }
+
+    /// Segment the PDU lazily, the frames are encoded one by one, see [`Segments`].
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - the PDU.
+    /// * `config` - [`SegmentConfig`].
+    ///
+    /// # Returns
+    ///
+    /// The same frames as [`Frame::from_data_with_dl`] encoded by [`Frame::encode_with_ext`].
+    #[inline]
+    pub fn segments(data: &[u8], config: SegmentConfig) -> Result<Segments<'_>, Error> {
+        Ok(Segments { data, segmenter: Segmenter::new(data.len(), config)? })
     }
 
     /// Encode frame to data.
@@ -122,32 +128,46 @@ impl Frame {
     /// # Returns
     ///
     /// The encoded data.
+    #[inline]
     pub fn encode(self, padding: Option<u8>) -> Vec<u8> {
+        self.encode_with_ext(None, padding)
+    }
+
+    /// Encode frame to data with address information.
+    ///
+    /// # Parameters
+    ///
+    /// * `ext` - the address information(N_TA or N_AE) placed before N_PCI.
+    /// * `padding` - the padding value when the length of return value is insufficient.
+    ///
+    /// # Returns
+    ///
+    /// The encoded data.
+    pub fn encode_with_ext(self, ext: Option<u8>, padding: Option<u8>) -> Vec<u8> {
         match self {
             Self::SingleFrame { data } => {
-                #[cfg(feature = "can")]
-                crate::can::standard::encode_single(data, padding)
+                crate::standard::encode_single(data, ext, padding)
             },
             Self::FirstFrame { length, data } => {
-                #[cfg(feature = "can")]
-                crate::can::standard::encode_first(length, data)
+                crate::standard::encode_first(length, data, ext)
             },
             Self::ConsecutiveFrame { sequence, mut data } => {
-                let mut result = vec![FrameType::Consecutive as u8 | sequence];
+                let mut result: Vec<_> = ext.into_iter().collect();
+                result.push(FrameType::Consecutive as u8 | sequence);
                 result.append(&mut data);
-                #[cfg(feature = "can")]
-                result.resize(rs_can::MAX_FRAME_SIZE, padding.unwrap_or(rs_can::DEFAULT_PADDING));
+                crate::standard::pad_frame(&mut result, padding);
                 result
             },
             Self::FlowControlFrame(context) => {
                 let byte0_h: u8 = FrameType::FlowControl.into();
                 let byte0_l: u8 = context.state().into();
-                let mut result = vec![
+                let mut result: Vec<_> = ext.into_iter().collect();
+                result.extend([
                     byte0_h | byte0_l,
                     context.block_size(),
                     context.st_min(),
-                ];
-                result.resize(rs_can::MAX_FRAME_SIZE, padding.unwrap_or(rs_can::DEFAULT_PADDING));
+                ]);
+                result.resize(crate::standard::MAX_FRAME_SIZE, padding.unwrap_or(crate::standard::DEFAULT_PADDING));
                 result
             },
         }
@@ -168,8 +188,61 @@ impl Frame {
     /// with a `FirstFrame` and followed by at least one `FlowControlFrame`.
     #[inline]
     pub fn from_data<T: AsRef<[u8]>>(data: T) -> Result<Vec<Self>, Error> {
-        #[cfg(feature = "can")]
-        crate::can::standard::from_data(data.as_ref())
+        Self::from_data_with_offset(data, 0)
+    }
+
+    /// Encoding full multi-frame from original data with address information.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - original data
+    ///
+    /// * `offset` - the length of address information(N_TA or N_AE) before N_PCI.
+    ///
+    /// # Returns
+    ///
+    /// The same as [`Frame::from_data`], but each frame leaves room for the address information.
+    #[inline]
+    pub fn from_data_with_offset<T: AsRef<[u8]>>(data: T, offset: usize) -> Result<Vec<Self>, Error> {
+        Self::from_data_with_version(data, offset, Default::default())
+    }
+
+    /// Encoding full multi-frame from original data by the ISO 15765-2 version.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - original data
+    ///
+    /// * `offset` - the length of address information(N_TA or N_AE) before N_PCI.
+    ///
+    /// * `version` - [`Version`], the 2016 escape sequences are used only if the version is `Std2016`.
+    ///
+    /// # Returns
+    ///
+    /// The same as [`Frame::from_data_with_offset`].
+    #[inline]
+    pub fn from_data_with_version<T: AsRef<[u8]>>(data: T, offset: usize, version: Version) -> Result<Vec<Self>, Error> {
+        Self::from_data_with_dl(data, offset, version, crate::standard::MAX_FRAME_SIZE)
+    }
+
+    /// Encoding full multi-frame from original data by the ISO 15765-2 version and TX_DL.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - original data
+    ///
+    /// * `offset` - the length of address information(N_TA or N_AE) before N_PCI.
+    ///
+    /// * `version` - [`Version`], the 2016 escape sequences are used only if the version is `Std2016`.
+    ///
+    /// * `tx_dl` - the data length of CAN frame, one of 8(classic CAN), 12, 16, 20, 24, 32, 48 or 64(CAN FD).
+    ///
+    /// # Returns
+    ///
+    /// The same as [`Frame::from_data_with_offset`].
+    #[inline]
+    pub fn from_data_with_dl<T: AsRef<[u8]>>(data: T, offset: usize, version: Version, tx_dl: usize) -> Result<Vec<Self>, Error> {
+        crate::standard::from_data(data.as_ref(), offset, version, tx_dl)
     }
 
     /// New single frame from data.
@@ -181,8 +254,7 @@ impl Frame {
     /// A new `SingleFrame` if parameters are valid.
     #[inline]
     pub fn single_frame<T: AsRef<[u8]>>(data: T) -> Result<Self, Error> {
-        #[cfg(feature = "can")]
-        crate::can::standard::new_single(data)
+        crate::standard::new_single(data)
     }
 
     /// New flow control frame from data.
@@ -216,3 +288,144 @@ impl Frame {
             .unwrap()
     }
 }
+
+/// ISO-TP frame borrowed from the CAN frame data, the decoding doesn't allocate, see [`Frame`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameRef<'a> {
+    /// The ISO-TP single frame.
+    SingleFrame { data: &'a [u8] },
+    /// The ISO-TP first frame.
+    FirstFrame { length: u32, data: &'a [u8] },
+    /// The ISO-TP consecutive frame.
+    ConsecutiveFrame { sequence: u8, data: &'a [u8] },
+    /// The ISO-TP flow control frame.
+    FlowControlFrame(FlowControlContext)
+}
+
+impl<'a> FrameRef<'a> {
+    /// Decode frame from origin data like `02 10 01`, see [`Frame::decode`].
+    #[inline]
+    pub fn decode(data: &'a [u8]) -> Result<Self, Error> {
+        Self::decode_with_offset(data, 0)
+    }
+
+    /// Decode frame from origin data like `F1 02 10 01`, see [`Frame::decode_with_offset`].
+    pub fn decode_with_offset(data: &'a [u8], offset: usize) -> Result<Self, Error> {
+        let data = data.get(offset..)
+            .ok_or(Error::InvalidPdu(data.to_vec()))?;
+        let length = data.len();
+        match length {
+            0 => Err(Error::EmptyPdu),
+            1..=2 => Err(Error::InvalidPdu(data.to_vec())),
+            3.. => {
+                let byte0 = data[0];
+                match FrameType::try_from(byte0)? {
+                    FrameType::Single => {   // Single frame
+                        crate::standard::decode_single(data, byte0, length, offset)
+                    },
+                    FrameType::First => {   // First frame
+                        crate::standard::decode_first(data, byte0, length, offset)
+                    },
+                    FrameType::Consecutive => {
+                        let sequence = byte0 & 0x0F;
+                        Ok(Self::ConsecutiveFrame { sequence, data: &data[1..] })
+                    },
+                    FrameType::FlowControl => {
+                        // let suppress_positive = (data1 & 0x80) == 0x80;
+                        let state = FlowControlState::try_from(byte0 & 0x0F)?;
+                        let fc = FlowControlContext::new(state, data[1], data[2])?;
+                        Ok(Self::FlowControlFrame(fc))
+                    },
+                }
+            }
+        }
+    }
+
+    #[inline]
+    pub fn frame_type(&self) -> FrameType {
+        match self {
+            Self::SingleFrame { .. } => FrameType::Single,
+            Self::FirstFrame { .. } => FrameType::First,
+            Self::ConsecutiveFrame { .. } => FrameType::Consecutive,
+            Self::FlowControlFrame(..) => FrameType::FlowControl,
+        }
+    }
+}
+
+impl From<FrameRef<'_>> for Frame {
+    fn from(frame: FrameRef<'_>) -> Self {
+        match frame {
+            FrameRef::SingleFrame { data } => Self::SingleFrame { data: data.to_vec() },
+            FrameRef::FirstFrame { length, data } => Self::FirstFrame { length, data: data.to_vec() },
+            FrameRef::ConsecutiveFrame { sequence, data } => Self::ConsecutiveFrame { sequence, data: data.to_vec() },
+            FrameRef::FlowControlFrame(context) => Self::FlowControlFrame(context),
+        }
+    }
+}
+
+/// The parameters of segmenting the PDU, see [`Frame::segments`].
+///
+/// * `ext`: the address information(N_TA or N_AE) placed before N_PCI.
+/// * `version`: [`Version`], the 2016 escape sequences are used only if the version is `Std2016`.
+/// * `tx_dl`: the data length of CAN frame, one of 8(classic CAN), 12, 16, 20, 24, 32, 48 or 64(CAN FD).
+/// * `padding`: the padding value of frames, the default value is used when it's `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentConfig {
+    pub ext: Option<u8>,
+    pub version: Version,
+    pub tx_dl: usize,
+    pub padding: Option<u8>,
+}
+
+impl Default for SegmentConfig {
+    fn default() -> Self {
+        Self {
+            ext: Default::default(),
+            version: Default::default(),
+            tx_dl: crate::standard::MAX_FRAME_SIZE,
+            padding: Default::default(),
+        }
+    }
+}
+
+/// The lazy segmenting of PDU, created by [`Frame::segments`].
+///
+/// [`Segments::next_into`] encodes the next frame into the buffer of caller without allocation,
+/// the iterator allocates one `Vec` per frame.
+#[derive(Debug, Clone)]
+pub struct Segments<'a> {
+    data: &'a [u8],
+    segmenter: Segmenter,
+}
+
+impl Segments<'_> {
+    /// Encode the next frame into `buffer`, it should be at least TX_DL bytes.
+    ///
+    /// # Returns
+    ///
+    /// The length of frame written, `None` if all frames are encoded.
+    /// [`Error::InvalidDataLength`] if the buffer is too small, the frame is not skipped.
+    #[inline]
+    pub fn next_into(&mut self, buffer: &mut [u8]) -> Option<Result<usize, Error>> {
+        self.segmenter.next_into(self.data, buffer)
+    }
+}
+
+impl Iterator for Segments<'_> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buffer = [0; crate::standard::MAX_FD_FRAME_SIZE];
+        // the buffer is large enough for all frames.
+        let length = self.next_into(&mut buffer)?.ok()?;
+        Some(buffer[..length].to_vec())
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.segmenter.remaining();
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Segments<'_> {}
@@ -1,23 +1,42 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 #[cfg(feature = "can")]
 mod can;
 mod constants;
 mod core;
 mod error;
 mod frame;
+mod machine;
+mod standard;
 
 #[cfg(feature = "can")]
 pub use crate::can::*;
 pub use crate::constants::*;
 pub use crate::core::{
     ByteOrder,
+    FlowControlConfig,
     FlowControlContext,
     FlowControlState,
     Event as IsoTpEvent,
     EventListener as IsoTpEventListener,
-    State as IsoTpState
+    State as IsoTpState,
+    Statistics as IsoTpStatistics,
+    Timer as IsoTpTimer,
+    TimingConfig,
+    Version as IsoTpVersion,
 };
 pub use crate::error::{Error as IsoTpError};
+pub use crate::machine::{
+    Action as IsoTpAction,
+    Actions as IsoTpActions,
+    IsoTpMachine,
+};
 pub use crate::frame::{
     Frame as IsoTpFrame,
+    FrameRef as IsoTpFrameRef,
     FrameType as IsoTpFrameType,
+    SegmentConfig,
+    Segments as IsoTpSegments,
 };
@@ -0,0 +1,661 @@
+use alloc::{collections::VecDeque, format, vec::Vec};
+use ::core::time::Duration;
+
+use crate::constants::CONSECUTIVE_SEQUENCE_START;
+use crate::core::{Event, FlowControlConfig, FlowControlContext, FlowControlState, State, Statistics, Timer, TimingConfig, Version};
+use crate::error::Error;
+use crate::frame::{Frame, FrameRef, FrameType, SegmentConfig};
+use crate::standard::{Segmenter, CAN_DATA_LENGTHS, MAX_FD_FRAME_SIZE, MAX_FRAME_SIZE};
+
+/// The output of [`IsoTpMachine`], the driver should execute them in order.
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Transmit the frame of the PDU written.
+    Transmit(Vec<u8>),
+    /// Transmit the flow control frame of the PDU receiving.
+    TransmitFlowCtrl(Vec<u8>),
+    /// Notify the event to the upper layer.
+    Event(Event),
+    /// The PDU written is transmitted, or aborted by the error.
+    TransmitFinished(Result<(), Error>),
+}
+
+pub type Actions = Vec<Action>;
+
+#[derive(Debug, Clone)]
+struct Transmitter {
+    /// The data written and not encoded yet, the frames are encoded when they are transmitted.
+    pending: VecDeque<u8>,
+    /// The length of data written.
+    written: usize,
+    segmenter: Segmenter,
+    block_size: u8,
+    block_index: u8,
+    st_min: Duration,
+    /// The flow control frame is required after the first frame or the last consecutive frame of block.
+    flow_ctrl_required: bool,
+    /// The number of `Wait` flow control frames received in a row.
+    wait_count: u8,
+    /// The deadline of N_Bs when waiting flow control frame.
+    flow_ctrl_deadline: Option<Duration>,
+    /// The time when the next consecutive frame should be transmitted.
+    next_frame: Option<Duration>,
+    /// The time when the last frame is transmitted and not confirmed yet(N_As).
+    confirm: Option<Duration>,
+    /// The time when the last consecutive frame of current block is confirmed.
+    last_frame: Option<Duration>,
+}
+
+impl Transmitter {
+    /// Encode the next frame if its data is written.
+    fn encode_next(&mut self) -> Option<Vec<u8>> {
+        let length = self.segmenter.chunk_len();
+        if length == 0 || self.pending.len() < length {
+            return None;
+        }
+
+        let mut chunk = [0; MAX_FD_FRAME_SIZE];
+        chunk.iter_mut()
+            .zip(self.pending.drain(..length))
+            .for_each(|(v, b)| *v = b);
+        let mut buffer = [0; MAX_FD_FRAME_SIZE];
+        self.segmenter.encode(&chunk[..length], &mut buffer)
+            .ok()
+            .map(|size| buffer[..size].to_vec())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Receiver {
+    length: usize,
+    buffer: Vec<u8>,
+    /// The sequence of the last consecutive frame.
+    sequence: u8,
+    rx_dl: usize,
+    block_index: u8,
+    /// The number of `Wait` flow control frames sent in a row.
+    wait_sent: u8,
+    /// The deadline of N_Cr, or the time to send the next flow control frame after `Wait`(N_Br).
+    deadline: Option<(Timer, Duration)>,
+    /// The time when the last flow control frame is transmitted and not confirmed yet(N_Ar).
+    confirm: Option<Duration>,
+}
+
+/// The sans-IO ISO-TP state machine of one connection.
+///
+/// The machine doesn't touch any device or clock, the driver feeds it with the received frames,
+/// the transmission confirmations and the current time, and executes the returned [`Action`]s.
+/// The time `now` is the monotonic time since any fixed point chosen by the driver.
+///
+/// The transmission and the reception are full-duplex, each direction has its own state and timers,
+/// an error of one direction never aborts the other.
+///
+/// * [`IsoTpMachine::write`] - start transmitting a PDU.
+/// * [`IsoTpMachine::on_frame`] - a frame is received.
+/// * [`IsoTpMachine::on_transmitted`] - a frame transmitted is confirmed(N_As, N_Ar).
+/// * [`IsoTpMachine::poll_timeout`] - check the timers and transmit the pending consecutive frames,
+///   it should be called at [`IsoTpMachine::next_deadline`] at latest.
+#[derive(Debug, Clone)]
+pub struct IsoTpMachine {
+    /// The length of address information(N_TA or N_AE) before N_PCI of received frames.
+    offset: usize,
+    /// The address information placed before N_PCI of flow control frames.
+    ext: Option<u8>,
+    timing: TimingConfig,
+    flow_ctrl_config: FlowControlConfig,
+    version: Version,
+    tx_dl: usize,
+    rx_dl: usize,
+    padding: Option<u8>,
+    statistics: Statistics,
+    tx: Option<Transmitter>,
+    rx: Option<Receiver>,
+}
+
+impl IsoTpMachine {
+    /// # Parameters
+    ///
+    /// * `offset` - the length of address information(N_TA or N_AE) before N_PCI of received frames.
+    /// * `ext` - the address information placed before N_PCI of flow control frames.
+    pub fn new(offset: usize, ext: Option<u8>) -> Self {
+        Self {
+            offset,
+            ext,
+            timing: Default::default(),
+            flow_ctrl_config: Default::default(),
+            version: Default::default(),
+            tx_dl: MAX_FRAME_SIZE,
+            rx_dl: MAX_FRAME_SIZE,
+            padding: Default::default(),
+            statistics: Default::default(),
+            tx: Default::default(),
+            rx: Default::default(),
+        }
+    }
+
+    /// Update the address information, see [`IsoTpMachine::new`].
+    #[inline]
+    pub fn set_address(&mut self, offset: usize, ext: Option<u8>) {
+        self.offset = offset;
+        self.ext = ext;
+    }
+
+    #[inline]
+    pub fn set_timing(&mut self, timing: TimingConfig) {
+        self.timing = timing;
+    }
+
+    #[inline]
+    pub fn timing(&self) -> TimingConfig {
+        self.timing
+    }
+
+    pub fn set_flow_ctrl(&mut self, config: FlowControlConfig) -> Result<(), Error> {
+        config.validate()?;
+        self.flow_ctrl_config = config;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn flow_ctrl(&self) -> FlowControlConfig {
+        self.flow_ctrl_config
+    }
+
+    #[inline]
+    pub fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+
+    #[inline]
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Set TX_DL, the value must be 8(classic CAN), 12, 16, 20, 24, 32, 48 or 64(CAN FD).
+    pub fn set_tx_dl(&mut self, tx_dl: usize) -> Result<(), Error> {
+        if !CAN_DATA_LENGTHS.contains(&tx_dl) {
+            return Err(Error::InvalidParam(format!("`tx_dl`({})", tx_dl)));
+        }
+
+        self.tx_dl = tx_dl;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn tx_dl(&self) -> usize {
+        self.tx_dl
+    }
+
+    /// RX_DL detected from the last first frame received.
+    #[inline]
+    pub fn rx_dl(&self) -> usize {
+        self.rx_dl
+    }
+
+    /// Set the padding value of frames, [`Frame::encode`] is referred when it's `None`.
+    #[inline]
+    pub fn set_padding(&mut self, padding: Option<u8>) {
+        self.padding = padding;
+    }
+
+    #[inline]
+    pub fn is_transmitting(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    #[inline]
+    pub fn is_receiving(&self) -> bool {
+        self.rx.is_some()
+    }
+
+    /// The state of transmission.
+    ///
+    /// * [`State::Idle`] - no PDU is writing.
+    /// * [`State::Sending`] - a frame is waiting for confirmation or the separation time.
+    /// * [`State::WaitFlowCtrl`] - waiting for the flow control frame.
+    /// * [`State::WaitBusy`] - the `Wait` flow control frame is received.
+    pub fn tx_state(&self) -> State {
+        match &self.tx {
+            None => State::Idle,
+            Some(tx) if tx.confirm.is_some() || !tx.segmenter.is_started() => State::Sending,
+            Some(tx) if tx.wait_count > 0 => State::WaitBusy,
+            Some(tx) if tx.flow_ctrl_required => State::WaitFlowCtrl,
+            Some(_) => State::Sending,
+        }
+    }
+
+    /// The state of reception.
+    ///
+    /// * [`State::Idle`] - no multi-frame PDU is receiving.
+    /// * [`State::Sending`] - the flow control frame is waiting for confirmation.
+    /// * [`State::WaitBusy`] - the `Wait` flow control frame is sent.
+    /// * [`State::WaitData`] - waiting for the consecutive frames.
+    pub fn rx_state(&self) -> State {
+        match &self.rx {
+            None => State::Idle,
+            Some(rx) if rx.confirm.is_some() => State::Sending,
+            Some(rx) if rx.wait_sent > 0 => State::WaitBusy,
+            Some(_) => State::WaitData,
+        }
+    }
+
+    /// The statistics of the consecutive frames transmitted.
+    #[inline]
+    pub fn statistics(&self) -> Statistics {
+        self.statistics
+    }
+
+    #[inline]
+    pub fn reset_statistics(&mut self) {
+        self.statistics = Default::default();
+    }
+
+    /// Abort the transmission without any action, used when the driver fails to transmit the frame.
+    #[inline]
+    pub fn abort_transmit(&mut self) {
+        self.tx = Default::default();
+    }
+
+    /// Abort the reception without any action, used when the driver fails to transmit the flow control frame.
+    #[inline]
+    pub fn abort_receive(&mut self) {
+        self.rx = Default::default();
+    }
+
+    /// Abort the transmission and the reception without any action.
+    pub fn reset(&mut self) {
+        self.tx = Default::default();
+        self.rx = Default::default();
+    }
+
+    /// Start transmitting a PDU.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - the PDU.
+    /// * `ext` - the address information placed before N_PCI(N_TA or N_AE).
+    /// * `now` - the current time.
+    ///
+    /// # Returns
+    ///
+    /// The single frame or the first frame to transmit,
+    /// [`Action::TransmitFinished`] is returned later when the PDU is transmitted or aborted.
+    pub fn write(&mut self, data: &[u8], ext: Option<u8>, now: Duration) -> Result<Actions, Error> {
+        self.write_stream(data.len(), ext)?;
+        self.feed(data, now)
+    }
+
+    /// Start transmitting a PDU whose data is written by [`IsoTpMachine::feed`] later,
+    /// the first frame is transmitted when its data is fed.
+    ///
+    /// # Parameters
+    ///
+    /// * `length` - the length of PDU.
+    /// * `ext` - the address information placed before N_PCI(N_TA or N_AE).
+    pub fn write_stream(&mut self, length: usize, ext: Option<u8>) -> Result<(), Error> {
+        if self.tx.is_some() {
+            return Err(Error::Busy);
+        }
+
+        let config = SegmentConfig { ext, version: self.version, tx_dl: self.tx_dl, padding: self.padding };
+        self.tx = Some(Transmitter {
+            pending: Default::default(),
+            written: Default::default(),
+            segmenter: Segmenter::new(length, config)?,
+            block_size: Default::default(),
+            block_index: Default::default(),
+            st_min: Default::default(),
+            flow_ctrl_required: true,
+            wait_count: Default::default(),
+            flow_ctrl_deadline: Default::default(),
+            next_frame: Default::default(),
+            confirm: Default::default(),
+            last_frame: Default::default(),
+        });
+
+        Ok(())
+    }
+
+    /// The length of data required to transmit the next frame of [`IsoTpMachine::write_stream`],
+    /// 0 if the data is enough or no PDU is transmitting.
+    pub fn data_required(&self) -> usize {
+        self.tx.as_ref()
+            .map_or(0, |tx| tx.segmenter.chunk_len().saturating_sub(tx.pending.len()))
+    }
+
+    /// Feed the data of the PDU transmitting, the frame is transmitted if its data is enough
+    /// and the flow control allows.
+    ///
+    /// # Returns
+    ///
+    /// The frame to transmit, [`Error::InvalidDataLength`] if the data exceeds the length of PDU.
+    pub fn feed(&mut self, data: &[u8], now: Duration) -> Result<Actions, Error> {
+        let Some(tx) = self.tx.as_mut() else {
+            return Err(Error::InvalidParam("no PDU is transmitting".into()));
+        };
+        let length = tx.segmenter.length();
+        if tx.written + data.len() > length {
+            return Err(Error::InvalidDataLength { actual: tx.written + data.len(), expect: length });
+        }
+
+        tx.pending.extend(data);
+        tx.written += data.len();
+
+        let mut actions = Vec::new();
+        if !tx.segmenter.is_started() {
+            if let Some(frame) = tx.encode_next() {
+                tx.confirm = Some(now);
+                actions.push(Action::Transmit(frame));
+            }
+        }
+        else {
+            self.transmit_next(&mut actions, now);
+        }
+
+        Ok(actions)
+    }
+
+    /// Abort the PDU transmitting by the upper layer, e.g. the source of data fails.
+    pub fn abort_write(&mut self, error: Error) -> Actions {
+        let mut actions = Vec::new();
+        self.abort_tx(&mut actions, error);
+        actions
+    }
+
+    /// Handle the received frame.
+    ///
+    /// The reception recovers by itself:
+    ///
+    /// * a single frame or first frame aborts the PDU receiving and starts the new one.
+    /// * a consecutive frame with wrong sequence or length aborts the PDU receiving only.
+    /// * an unexpected or invalid frame is ignored.
+    ///
+    /// The aborted PDU is reported by [`Event::ReceiveAborted`] and the reception returns to idle.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - the frame data with address information.
+    /// * `now` - the current time.
+    pub fn on_frame(&mut self, data: &[u8], now: Duration) -> Actions {
+        let mut actions = Vec::new();
+        match FrameRef::decode_with_offset(data, self.offset) {
+            Ok(FrameRef::SingleFrame { data }) => {
+                self.abort_rx(&mut actions, Error::MixFramesError);
+                actions.push(Action::Event(Event::DataReceived(data.to_vec())));
+            },
+            Ok(FrameRef::FirstFrame { length, data: payload }) => {
+                self.abort_rx(&mut actions, Error::MixFramesError);
+                self.rx_dl = data.len();
+                self.rx = Some(Receiver {
+                    length: length as usize,
+                    buffer: payload.to_vec(),
+                    sequence: CONSECUTIVE_SEQUENCE_START - 1,
+                    rx_dl: data.len(),
+                    block_index: Default::default(),
+                    wait_sent: Default::default(),
+                    deadline: Default::default(),
+                    confirm: Default::default(),
+                });
+                self.send_flow_ctrl(&mut actions, now);
+                actions.push(Action::Event(Event::FirstFrameReceived));
+            },
+            Ok(FrameRef::ConsecutiveFrame { sequence, data: payload }) => {
+                self.on_consecutive_frame(&mut actions, sequence, payload, data.len(), now);
+            },
+            Ok(FrameRef::FlowControlFrame(ctx)) => {
+                self.on_flow_ctrl_frame(&mut actions, ctx, now);
+            },
+            // the frame with unknown N_PCI or invalid length is ignored.
+            Err(e) => actions.push(Action::Event(Event::ErrorOccurred(e))),
+        }
+
+        actions
+    }
+
+    /// Handle the transmission confirmation of a frame.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - the frame data with address information, the flow control frame confirms the reception,
+    ///   others confirm the transmission.
+    /// * `now` - the current time.
+    pub fn on_transmitted(&mut self, data: &[u8], now: Duration) -> Actions {
+        let mut actions = Vec::new();
+        let frame_type = data.get(self.offset)
+            .and_then(|pci| FrameType::try_from(*pci).ok());
+        if frame_type == Some(FrameType::FlowControl) {
+            if let Some(rx) = self.rx.as_mut().filter(|rx| rx.confirm.is_some()) {
+                rx.confirm = None;
+                if rx.deadline.is_none() {
+                    rx.deadline = Some((Timer::NCr, now + millis(self.timing.n_cr)));
+                }
+            }
+        }
+        else if let Some(tx) = self.tx.as_mut().filter(|tx| tx.confirm.is_some()) {
+            tx.confirm = None;
+            if frame_type == Some(FrameType::Consecutive) {
+                self.statistics.frames += 1;
+                if let Some(last) = tx.last_frame {
+                    self.statistics.record(now.saturating_sub(last), tx.st_min);
+                }
+                tx.last_frame = Some(now);
+            }
+            if tx.segmenter.is_finished() {
+                self.tx = None;
+                actions.push(Action::TransmitFinished(Ok(())));
+            }
+            // the flow control frame may be received before the confirmation.
+            else if tx.next_frame.is_none() && tx.flow_ctrl_deadline.is_none() {
+                if tx.flow_ctrl_required {
+                    tx.flow_ctrl_deadline = Some(now + millis(self.timing.n_bs));
+                }
+                else {
+                    tx.next_frame = Some(now + tx.st_min);
+                }
+            }
+        }
+
+        self.transmit_next(&mut actions, now);
+        actions
+    }
+
+    /// Check the timers and transmit the consecutive frames whose separation time is passed.
+    pub fn poll_timeout(&mut self, now: Duration) -> Actions {
+        let mut actions = Vec::new();
+
+        if let Some(time) = self.tx.as_ref().and_then(|tx| tx.confirm) {
+            if now > time + millis(self.timing.n_as) {
+                self.abort_tx(&mut actions, self.timing.timeout(Timer::NAs));
+            }
+        }
+        if let Some(deadline) = self.tx.as_ref().and_then(|tx| tx.flow_ctrl_deadline) {
+            if now > deadline {
+                self.abort_tx(&mut actions, self.timing.timeout(Timer::NBs));
+            }
+        }
+        if let Some(next) = self.tx.as_ref().and_then(|tx| tx.next_frame) {
+            if now > next + millis(self.timing.n_cs) {
+                self.abort_tx(&mut actions, self.timing.timeout(Timer::NCs));
+            }
+        }
+        self.transmit_next(&mut actions, now);
+
+        if let Some(time) = self.rx.as_ref().and_then(|rx| rx.confirm) {
+            if now > time + millis(self.timing.n_ar) {
+                self.abort_rx(&mut actions, self.timing.timeout(Timer::NAr));
+            }
+        }
+        if let Some((timer, deadline)) = self.rx.as_ref().and_then(|rx| rx.deadline) {
+            if now >= deadline {
+                match timer {
+                    Timer::NBr => self.send_flow_ctrl(&mut actions, now),
+                    _ => self.abort_rx(&mut actions, self.timing.timeout(timer)),
+                }
+            }
+        }
+
+        actions
+    }
+
+    /// The time when the next consecutive frame is transmitted(the STmin is passed),
+    /// `None` if it's waiting for the confirmation or the flow control frame.
+    #[inline]
+    pub fn next_frame(&self) -> Option<Duration> {
+        self.tx.as_ref()
+            .filter(|tx| tx.confirm.is_none())
+            .and_then(|tx| tx.next_frame)
+    }
+
+    /// The time when [`IsoTpMachine::poll_timeout`] should be called next.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        let tx = self.tx.as_ref()
+            .and_then(|tx| tx.confirm.map(|t| t + millis(self.timing.n_as))
+                .or(tx.flow_ctrl_deadline)
+                .or(tx.next_frame));
+        let rx = self.rx.as_ref()
+            .and_then(|rx| rx.confirm.map(|t| t + millis(self.timing.n_ar))
+                .or(rx.deadline.map(|(_, deadline)| deadline)));
+
+        [tx, rx].into_iter()
+            .flatten()
+            .min()
+    }
+
+    fn on_consecutive_frame(&mut self, actions: &mut Actions, sequence: u8, payload: &[u8], dl: usize, now: Duration) {
+        let Some(rx) = self.rx.as_mut() else {
+            log::warn!("ISO-TP - unexpected consecutive frame is ignored");
+            return;
+        };
+
+        let expect = (rx.sequence + 1) & 0x0F;
+        if sequence != expect {
+            self.abort_rx(actions, Error::InvalidSequence { expect, actual: sequence });
+            return;
+        }
+        if dl > rx.rx_dl {
+            let error = Error::InvalidDataLength { actual: dl, expect: rx.rx_dl };
+            self.abort_rx(actions, error);
+            return;
+        }
+
+        rx.sequence = sequence;
+        rx.buffer.extend_from_slice(payload);
+        if rx.buffer.len() >= rx.length {
+            let mut data = core::mem::take(&mut rx.buffer);
+            data.truncate(rx.length);
+            self.rx = None;
+            actions.push(Action::Event(Event::DataReceived(data)));
+            return;
+        }
+
+        actions.push(Action::Event(Event::Wait));
+        rx.deadline = Some((Timer::NCr, now + millis(self.timing.n_cr)));
+        let block_size = self.flow_ctrl_config.block_size;
+        if block_size != 0 {
+            rx.block_index += 1;
+            if rx.block_index >= block_size {
+                self.send_flow_ctrl(actions, now);
+            }
+        }
+    }
+
+    fn on_flow_ctrl_frame(&mut self, actions: &mut Actions, ctx: FlowControlContext, now: Duration) {
+        let Some(tx) = self.tx.as_mut()
+            .filter(|tx| tx.flow_ctrl_required) else {
+            log::warn!("ISO-TP - unexpected flow control frame is ignored");
+            return;
+        };
+
+        match ctx.state() {
+            FlowControlState::Continues => {
+                tx.wait_count = 0;
+                tx.block_size = ctx.block_size();
+                tx.st_min = Duration::from_micros(ctx.st_min_us() as u64);
+                tx.flow_ctrl_required = false;
+                tx.flow_ctrl_deadline = None;
+                tx.last_frame = None;
+                tx.next_frame = Some(now);
+                self.transmit_next(actions, now);
+            },
+            FlowControlState::Wait => {
+                tx.wait_count += 1;
+                let wft_max = self.flow_ctrl_config.wft_max;
+                if tx.wait_count > wft_max {
+                    self.abort_tx(actions, Error::WaitFlowOverrun(wft_max));
+                }
+                else {
+                    tx.flow_ctrl_deadline = Some(now + millis(self.timing.n_bs));
+                    actions.push(Action::Event(Event::Wait));
+                }
+            },
+            FlowControlState::Overload => self.abort_tx(actions, Error::OverloadFlow),
+        }
+    }
+
+    /// Transmit the next consecutive frame if the previous frame is confirmed
+    /// and the separation time is passed.
+    fn transmit_next(&mut self, actions: &mut Actions, now: Duration) {
+        let Some(tx) = self.tx.as_mut()
+            .filter(|tx| tx.confirm.is_none()) else { return };
+        if tx.next_frame.is_none_or(|next| now < next) {
+            return;
+        }
+
+        if let Some(frame) = tx.encode_next() {
+            tx.next_frame = None;
+            if tx.block_size != 0 {
+                tx.block_index += 1;
+                if tx.block_index >= tx.block_size {
+                    tx.block_index = 0;
+                    tx.flow_ctrl_required = true;
+                }
+            }
+            tx.confirm = Some(now);
+            actions.push(Action::Transmit(frame));
+        }
+    }
+
+    /// Send `Wait` flow control frames according to [`FlowControlConfig`], then `Continues`.
+    fn send_flow_ctrl(&mut self, actions: &mut Actions, now: Duration) {
+        let config = self.flow_ctrl_config;
+        let Some(rx) = self.rx.as_mut() else { return };
+
+        let state = if rx.wait_sent < config.wait_count {
+            rx.wait_sent += 1;
+            rx.deadline = Some((Timer::NBr, now + millis(self.timing.n_br / 2)));
+            FlowControlState::Wait
+        }
+        else {
+            rx.wait_sent = 0;
+            rx.block_index = 0;
+            rx.deadline = None;
+            FlowControlState::Continues
+        };
+
+        match Frame::flow_ctrl_frame(state, config.block_size, config.st_min) {
+            Ok(frame) => {
+                rx.confirm = Some(now);
+                actions.push(Action::TransmitFlowCtrl(frame.encode_with_ext(self.ext, self.padding)));
+            },
+            Err(e) => self.abort_rx(actions, e),
+        }
+    }
+
+    fn abort_tx(&mut self, actions: &mut Actions, error: Error) {
+        if self.tx.take().is_some() {
+            actions.push(Action::Event(Event::TransmitAborted(error.clone())));
+            actions.push(Action::TransmitFinished(Err(error)));
+        }
+    }
+
+    fn abort_rx(&mut self, actions: &mut Actions, error: Error) {
+        if self.rx.take().is_some() {
+            actions.push(Action::Event(Event::ReceiveAborted(error)));
+        }
+    }
+}
+
+#[inline]
+fn millis(value: u32) -> Duration {
+    Duration::from_millis(value as u64)
+}
@@ -0,0 +1,405 @@
+use alloc::{format, vec, vec::Vec};
+
+use crate::constants::{CONSECUTIVE_SEQUENCE_START, MAX_LENGTH_2004, MAX_LENGTH_2016};
+use crate::core::Version;
+use crate::error::Error;
+use crate::frame::{Frame, FrameRef, FrameType, SegmentConfig};
+
+/// The data length of classic CAN frame.
+pub(crate) const MAX_FRAME_SIZE: usize = 8;
+/// The max data length of CAN FD frame.
+pub(crate) const MAX_FD_FRAME_SIZE: usize = 64;
+pub(crate) const DEFAULT_PADDING: u8 = 0xAA;
+/// The valid data length(TX_DL and RX_DL) of CAN frame, the first one is classic CAN.
+pub(crate) const CAN_DATA_LENGTHS: [usize; 8] = [MAX_FRAME_SIZE, 12, 16, 20, 24, 32, 48, MAX_FD_FRAME_SIZE];
+
+const SINGLE_FRAME_SIZE_2004: usize = MAX_FRAME_SIZE - 1;
+
+/// The max data length of single frame, the escape sequence is used when TX_DL is greater than 8.
+#[inline]
+pub(crate) fn single_frame_size(tx_dl: usize) -> usize {
+    if tx_dl > MAX_FRAME_SIZE { tx_dl - 2 } else { tx_dl - 1 }
+}
+
+/// The data length of first frame, the escape sequence is used when the PDU is larger than 4095 bytes.
+#[inline]
+fn first_frame_size(tx_dl: usize, escape: bool) -> usize {
+    if escape { tx_dl - 6 } else { tx_dl - 2 }
+}
+
+/// The max data length of consecutive frame.
+#[inline]
+fn consecutive_frame_size(tx_dl: usize) -> usize {
+    tx_dl - 1
+}
+
+/// The valid data length of CAN(FD) frame that can hold `length` bytes, at least 8 bytes.
+#[inline]
+fn can_dl(length: usize) -> Option<usize> {
+    CAN_DATA_LENGTHS.into_iter()
+        .find(|dl| *dl >= length)
+}
+
+/// Decode the single frame, both the 2004 form(SF_DL in byte0)
+/// and the 2016 escape sequence(SF_DL in byte1) are accepted.
+pub(crate) fn decode_single<'a>(
+    data: &'a [u8],
+    byte0: u8,
+    length: usize,
+    offset: usize,
+) -> Result<FrameRef<'a>, Error> {
+    if length + offset > MAX_FD_FRAME_SIZE {
+        return Err(Error::LengthOutOfRange(length + offset));
+    }
+
+    let pdu_len = (byte0 & 0x0F) as usize;
+    if pdu_len > 0 {
+        if length < pdu_len + 1 {
+            return Err(Error::InvalidPdu(Vec::from(data)));
+        }
+
+        Ok(FrameRef::SingleFrame { data: &data[1..=pdu_len] })
+    }
+    else {
+        let pdu_len = data[1] as usize;
+        if pdu_len == 0 || length < pdu_len + 2 {
+            return Err(Error::InvalidPdu(Vec::from(data)));
+        }
+
+        Ok(FrameRef::SingleFrame { data: &data[2..pdu_len + 2] })
+    }
+}
+
+/// Decode the first frame, both the 2004 form(12bit FF_DL)
+/// and the 2016 escape sequence(32bit FF_DL) are accepted.
+///
+/// The FF_DL not larger than the max SF_DL of the frame's TX_DL is invalid.
+pub(crate) fn decode_first<'a>(
+    data: &'a [u8],
+    byte0: u8,
+    length: usize,
+    offset: usize,
+) -> Result<FrameRef<'a>, Error> {
+    // the first frame is not padded, so the RX_DL of the sender is its length.
+    let dl = length + offset;
+    if !CAN_DATA_LENGTHS.contains(&dl) {
+        return Err(Error::InvalidDataLength { actual: dl, expect: can_dl(dl).unwrap_or(MAX_FD_FRAME_SIZE) })
+    }
+
+    let pdu_len = (byte0 as u32 & 0x0F) << 8 | data[1] as u32;
+    if pdu_len > 0 {
+        // the PDU fits in a single frame of the same TX_DL is ignored, it also rejects
+        // the FF_DL smaller than the data carried by the first frame.
+        let capacity = single_frame_size(dl) - offset;
+        if pdu_len as usize <= capacity {
+            return Err(Error::InvalidDataLength { actual: pdu_len as usize, expect: capacity });
+        }
+
+        Ok(FrameRef::FirstFrame { length: pdu_len, data: &data[2..] })
+    }
+    else {
+        if length < 6 {
+            return Err(Error::InvalidPdu(Vec::from(data)));
+        }
+
+        let pdu_len = u32::from_be_bytes([data[2], data[3], data[4], data[5]]);
+        // the escape sequence is only valid for the PDU larger than 4095 bytes.
+        if pdu_len as usize <= MAX_LENGTH_2004 {
+            return Err(Error::InvalidPdu(Vec::from(data)));
+        }
+
+        Ok(FrameRef::FirstFrame { length: pdu_len, data: &data[6..] })
+    }
+}
+
+pub(crate) fn encode_single(mut data: Vec<u8>, ext: Option<u8>, padding: Option<u8>) -> Vec<u8> {
+    let length = data.len();
+    let mut result: Vec<_> = ext.into_iter().collect();
+    if length + result.len() < MAX_FRAME_SIZE {
+        result.push(FrameType::Single as u8 | length as u8);
+    }
+    else {
+        result.extend([FrameType::Single as u8, length as u8]);
+    }
+    result.append(&mut data);
+    pad_frame(&mut result, padding);
+
+    result
+}
+
+/// Pad the frame data to the next valid CAN(FD) data length, at least 8 bytes.
+pub(crate) fn pad_frame(data: &mut Vec<u8>, padding: Option<u8>) {
+    if let Some(resize) = can_dl(data.len()) {
+        data.resize(resize, padding.unwrap_or(DEFAULT_PADDING));
+    }
+}
+
+pub(crate) fn encode_first(length: u32, mut data: Vec<u8>, ext: Option<u8>) -> Vec<u8> {
+    let mut result: Vec<_> = ext.into_iter().collect();
+    if length as usize > MAX_LENGTH_2004 {
+        result.extend([FrameType::First as u8, 0x00]);
+        result.extend(length.to_be_bytes());
+    }
+    else {
+        let len_h = ((length & 0x0F00) >> 8) as u8;
+        let len_l = (length & 0x00FF) as u8;
+        result.extend([FrameType::First as u8 | len_h, len_l]);
+    }
+    result.append(&mut data);
+    result
+}
+
+pub fn new_single<T: AsRef<[u8]>>(data: T) -> Result<Frame, Error> {
+    let data = data.as_ref();
+    let length = data.len();
+    match length {
+        0 => Err(Error::EmptyPdu),
+        1..=SINGLE_FRAME_SIZE_2004 => {
+            let mut result = vec![FrameType::Single as u8 | length as u8];
+            result.append(&mut data.to_vec());
+            result.resize(SINGLE_FRAME_SIZE_2004, DEFAULT_PADDING);
+            Ok(Frame::SingleFrame { data: result })
+        },
+        v => Err(Error::LengthOutOfRange(v)),
+    }
+}
+
+pub fn from_data(data: &[u8], ext_len: usize, version: Version, tx_dl: usize) -> Result<Vec<Frame>, Error> {
+    if !CAN_DATA_LENGTHS.contains(&tx_dl) {
+        return Err(Error::InvalidParam(format!("`tx_dl`({})", tx_dl)));
+    }
+
+    let length = data.len();
+    let max_length = match version {
+        Version::Std2004 => MAX_LENGTH_2004,
+        Version::Std2016 => MAX_LENGTH_2016,
+    };
+    match length {
+        0 => Err(Error::EmptyPdu),
+        v if v <= single_frame_size(tx_dl) - ext_len => Ok(vec![Frame::SingleFrame { data: Vec::from(data) }]),
+        v if v <= max_length => {
+            let mut offset = 0;
+            let mut sequence = 1;
+            let mut results = Vec::new();
+
+            parse_frame_util(
+                data,
+                &mut offset,
+                &mut sequence,
+                &mut results,
+                length,
+                first_frame_size(tx_dl, v > MAX_LENGTH_2004) - ext_len,
+                consecutive_frame_size(tx_dl) - ext_len,
+            );
+
+            Ok(results)
+        },
+        v => Err(Error::LengthOutOfRange(v)),
+    }
+}
+
+fn parse_frame_util(
+    data: &[u8],
+    offset: &mut usize,
+    sequence: &mut u8,
+    results: &mut Vec<Frame>,
+    length: usize,
+    first_size: usize,
+    consecutive_size: usize,
+) {
+    loop {
+        match *offset {
+            0 => {
+                *offset += first_size;
+                let frame = Frame::FirstFrame {
+                    length: length as u32,
+                    data: Vec::from(&data[..*offset])
+                };
+                results.push(frame);
+            },
+            _ => {
+                if *offset + consecutive_size >= length {
+                    let frame = Frame::ConsecutiveFrame {
+                        sequence: *sequence,
+                        data: Vec::from(&data[*offset..length])
+                    };
+                    results.push(frame);
+                    break;
+                }
+
+                let frame = Frame::ConsecutiveFrame {
+                    sequence: *sequence,
+                    data: Vec::from(&data[*offset..*offset + consecutive_size])
+                };
+                *offset += consecutive_size;
+                if *sequence >= 0x0F {
+                    *sequence = 0;
+                }
+                else {
+                    *sequence += 1;
+                }
+
+                results.push(frame);
+            }
+        }
+    }
+}
+
+/// The state of segmenting one PDU, the data is passed by the caller each time,
+/// so the owner of data(e.g. [`crate::IsoTpMachine`]) can keep it without borrowing.
+#[derive(Debug, Clone)]
+pub(crate) struct Segmenter {
+    config: SegmentConfig,
+    length: usize,
+    /// The length of data encoded.
+    offset: usize,
+    sequence: u8,
+    single: bool,
+    first_size: usize,
+    consecutive_size: usize,
+}
+
+impl Segmenter {
+    pub(crate) fn new(length: usize, config: SegmentConfig) -> Result<Self, Error> {
+        let tx_dl = config.tx_dl;
+        if !CAN_DATA_LENGTHS.contains(&tx_dl) {
+            return Err(Error::InvalidParam(format!("`tx_dl`({})", tx_dl)));
+        }
+
+        let ext_len = config.ext.map_or(0, |_| 1);
+        let max_length = match config.version {
+            Version::Std2004 => MAX_LENGTH_2004,
+            Version::Std2016 => MAX_LENGTH_2016,
+        };
+        let single = match length {
+            0 => return Err(Error::EmptyPdu),
+            v if v <= single_frame_size(tx_dl) - ext_len => true,
+            v if v <= max_length => false,
+            v => return Err(Error::LengthOutOfRange(v)),
+        };
+
+        Ok(Self {
+            config,
+            length,
+            offset: Default::default(),
+            sequence: CONSECUTIVE_SEQUENCE_START,
+            single,
+            first_size: first_frame_size(tx_dl, length > MAX_LENGTH_2004) - ext_len,
+            consecutive_size: consecutive_frame_size(tx_dl) - ext_len,
+        })
+    }
+
+    #[inline]
+    pub(crate) fn is_finished(&self) -> bool {
+        self.offset >= self.length
+    }
+
+    /// The number of frames not encoded yet.
+    pub(crate) fn remaining(&self) -> usize {
+        match self.offset {
+            _ if self.is_finished() => 0,
+            0 if self.single => 1,
+            0 => 1 + (self.length - self.first_size).div_ceil(self.consecutive_size),
+            v => (self.length - v).div_ceil(self.consecutive_size),
+        }
+    }
+
+    /// The length of PDU.
+    #[inline]
+    pub(crate) fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Whether the first frame(or single frame) is encoded.
+    #[inline]
+    pub(crate) fn is_started(&self) -> bool {
+        self.offset > 0
+    }
+
+    /// The length of data carried by the next frame, 0 if all frames are encoded.
+    pub(crate) fn chunk_len(&self) -> usize {
+        match self.offset {
+            _ if self.is_finished() => 0,
+            0 if self.single => self.length,
+            0 => self.first_size,
+            v => self.consecutive_size.min(self.length - v),
+        }
+    }
+
+    /// Encode the next frame into `buffer`, the same as the frames of [`from_data`] encoded by [`Frame::encode_with_ext`].
+    ///
+    /// # Returns
+    ///
+    /// The length of frame, `None` if all frames are encoded.
+    pub(crate) fn next_into(&mut self, data: &[u8], buffer: &mut [u8]) -> Option<Result<usize, Error>> {
+        if self.is_finished() {
+            return None;
+        }
+        if data.len() != self.length {
+            return Some(Err(Error::InvalidDataLength { actual: data.len(), expect: self.length }));
+        }
+
+        let chunk = &data[self.offset..self.offset + self.chunk_len()];
+        Some(self.encode(chunk, buffer))
+    }
+
+    /// Encode the next frame with the data it carries, see [`Segmenter::chunk_len`].
+    pub(crate) fn encode(&mut self, data: &[u8], buffer: &mut [u8]) -> Result<usize, Error> {
+        let chunk = self.chunk_len();
+        if data.len() != chunk {
+            return Err(Error::InvalidDataLength { actual: data.len(), expect: chunk });
+        }
+
+        // the address information and N_PCI.
+        let mut header = [0; 7];
+        let mut len = 0;
+        if let Some(ext) = self.config.ext {
+            header[len] = ext;
+            len += 1;
+        }
+        if self.single {
+            if self.length + len < MAX_FRAME_SIZE {
+                header[len] = FrameType::Single as u8 | self.length as u8;
+                len += 1;
+            }
+            else {
+                header[len..len + 2].copy_from_slice(&[FrameType::Single as u8, self.length as u8]);
+                len += 2;
+            }
+        }
+        else if self.offset == 0 {
+            if self.length > MAX_LENGTH_2004 {
+                header[len] = FrameType::First as u8;
+                header[len + 2..len + 6].copy_from_slice(&(self.length as u32).to_be_bytes());
+                len += 6;
+            }
+            else {
+                header[len] = FrameType::First as u8 | ((self.length & 0x0F00) >> 8) as u8;
+                header[len + 1] = (self.length & 0x00FF) as u8;
+                len += 2;
+            }
+        }
+        else {
+            header[len] = FrameType::Consecutive as u8 | self.sequence;
+            len += 1;
+        }
+
+        let used = len + chunk;
+        // the first frame is always full.
+        let size = can_dl(used).unwrap_or(used);
+        if buffer.len() < size {
+            return Err(Error::InvalidDataLength { actual: buffer.len(), expect: size });
+        }
+
+        buffer[..len].copy_from_slice(&header[..len]);
+        buffer[len..used].copy_from_slice(data);
+        buffer[used..size].fill(self.config.padding.unwrap_or(DEFAULT_PADDING));
+
+        if !self.single && self.offset != 0 {
+            self.sequence = (self.sequence + 1) & 0x0F;
+        }
+        self.offset += chunk;
+
+        Ok(size)
+    }
+}
@@ -0,0 +1,295 @@
+mod common;
+
+use std::{any::Any, sync::{Arc, Mutex, atomic::{AtomicBool, AtomicUsize, Ordering}}, thread, time::Duration};
+use iso15765_2::*;
+use rs_can::{CanDevice, CanError, CanFrame, CanId, CanListener, CanResult};
+
+use common::*;
+
+type Adapter<D> = CanAdapter<D, String, MockFrame>;
+
+fn setup<D>(device: D) -> (Adapter<D>, CanIsoTp<String, MockFrame>)
+where
+    D: CanDevice<Channel = String, Frame = MockFrame> + Clone + Send + 'static,
+{
+    let adapter = CanAdapter::new(device);
+    let iso_tp = CanIsoTp::new(
+        CHANNEL.to_string(),
+        Address::normal(0x7E0, 0x7E8, 0x7DF),
+        adapter.sender(),
+        Box::new(NullListener),
+    );
+    adapter.register_listener("iso-tp".into(), Box::new(iso_tp.clone()));
+
+    (adapter, iso_tp)
+}
+
+/// The device fails to transmit every frame.
+#[derive(Clone)]
+struct BrokenDevice(MockDevice);
+
+impl CanDevice for BrokenDevice {
+    type Channel = String;
+    type Frame = MockFrame;
+
+    fn is_closed(&self) -> bool { self.0.is_closed() }
+    fn opened_channels(&self) -> Vec<Self::Channel> { self.0.opened_channels() }
+    fn transmit(&self, _: Self::Frame, _: Option<u32>) -> CanResult<(), CanError> {
+        Err(CanError::OperationError("bus off".into()))
+    }
+    fn receive(&self, channel: Self::Channel, timeout: Option<u32>) -> CanResult<Vec<Self::Frame>, CanError> {
+        self.0.receive(channel, timeout)
+    }
+    fn shutdown(&mut self) { self.0.shutdown() }
+}
+
+/// The device counts the calls of receive, it waits for the timeout if `blocking`.
+#[derive(Clone)]
+struct CountingDevice {
+    inner: MockDevice,
+    blocking: bool,
+    calls: Arc<AtomicUsize>,
+}
+
+impl CanDevice for CountingDevice {
+    type Channel = String;
+    type Frame = MockFrame;
+
+    fn is_closed(&self) -> bool { self.inner.is_closed() }
+    fn opened_channels(&self) -> Vec<Self::Channel> { self.inner.opened_channels() }
+    fn transmit(&self, msg: Self::Frame, timeout: Option<u32>) -> CanResult<(), CanError> {
+        self.inner.transmit(msg, timeout)
+    }
+    fn receive(&self, channel: Self::Channel, timeout: Option<u32>) -> CanResult<Vec<Self::Frame>, CanError> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        let frames = self.inner.receive(channel, timeout)?;
+        if let (true, true, Some(timeout)) = (self.blocking, frames.is_empty(), timeout) {
+            thread::sleep(Duration::from_millis(timeout as u64));
+        }
+        Ok(frames)
+    }
+    fn shutdown(&mut self) { self.inner.shutdown() }
+}
+
+/// The device blocks in receive while `hold` is set, and records whether it's shut down.
+#[derive(Clone)]
+struct StuckDevice {
+    inner: MockDevice,
+    hold: Arc<AtomicBool>,
+    closed: Arc<AtomicBool>,
+}
+
+impl CanDevice for StuckDevice {
+    type Channel = String;
+    type Frame = MockFrame;
+
+    fn is_closed(&self) -> bool { self.closed.load(Ordering::Acquire) }
+    fn opened_channels(&self) -> Vec<Self::Channel> { self.inner.opened_channels() }
+    fn transmit(&self, msg: Self::Frame, timeout: Option<u32>) -> CanResult<(), CanError> {
+        self.inner.transmit(msg, timeout)
+    }
+    fn receive(&self, channel: Self::Channel, timeout: Option<u32>) -> CanResult<Vec<Self::Frame>, CanError> {
+        while self.hold.load(Ordering::Acquire) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        self.inner.receive(channel, timeout)
+    }
+    fn shutdown(&mut self) { self.closed.store(true, Ordering::Release) }
+}
+
+/// Record the name of listener and CAN-ID of the frames received in notification order.
+#[derive(Clone)]
+struct Recorder {
+    name: &'static str,
+    log: Arc<Mutex<Vec<(&'static str, u32)>>>,
+}
+
+impl CanListener<String, MockFrame> for Recorder {
+    fn as_any(&self) -> &dyn Any { self }
+    fn on_frame_transmitting(&self, _: String, _: &MockFrame) {}
+    fn on_frame_transmitted(&self, _: String, _: CanId) {}
+    fn on_frame_received(&self, _: String, frames: &[MockFrame]) {
+        let mut log = self.log.lock().unwrap();
+        frames.iter()
+            .for_each(|f| log.push((self.name, f.id().into_bits())));
+    }
+}
+
+#[test]
+fn test_restart() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E8, |frame| match frame.data[0] {
+        0x02 => vec![hex::decode("025001AAAAAAAAAA").unwrap()],
+        _ => vec![],
+    });
+    let (mut adapter, iso_tp) = setup(device.clone());
+    assert!(!adapter.is_running());
+
+    for _ in 0..2 {
+        adapter.start(100)?;
+        assert!(adapter.is_running());
+        let ret = adapter.start(100);
+        assert!(matches!(ret, Err(IsoTpError::AdapterError(_))));
+
+        let response = iso_tp.transceive(AddressType::Physical, hex::decode("1001")?, 1000)?;
+        assert_eq!(response, hex::decode("5001")?);
+
+        // both loops are joined.
+        adapter.pause()?;
+        assert!(!adapter.is_running());
+    }
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_device_error() -> anyhow::Result<()> {
+    let (mut adapter, iso_tp) = setup(BrokenDevice(MockDevice::new(0x7E8, |_| vec![])));
+    iso_tp.set_timing(TimingConfig { n_as: 50, ..Default::default() });
+    adapter.start(100)?;
+
+    let ret = iso_tp.write(AddressType::Physical, hex::decode("1001")?);
+    assert!(matches!(ret, Err(IsoTpError::Timeout { timer: IsoTpTimer::NAs, .. })));
+
+    // the first transmit failure is returned when stopping.
+    let ret = adapter.pause();
+    assert!(matches!(ret, Err(IsoTpError::AdapterError(ref e)) if e.contains("bus off")));
+    // the error is cleared by restarting.
+    adapter.start(100)?;
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_stuck_worker() -> anyhow::Result<()> {
+    let device = StuckDevice {
+        inner: MockDevice::new(0x7E8, |_| vec![]),
+        hold: Default::default(),
+        closed: Default::default(),
+    };
+    let (mut adapter, _) = setup(device.clone());
+    adapter.start(100)?;
+    device.hold.store(true, Ordering::Release);
+
+    // the receive worker doesn't exit in time, it's kept and the adapter can't be restarted.
+    let ret = adapter.pause();
+    assert!(matches!(ret, Err(IsoTpError::AdapterError(ref e)) if e.contains("receive")));
+    assert!(adapter.is_running());
+    assert!(matches!(adapter.start(100), Err(IsoTpError::AdapterError(_))));
+
+    // it's joined after exiting.
+    device.hold.store(false, Ordering::Release);
+    adapter.pause()?;
+    assert!(!adapter.is_running());
+    assert!(!device.is_closed());
+    adapter.start(100)?;
+
+    // the device is shut down by stop.
+    adapter.stop()?;
+    assert!(device.is_closed());
+    Ok(())
+}
+
+#[test]
+fn test_blocking_receive() -> anyhow::Result<()> {
+    let idle_calls = |blocking: bool| -> anyhow::Result<usize> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let device = CountingDevice { inner: MockDevice::new(0x7E8, |_| vec![]), blocking, calls: Arc::clone(&calls) };
+        let (mut adapter, _) = setup(device);
+        adapter.start(1000)?;
+        thread::sleep(Duration::from_millis(200));
+        adapter.stop()?;
+        Ok(calls.load(Ordering::Relaxed))
+    };
+
+    // the idle device is waited instead of polled every 1ms.
+    let blocking = idle_calls(true)?;
+    assert!(blocking <= 25, "{} calls", blocking);
+    // the device can't block is polled.
+    let polling = idle_calls(false)?;
+    assert!(polling >= 50, "{} calls", polling);
+
+    Ok(())
+}
+
+#[test]
+fn test_listener_filter() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E8, |_| vec![]);
+    let mut adapter = CanAdapter::new(device.clone());
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let recorder = |name| Box::new(Recorder { name, log: Arc::clone(&log) });
+    adapter.register_listener_with("exact".into(), recorder("exact"), vec![ListenerFilter::exact(0x7E8)], 0);
+    adapter.register_listener("all".into(), recorder("all"));
+    adapter.register_listener_with("masked".into(), recorder("masked"), vec![ListenerFilter::new(0x700, 0x700)], 10);
+    assert_eq!(adapter.listener_names(), vec!["masked", "exact", "all"]);
+    adapter.start(100)?;
+
+    let received = |ids: &[u32]| {
+        ids.iter().for_each(|id| device.inject_with_id(*id, hex::decode("0100").unwrap()));
+        thread::sleep(Duration::from_millis(50));
+        std::mem::take(&mut *log.lock().unwrap())
+    };
+
+    // the listeners are notified in priority order with the frames accepted.
+    assert_eq!(received(&[0x7E8, 0x123, 0x701]), vec![
+        ("masked", 0x7E8), ("masked", 0x701),
+        ("exact", 0x7E8),
+        ("all", 0x7E8), ("all", 0x123), ("all", 0x701),
+    ]);
+
+    // the listeners can be changed while running.
+    adapter.unregister_listener("masked");
+    adapter.register_listener_with("first".into(), recorder("first"), vec![ListenerFilter::exact(0x123)], 1);
+    assert_eq!(received(&[0x123, 0x7E8]), vec![
+        ("first", 0x123),
+        ("exact", 0x7E8),
+        ("all", 0x123), ("all", 0x7E8),
+    ]);
+
+    adapter.stop()?;
+    Ok(())
+}
+
+/// Count the notifications of listener without frames and the frames received.
+#[derive(Clone, Default)]
+struct Ticker {
+    ticks: Arc<AtomicUsize>,
+    frames: Arc<AtomicUsize>,
+}
+
+impl CanListener<String, MockFrame> for Ticker {
+    fn as_any(&self) -> &dyn Any { self }
+    fn on_frame_transmitting(&self, _: String, _: &MockFrame) {}
+    fn on_frame_transmitted(&self, _: String, _: CanId) {}
+    fn on_frame_received(&self, _: String, frames: &[MockFrame]) {
+        match frames.len() {
+            0 => self.ticks.fetch_add(1, Ordering::Relaxed),
+            n => self.frames.fetch_add(n, Ordering::Relaxed),
+        };
+    }
+}
+
+#[test]
+fn test_listener_tick() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E8, |_| vec![]);
+    let mut adapter = CanAdapter::new(device.clone());
+    let ticker = Ticker::default();
+    adapter.register_listener_with("ticker".into(), Box::new(ticker.clone()), vec![ListenerFilter::exact(0x7E8)], 0);
+    adapter.start(100)?;
+
+    // the frames not accepted are not passed, the listener is ticked instead at most every millisecond.
+    let start = std::time::Instant::now();
+    (0..100).for_each(|_| {
+        device.inject_with_id(0x123, hex::decode("0100").unwrap());
+        thread::sleep(Duration::from_millis(2));
+    });
+    device.inject_with_id(0x7E8, hex::decode("0100").unwrap());
+    thread::sleep(Duration::from_millis(50));
+    adapter.stop()?;
+
+    let ticks = ticker.ticks.load(Ordering::Relaxed);
+    assert_eq!(ticker.frames.load(Ordering::Relaxed), 1);
+    assert!(ticks > 0);
+    assert!(ticks <= start.elapsed().as_millis() as usize + 1, "ticked {} times", ticks);
+    Ok(())
+}
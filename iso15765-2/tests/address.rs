@@ -0,0 +1,141 @@
+use iso15765_2::*;
+
+fn round_trip(address: &Address, addr_type: AddressType, source: &[u8]) -> anyhow::Result<Vec<Vec<u8>>> {
+    let offset = address.format.ext_len();
+    let frames = IsoTpFrame::from_data_with_offset(source, offset)?;
+
+    let mut encoded = Vec::new();
+    let mut result = Vec::new();
+    for frame in frames {
+        let data = frame.encode_with_ext(address.tx_ext(addr_type), None);
+        assert!(data.len() <= 8);
+
+        match IsoTpFrame::decode_with_offset(&data, offset)? {
+            IsoTpFrame::SingleFrame { data } => result.extend(data),
+            IsoTpFrame::FirstFrame { length, data } => {
+                assert_eq!(length as usize, source.len());
+                result.extend(data);
+            },
+            IsoTpFrame::ConsecutiveFrame { data, .. } => result.extend(data),
+            IsoTpFrame::FlowControlFrame(_) => panic!("Wrong frame type"),
+        }
+        encoded.push(data);
+    }
+    result.truncate(source.len());
+    assert_eq!(result, source);
+
+    Ok(encoded)
+}
+
+#[test]
+fn test_normal() -> anyhow::Result<()> {
+    let address = Address::normal(0x7E0, 0x7E8, 0x7DF);
+    assert_eq!(address.tx_ext(AddressType::Physical), None);
+    assert!(address.is_rx_frame(0x7E8, &hex::decode("0250010000000000")?));
+    assert!(!address.is_rx_frame(0x7E9, &hex::decode("0250010000000000")?));
+
+    let frames = round_trip(&address, AddressType::Physical, &hex::decode("1001")?)?;
+    assert_eq!(frames, vec![hex::decode("021001AAAAAAAAAA")?]);
+
+    let source = hex::decode("36010102030405060708090A0B0C")?;
+    let frames = round_trip(&address, AddressType::Physical, &source)?;
+    assert_eq!(frames, vec![
+        hex::decode("100E360101020304")?,
+        hex::decode("2105060708090A0B")?,
+        hex::decode("220CAAAAAAAAAAAA")?,
+    ]);
+
+    Ok(())
+}
+
+#[test]
+fn test_normal_fixed() -> anyhow::Result<()> {
+    let address = Address::normal_fixed(0xF1, 0x10, 0x33);
+    assert_eq!(address.tx_id, 0x18DA10F1);
+    assert_eq!(address.rx_id, 0x18DAF110);
+    assert_eq!(address.fid, 0x18DB33F1);
+    assert_eq!(address.tx_can_id(AddressType::Functional), 0x18DB33F1);
+    assert_eq!(address.tx_ext(AddressType::Physical), None);
+    assert!(address.is_rx_frame(0x18DAF110, &hex::decode("0250010000000000")?));
+
+    let frames = round_trip(&address, AddressType::Functional, &hex::decode("3E00")?)?;
+    assert_eq!(frames, vec![hex::decode("023E00AAAAAAAAAA")?]);
+
+    let source = hex::decode("36010102030405060708090A0B0C")?;
+    let frames = round_trip(&address, AddressType::Physical, &source)?;
+    assert_eq!(frames.len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_extended() -> anyhow::Result<()> {
+    let address = Address::extended(0x6F1, 0x610, 0x6F1, 0xF1, 0x10, 0xDF);
+    assert_eq!(address.tx_ext(AddressType::Physical), Some(0x10));
+    assert_eq!(address.tx_ext(AddressType::Functional), Some(0xDF));
+    assert_eq!(address.rx_ext(), Some(0xF1));
+    assert!(address.is_rx_frame(0x610, &hex::decode("F102500100000000")?));
+    assert!(!address.is_rx_frame(0x610, &hex::decode("F202500100000000")?));
+
+    let frames = round_trip(&address, AddressType::Physical, &hex::decode("1001")?)?;
+    assert_eq!(frames, vec![hex::decode("10021001AAAAAAAA")?]);
+
+    let frames = round_trip(&address, AddressType::Functional, &hex::decode("3E80")?)?;
+    assert_eq!(frames, vec![hex::decode("DF023E80AAAAAAAA")?]);
+
+    let source = hex::decode("36010102030405060708090A0B0C")?;
+    let frames = round_trip(&address, AddressType::Physical, &source)?;
+    assert_eq!(frames, vec![
+        hex::decode("10100E3601010203")?,
+        hex::decode("1021040506070809")?,
+        hex::decode("10220A0B0CAAAAAA")?,
+    ]);
+
+    // the longest single frame is one byte shorter than normal addressing.
+    let frames = round_trip(&address, AddressType::Physical, &hex::decode("010203040506")?)?;
+    assert_eq!(frames, vec![hex::decode("1006010203040506")?]);
+    let frames = round_trip(&address, AddressType::Physical, &hex::decode("01020304050607")?)?;
+    assert_eq!(frames.len(), 2);
+
+    let frame = IsoTpFrame::default_flow_ctrl_frame();
+    let data = frame.encode_with_ext(address.tx_ext(AddressType::Physical), None);
+    assert_eq!(data, hex::decode("1030000AAAAAAAAA")?);
+    match IsoTpFrame::decode_with_offset(&data, 1)? {
+        IsoTpFrame::FlowControlFrame(ctx) => {
+            assert_eq!(ctx.state(), FlowControlState::Continues);
+            assert_eq!(ctx.st_min(), 0x0A);
+        },
+        _ => panic!("Wrong frame type"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_mixed() -> anyhow::Result<()> {
+    let address = Address::mixed(0x700, 0x708, 0x7DF, 0x5A);
+    assert_eq!(address.tx_ext(AddressType::Physical), Some(0x5A));
+    assert_eq!(address.tx_ext(AddressType::Functional), Some(0x5A));
+    assert!(address.is_rx_frame(0x708, &hex::decode("5A02500100000000")?));
+    assert!(!address.is_rx_frame(0x708, &hex::decode("5B02500100000000")?));
+
+    let frames = round_trip(&address, AddressType::Physical, &hex::decode("1003")?)?;
+    assert_eq!(frames, vec![hex::decode("5A021003AAAAAAAA")?]);
+
+    let source = hex::decode("36010102030405060708090A0B0C")?;
+    round_trip(&address, AddressType::Physical, &source)?;
+
+    let address = Address::mixed_fixed(0xF1, 0x10, 0x33, 0x5A);
+    assert_eq!(address.tx_id, 0x18CE10F1);
+    assert_eq!(address.rx_id, 0x18CEF110);
+    assert_eq!(address.fid, 0x18CD33F1);
+    assert!(address.is_rx_frame(0x18CEF110, &hex::decode("5A02500100000000")?));
+    round_trip(&address, AddressType::Physical, &source)?;
+
+    // the server receives the functional requests of the tester.
+    let address = Address::mixed_fixed_server(0x10, 0xF1, 0x33, 0x5A);
+    assert_eq!((address.tx_id, address.rx_id, address.fid), (0x18CEF110, 0x18CE10F1, 0x18CD33F1));
+    assert!(address.is_func_rx_frame(0x18CD33F1, &hex::decode("5A023E8000000000")?));
+
+    Ok(())
+}
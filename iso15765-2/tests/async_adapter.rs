@@ -0,0 +1,207 @@
+mod common;
+
+use std::time::Duration;
+use iso15765_2::*;
+use rs_can::{CanDevice, CanError, CanResult};
+use tokio_stream::StreamExt;
+
+use common::*;
+
+/// The device fails to transmit every frame.
+#[derive(Clone)]
+struct BrokenDevice(MockDevice);
+
+impl CanDevice for BrokenDevice {
+    type Channel = String;
+    type Frame = MockFrame;
+
+    fn is_closed(&self) -> bool { self.0.is_closed() }
+    fn opened_channels(&self) -> Vec<Self::Channel> { self.0.opened_channels() }
+    fn transmit(&self, _: Self::Frame, _: Option<u32>) -> CanResult<(), CanError> {
+        Err(CanError::OperationError("bus off".into()))
+    }
+    fn receive(&self, channel: Self::Channel, timeout: Option<u32>) -> CanResult<Vec<Self::Frame>, CanError> {
+        self.0.receive(channel, timeout)
+    }
+    fn shutdown(&mut self) { self.0.shutdown() }
+}
+
+type Adapter<D> = AsyncCanAdapter<D, String, MockFrame>;
+
+fn setup<D>(device: D) -> anyhow::Result<(Adapter<D>, CanIsoTp<String, MockFrame>)>
+where
+    D: CanDevice<Channel = String, Frame = MockFrame> + Clone + Send + Sync + 'static,
+{
+    let mut adapter = AsyncCanAdapter::new(device);
+    let iso_tp = CanIsoTp::new(
+        CHANNEL.to_string(),
+        Address::normal(0x7E0, 0x7E8, 0x7DF),
+        adapter.sender(),
+        Box::new(NullListener),
+    );
+    adapter.register_listener("iso-tp".into(), Box::new(iso_tp.clone()));
+    adapter.start(100)?;
+
+    Ok((adapter, iso_tp))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_write_single() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E8, |_| vec![]);
+    let (mut adapter, iso_tp) = setup(device.clone())?;
+
+    iso_tp.write_async(AddressType::Functional, hex::decode("3E80")?).await?;
+
+    let transmitted = device.transmitted.lock().unwrap().clone();
+    assert_eq!(transmitted.len(), 1);
+    assert_eq!(transmitted[0].id.into_bits(), 0x7DF);
+    assert_eq!(transmitted[0].data, hex::decode("023E80AAAAAAAAAA")?);
+
+    adapter.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_write_multi() -> anyhow::Result<()> {
+    // the ECU answers the first frame with block size 2.
+    let device = MockDevice::new(0x7E8, |frame| {
+        match frame.data[0] & 0xF0 {
+            0x10 => vec![hex::decode("30020000AAAAAAAA").unwrap()],
+            0x20 if frame.data[0] == 0x22 => vec![hex::decode("30000000AAAAAAAA").unwrap()],
+            _ => vec![],
+        }
+    });
+    let (mut adapter, iso_tp) = setup(device.clone())?;
+
+    let source = hex::decode("2EF190000102030405060708090A0B0C0D0E0F101112131415")?;
+    iso_tp.write_async(AddressType::Physical, source).await?;
+
+    assert_eq!(device.transmitted(), vec![
+        "10192EF190000102",
+        "2103040506070809",
+        "220A0B0C0D0E0F10",
+        "231112131415AAAA",
+    ]);
+
+    adapter.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_write_timeout() -> anyhow::Result<()> {
+    // the ECU never answers flow control.
+    let device = MockDevice::new(0x7E8, |_| vec![]);
+    let (mut adapter, iso_tp) = setup(device.clone())?;
+    iso_tp.set_timing(TimingConfig { n_bs: 50, ..Default::default() });
+
+    let source = hex::decode("2EF190000102030405060708090A0B0C0D0E0F101112131415")?;
+    match iso_tp.write_async(AddressType::Physical, source).await {
+        Err(IsoTpError::Timeout { timer, .. }) => assert_eq!(timer, IsoTpTimer::NBs),
+        v => panic!("unexpected result: {:?}", v),
+    }
+
+    adapter.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_stream() -> anyhow::Result<()> {
+    // the ECU sends the consecutive frames after the flow control.
+    let device = MockDevice::new(0x7E8, |frame| {
+        match frame.data[0] & 0xF0 {
+            0x30 => vec![
+                hex::decode("21F1900102030405").unwrap(),
+                hex::decode("2206070809AAAAAA").unwrap(),
+            ],
+            _ => vec![],
+        }
+    });
+    let (mut adapter, iso_tp) = setup(device.clone())?;
+    let mut stream = iso_tp.stream();
+
+    device.inject(hex::decode("025001AAAAAAAAAA")?);
+    let pdu = tokio::time::timeout(Duration::from_secs(1), stream.next()).await?;
+    assert_eq!(pdu.unwrap()?, hex::decode("5001")?);
+
+    device.inject(hex::decode("10106EF190414243")?);
+    let pdu = tokio::time::timeout(Duration::from_secs(1), stream.recv()).await?;
+    assert_eq!(pdu.unwrap()?, hex::decode("6EF190414243F1900102030405060708")?);
+
+    let transmitted = device.transmitted.lock().unwrap().clone();
+    assert_eq!(transmitted.len(), 1);
+    assert_eq!(transmitted[0].id.into_bits(), 0x7E0);
+    assert_eq!(transmitted[0].data, hex::decode("30000AAAAAAAAAAA")?);
+
+    adapter.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_blocking_device() -> anyhow::Result<()> {
+    let device = BlockingDevice::new(MockDevice::new(0x7E8, |frame| match frame.data[0] {
+        0x02 => vec![hex::decode("025001AAAAAAAAAA").unwrap()],
+        _ => vec![],
+    }));
+    let (mut adapter, iso_tp) = setup(device.clone())?;
+    let mut stream = iso_tp.stream();
+
+    // the blocking device doesn't stall the only runtime thread.
+    let started = tokio::time::Instant::now();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(started.elapsed() < Duration::from_millis(300));
+    // the idle device is waited instead of polled every 100us.
+    let idle = device.calls();
+    assert!(idle <= 25, "{} calls", idle);
+
+    iso_tp.write_async(AddressType::Physical, hex::decode("1001")?).await?;
+    let pdu = tokio::time::timeout(Duration::from_secs(1), stream.next()).await?;
+    assert_eq!(pdu.unwrap()?, hex::decode("5001")?);
+
+    adapter.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_restart() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E8, |frame| match frame.data[0] {
+        0x02 => vec![hex::decode("025001AAAAAAAAAA").unwrap()],
+        _ => vec![],
+    });
+    let (mut adapter, iso_tp) = setup(device.clone())?;
+    let mut stream = iso_tp.stream();
+
+    for _ in 0..2 {
+        assert!(adapter.is_running());
+        let ret = adapter.start(100);
+        assert!(matches!(ret, Err(IsoTpError::AdapterError(_))));
+
+        iso_tp.write_async(AddressType::Physical, hex::decode("1001")?).await?;
+        let pdu = tokio::time::timeout(Duration::from_secs(1), stream.next()).await?;
+        assert_eq!(pdu.unwrap()?, hex::decode("5001")?);
+
+        // both tasks are waited, the device is kept open.
+        adapter.pause().await?;
+        assert!(!adapter.is_running());
+        adapter.start(100)?;
+    }
+
+    adapter.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_device_error() -> anyhow::Result<()> {
+    let (mut adapter, iso_tp) = setup(BrokenDevice(MockDevice::new(0x7E8, |_| vec![])))?;
+    iso_tp.set_timing(TimingConfig { n_as: 50, ..Default::default() });
+
+    let ret = iso_tp.write_async(AddressType::Physical, hex::decode("1001")?).await;
+    assert!(matches!(ret, Err(IsoTpError::Timeout { timer: IsoTpTimer::NAs, .. })));
+
+    // the first transmit failure is returned when stopping.
+    let ret = adapter.pause().await;
+    assert!(matches!(ret, Err(IsoTpError::AdapterError(ref e)) if e.contains("bus off")));
+    // the error is cleared by restarting.
+    adapter.start(100)?;
+    adapter.stop().await?;
+    Ok(())
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use iso15765_2::*;
+
+use common::*;
+
+fn encode_all(data: &[u8], tx_dl: usize) -> anyhow::Result<Vec<Vec<u8>>> {
+    Ok(IsoTpFrame::from_data_with_dl(data, 0, IsoTpVersion::Std2016, tx_dl)?
+        .into_iter()
+        .map(|f| f.encode(None))
+        .collect())
+}
+
+#[test]
+fn test_tx_dl() -> anyhow::Result<()> {
+    let source: Vec<_> = (0..100).collect();
+
+    let ret = IsoTpFrame::from_data_with_dl(&source, 0, IsoTpVersion::Std2016, 10);
+    assert!(matches!(ret, Err(IsoTpError::InvalidParam(_))));
+
+    // the escape single frame is padded to the next valid DLC.
+    let frames = encode_all(&source[..20], 64)?;
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].len(), 24);
+    assert_eq!(frames[0][..2], [0x00, 20]);
+    match IsoTpFrame::decode(&frames[0])? {
+        IsoTpFrame::SingleFrame { data } => assert_eq!(data, source[..20]),
+        _ => panic!("Wrong frame type"),
+    }
+
+    // the short single frame uses the 2004 form.
+    let frames = encode_all(&source[..5], 64)?;
+    assert_eq!(frames, vec![hex::decode("050001020304AAAA")?]);
+
+    let frames = encode_all(&source, 64)?;
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].len(), 64);
+    assert_eq!(frames[0][..3], [0x10, 100, 0x00]);
+    // 1 byte N_PCI and 38 bytes data are padded to 48.
+    assert_eq!(frames[1].len(), 48);
+    assert_eq!(frames[1][..2], [0x21, 62]);
+    assert_eq!(frames[1][39..], [0xAA; 9]);
+
+    let frames = encode_all(&source, 20)?;
+    assert_eq!(frames.len(), 1 + (100 - 18_usize).div_ceil(19));
+    assert!(frames[..frames.len() - 1].iter().all(|f| f.len() == 20));
+
+    // the RX_DL is detected from the length of first frame.
+    match IsoTpFrame::decode(&frames[0])? {
+        IsoTpFrame::FirstFrame { length, data } => {
+            assert_eq!(length, 100);
+            assert_eq!(data, source[..18]);
+        },
+        _ => panic!("Wrong frame type"),
+    }
+    assert!(IsoTpFrame::decode(&frames[0][..10]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_can_fd_iso_tp() -> anyhow::Result<()> {
+    let source: Vec<_> = (0..100).collect();
+    let response = encode_all(&source, 64)?;
+    let device = MockDevice::new(0x7E8, move |frame| match frame.data[0] {
+        // the first frame sent by CanIsoTp
+        0x10 => vec![hex::decode("300000").unwrap()],
+        // the single frame request
+        0x02 => vec![response[0].clone()],
+        // FC sent by CanIsoTp
+        0x30 => response[1..].to_vec(),
+        _ => vec![],
+    });
+    let mut adapter = CanAdapter::new(device.clone());
+    let iso_tp = CanIsoTp::new(
+        CHANNEL.to_string(),
+        Address::normal(0x7E0, 0x7E8, 0x7DF),
+        adapter.sender(),
+        Box::new(NullListener),
+    );
+    adapter.register_listener("iso-tp".into(), Box::new(iso_tp.clone()));
+    adapter.start(100)?;
+
+    assert!(iso_tp.set_tx_dl(10).is_err());
+    iso_tp.set_tx_dl(64)?;
+    iso_tp.set_version(IsoTpVersion::Std2016);
+
+    iso_tp.write(AddressType::Physical, source.clone())?;
+    {
+        let transmitted = device.transmitted.lock().unwrap();
+        assert_eq!(transmitted.len(), 2);
+        assert_eq!(transmitted[0].data.len(), 64);
+        assert_eq!(transmitted[1].data.len(), 48);
+        assert!(transmitted.iter().all(|f| f.can_fd));
+    }
+
+    let data = iso_tp.transceive(AddressType::Physical, hex::decode("3E00")?, 1000)?;
+    assert_eq!(data, source);
+    // the flow control frame is sent by CAN FD because the RX_DL is 64.
+    let transmitted = device.transmitted.lock().unwrap().clone();
+    let flow_ctrl = transmitted.last().unwrap();
+    assert_eq!(hex::encode_upper(&flow_ctrl.data), "30000AAAAAAAAAAA");
+    assert!(flow_ctrl.can_fd);
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_rx_dl_exceeded() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E8, |frame| match frame.data[0] {
+        // the single frame request
+        0x02 => vec![hex::decode("1014010203040506").unwrap()],
+        // the consecutive frame is longer than RX_DL(8)
+        0x30 => vec![[vec![0x21], vec![0x55; 15]].concat()],
+        _ => vec![],
+    });
+    let mut adapter = CanAdapter::new(device.clone());
+    let iso_tp = CanIsoTp::new(
+        CHANNEL.to_string(),
+        Address::normal(0x7E0, 0x7E8, 0x7DF),
+        adapter.sender(),
+        Box::new(NullListener),
+    );
+    adapter.register_listener("iso-tp".into(), Box::new(iso_tp.clone()));
+    adapter.start(100)?;
+
+    let ret = iso_tp.transceive(AddressType::Physical, hex::decode("3E00")?, 1000);
+    assert!(matches!(ret, Err(IsoTpError::InvalidDataLength { actual: 16, expect: 8 })));
+
+    adapter.stop()?;
+    Ok(())
+}
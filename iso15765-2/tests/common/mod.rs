@@ -0,0 +1,179 @@
+#![allow(dead_code)]
+
+use std::{collections::VecDeque, fmt::{Display, Formatter}, path::Path, process::Command, sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}}, thread, time::Duration};
+use iso15765_2::*;
+use rs_can::{CanDevice, CanDirect, CanError, CanFrame, CanId, CanResult};
+
+pub const CHANNEL: &str = "can0";
+
+#[derive(Debug, Clone)]
+pub struct MockFrame {
+    pub id: CanId,
+    pub data: Vec<u8>,
+    pub channel: String,
+    pub can_fd: bool,
+}
+
+impl Display for MockFrame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04X}#{}", self.id.into_bits(), hex::encode(&self.data))
+    }
+}
+
+impl CanFrame for MockFrame {
+    type Channel = String;
+
+    fn new(id: impl Into<CanId>, data: &[u8]) -> Option<Self> {
+        Some(Self { id: id.into(), data: data.to_vec(), channel: Default::default(), can_fd: false })
+    }
+    fn new_remote(_: impl Into<CanId>, _: usize) -> Option<Self> { None }
+    fn timestamp(&self) -> u64 { 0 }
+    fn set_timestamp(&mut self, _: Option<u64>) -> &mut Self { self }
+    fn id(&self) -> CanId { self.id }
+    fn is_can_fd(&self) -> bool { self.can_fd }
+    fn set_can_fd(&mut self, value: bool) -> &mut Self { self.can_fd = value; self }
+    fn is_remote(&self) -> bool { false }
+    fn is_extended(&self) -> bool { self.id.is_extended() }
+    fn direct(&self) -> CanDirect { Default::default() }
+    fn set_direct(&mut self, _: CanDirect) -> &mut Self { self }
+    fn is_bitrate_switch(&self) -> bool { false }
+    fn set_bitrate_switch(&mut self, _: bool) -> &mut Self { self }
+    fn is_error_frame(&self) -> bool { false }
+    fn set_error_frame(&mut self, _: bool) -> &mut Self { self }
+    fn is_esi(&self) -> bool { false }
+    fn set_esi(&mut self, _: bool) -> &mut Self { self }
+    fn channel(&self) -> Self::Channel { self.channel.clone() }
+    fn set_channel(&mut self, value: Self::Channel) -> &mut Self { self.channel = value; self }
+    fn data(&self) -> &[u8] { &self.data }
+    fn dlc(&self) -> Option<usize> { Some(self.data.len()) }
+    fn length(&self) -> usize { self.data.len() }
+}
+
+pub type Responder = Box<dyn Fn(&MockFrame) -> Vec<(u32, Vec<u8>)> + Send + Sync>;
+
+/// The mock device answers every transmitted frame by the responder(ECU simulator).
+#[derive(Clone)]
+pub struct MockDevice {
+    pub rx_id: u32,
+    pub transmitted: Arc<Mutex<Vec<MockFrame>>>,
+    pub received: Arc<Mutex<VecDeque<MockFrame>>>,
+    pub responder: Arc<Responder>,
+}
+
+impl MockDevice {
+    pub fn new(rx_id: u32, responder: impl Fn(&MockFrame) -> Vec<Vec<u8>> + Send + Sync + 'static) -> Self {
+        Self::with_ids(rx_id, move |frame| responder(frame).into_iter()
+            .map(|data| (rx_id, data))
+            .collect())
+    }
+
+    /// The responder answers the frames with CAN-ID, used to simulate multiple ECUs.
+    pub fn with_ids(rx_id: u32, responder: impl Fn(&MockFrame) -> Vec<(u32, Vec<u8>)> + Send + Sync + 'static) -> Self {
+        Self {
+            rx_id,
+            transmitted: Default::default(),
+            received: Default::default(),
+            responder: Arc::new(Box::new(responder)),
+        }
+    }
+
+    pub fn transmitted(&self) -> Vec<String> {
+        self.transmitted.lock().unwrap()
+            .iter()
+            .map(|f| hex::encode_upper(&f.data))
+            .collect()
+    }
+
+    pub fn inject(&self, data: Vec<u8>) {
+        self.inject_with_id(self.rx_id, data);
+    }
+
+    pub fn inject_with_id(&self, id: u32, data: Vec<u8>) {
+        let mut frame = MockFrame::new(CanId::from_bits(id, None), &data).unwrap();
+        frame.set_channel(CHANNEL.into());
+        self.received.lock().unwrap().push_back(frame);
+    }
+}
+
+impl CanDevice for MockDevice {
+    type Channel = String;
+    type Frame = MockFrame;
+
+    fn is_closed(&self) -> bool { false }
+    fn opened_channels(&self) -> Vec<Self::Channel> { vec![CHANNEL.into()] }
+    fn transmit(&self, msg: Self::Frame, _: Option<u32>) -> CanResult<(), CanError> {
+        (self.responder)(&msg).into_iter()
+            .for_each(|(id, data)| self.inject_with_id(id, data));
+        self.transmitted.lock().unwrap().push(msg);
+        Ok(())
+    }
+    fn receive(&self, _: Self::Channel, _: Option<u32>) -> CanResult<Vec<Self::Frame>, CanError> {
+        Ok(self.received.lock().unwrap().drain(..).collect())
+    }
+    fn shutdown(&mut self) {}
+}
+
+/// The device waits for the timeout if nothing received like a real device, and counts the calls of receive.
+#[derive(Clone)]
+pub struct BlockingDevice {
+    pub inner: MockDevice,
+    pub calls: Arc<AtomicUsize>,
+}
+
+impl BlockingDevice {
+    pub fn new(inner: MockDevice) -> Self {
+        Self { inner, calls: Default::default() }
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+}
+
+impl CanDevice for BlockingDevice {
+    type Channel = String;
+    type Frame = MockFrame;
+
+    fn is_closed(&self) -> bool { self.inner.is_closed() }
+    fn opened_channels(&self) -> Vec<Self::Channel> { self.inner.opened_channels() }
+    fn transmit(&self, msg: Self::Frame, timeout: Option<u32>) -> CanResult<(), CanError> {
+        self.inner.transmit(msg, timeout)
+    }
+    fn receive(&self, channel: Self::Channel, timeout: Option<u32>) -> CanResult<Vec<Self::Frame>, CanError> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        let frames = self.inner.receive(channel, timeout)?;
+        if let (true, Some(timeout)) = (frames.is_empty(), timeout) {
+            thread::sleep(Duration::from_millis(timeout as u64));
+        }
+        Ok(frames)
+    }
+    fn shutdown(&mut self) { self.inner.shutdown() }
+}
+
+pub struct NullListener;
+
+impl IsoTpEventListener for NullListener {
+    fn buffer_data(&mut self) -> Option<IsoTpEvent> { None }
+    fn clear_buffer(&mut self) {}
+    fn on_iso_tp_event(&mut self, _: IsoTpEvent) {}
+}
+
+/// Create the CAN FD capable vcan interface if it doesn't exist, root permission and the `vcan` module are required.
+///
+/// The existing interface is used as it is, so it must be up and CAN FD capable(MTU 72).
+/// The tests on vcan are ignored by default, run them with `cargo test --features socketcan -- --ignored`.
+pub fn vcan(name: &str) -> anyhow::Result<()> {
+    let path = Path::new("/sys/class/net").join(name);
+    if path.exists() {
+        return Ok(());
+    }
+
+    let ip = |args: &[&str]| -> anyhow::Result<()> {
+        let status = Command::new("ip").args(args).status()?;
+        anyhow::ensure!(status.success(), "`ip {}` failed: {}", args.join(" "), status);
+        Ok(())
+    };
+    ip(&["link", "add", "dev", name, "type", "vcan"])?;
+    ip(&["link", "set", name, "mtu", "72"])?;
+    ip(&["link", "set", "up", name])
+}
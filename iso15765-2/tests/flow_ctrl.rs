@@ -0,0 +1,22 @@
+use iso15765_2::*;
+
+#[test]
+fn test_flow_ctrl_config() -> anyhow::Result<()> {
+    let config = FlowControlConfig::default();
+    assert_eq!(config.block_size, DEFAULT_BLOCK_SIZE);
+    assert_eq!(config.st_min, DEFAULT_ST_MIN);
+    assert_eq!(config.wait_count, 0);
+    assert_eq!(config.wft_max, DEFAULT_WFT_MAX);
+    config.validate()?;
+
+    let config = FlowControlConfig { block_size: 8, st_min: 0xF5, wait_count: 2, wft_max: 2 };
+    config.validate()?;
+
+    let config = FlowControlConfig { st_min: 0x80, ..Default::default() };
+    assert!(matches!(config.validate(), Err(IsoTpError::InvalidStMin(0x80))));
+
+    let config = FlowControlConfig { wait_count: 3, wft_max: 2, ..Default::default() };
+    assert!(matches!(config.validate(), Err(IsoTpError::InvalidParam(_))));
+
+    Ok(())
+}
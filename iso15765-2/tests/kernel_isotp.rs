@@ -0,0 +1,81 @@
+mod common;
+
+use std::thread;
+use iso15765_2::*;
+use common::{vcan, NullListener};
+
+/// The caller is the same for userspace and kernel ISO-TP.
+fn request<T: IsoTpTransport>(iso_tp: &T, data: Vec<u8>) -> Result<Vec<u8>, IsoTpError> {
+    iso_tp.transceive(AddressType::Physical, data, 1000)
+}
+
+#[test]
+#[ignore = "requires the vcan interface"]
+fn test_kernel_iso_tp() -> anyhow::Result<()> {
+    let channel = "vcan2";
+    vcan(channel)?;
+
+    let tester = KernelIsoTp::new(channel, Address::normal(0x7E0, 0x7E8, 0x7DF))?;
+    let flow_ctrl = FlowControlConfig { block_size: 4, st_min: 0, ..Default::default() };
+    let address = Address::normal(0x7E8, 0x7E0, 0x7DF);
+    let ecu = KernelIsoTp::with_options(channel, address, IsoTpRole::Server, flow_ctrl, 64)?;
+    assert!(KernelIsoTp::with_options(channel, address, IsoTpRole::Server, flow_ctrl, 10).is_err());
+    let waiting = FlowControlConfig { wait_count: 1, wft_max: 1, ..flow_ctrl };
+    assert!(matches!(
+        KernelIsoTp::with_options(channel, address, IsoTpRole::Server, waiting, 64),
+        Err(IsoTpError::InvalidParam(_))
+    ));
+
+    let handle = thread::spawn(move || -> Result<(), IsoTpError> {
+        let data = ecu.read(1000)?;
+        ecu.write(AddressType::Physical, data.iter().rev().copied().collect())?;
+        // the functional request is received by the server, the reply is physical.
+        let data = ecu.read(1000)?;
+        assert!(ecu.write(AddressType::Functional, data.clone()).is_err());
+        ecu.write(AddressType::Physical, data)
+    });
+
+    let data: Vec<_> = (0..300_u32).map(|v| v as u8).collect();
+    let response = request(&tester, data.clone())?;
+    assert_eq!(response, data.into_iter().rev().collect::<Vec<_>>());
+
+    tester.write(AddressType::Functional, hex::decode("3E00")?)?;
+    assert_eq!(tester.read(1000)?, hex::decode("3E00")?);
+    assert!(matches!(tester.read(10), Err(IsoTpError::ReadTimeout { .. })));
+    handle.join().unwrap()?;
+
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires the vcan interface"]
+fn test_kernel_with_userspace() -> anyhow::Result<()> {
+    let channel = "vcan3";
+    vcan(channel)?;
+
+    let device = SocketCan::new();
+    device.open(channel, true)?;
+    let mut adapter = CanAdapter::new(device);
+    let tester = CanIsoTp::new(
+        channel.to_string(),
+        Address::normal(0x7E0, 0x7E8, 0x7DF),
+        adapter.sender(),
+        Box::new(NullListener),
+    );
+    adapter.register_listener("iso-tp".into(), Box::new(tester.clone()));
+    adapter.start(100)?;
+
+    let ecu = KernelIsoTp::new(channel, Address::normal(0x7E8, 0x7E0, 0x7DF))?;
+    let handle = thread::spawn(move || -> anyhow::Result<Vec<u8>> {
+        let data = ecu.read(1000)?;
+        ecu.write(AddressType::Physical, hex::decode("62F1900102030405060708090A")?)?;
+        Ok(data)
+    });
+
+    let response = request(&tester, hex::decode("22F190")?)?;
+    assert_eq!(response, hex::decode("62F1900102030405060708090A")?);
+    assert_eq!(handle.join().unwrap()?, hex::decode("22F190")?);
+
+    adapter.stop()?;
+    Ok(())
+}
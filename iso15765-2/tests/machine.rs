@@ -0,0 +1,285 @@
+use std::time::Duration;
+use iso15765_2::*;
+
+fn ms(value: u64) -> Duration {
+    Duration::from_millis(value)
+}
+
+fn confirm(machine: &mut IsoTpMachine, frame: &str, now: Duration) -> IsoTpActions {
+    machine.on_transmitted(&hex::decode(frame).unwrap(), now)
+}
+
+fn transmitted(actions: &[IsoTpAction]) -> Vec<String> {
+    actions.iter()
+        .filter_map(|a| match a {
+            IsoTpAction::Transmit(data) |
+            IsoTpAction::TransmitFlowCtrl(data) => Some(hex::encode_upper(data)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_write_multi_frame() -> anyhow::Result<()> {
+    let mut machine = IsoTpMachine::new(0, None);
+    machine.set_timing(TimingConfig { n_bs: 150, ..Default::default() });
+
+    let data = hex::decode("2EF1900102030405060708090A0B0C0D0E0F")?;
+    let actions = machine.write(&data, None, ms(0))?;
+    assert_eq!(transmitted(&actions), vec!["10122EF190010203"]);
+    assert!(matches!(machine.write(&data, None, ms(0)), Err(IsoTpError::Busy)));
+
+    // N_Bs is started after the first frame is confirmed.
+    assert!(confirm(&mut machine, "10122EF190010203", ms(1)).is_empty());
+    assert_eq!(machine.next_deadline(), Some(ms(151)));
+
+    // block size 1 and STmin 5ms.
+    let actions = machine.on_frame(&hex::decode("300105")?, ms(2));
+    assert_eq!(transmitted(&actions), vec!["210405060708090A"]);
+    // the flow control is required after each block.
+    assert!(confirm(&mut machine, "210405060708090A", ms(3)).is_empty());
+    let actions = machine.on_frame(&hex::decode("300105")?, ms(4));
+    assert_eq!(transmitted(&actions), vec!["220B0C0D0E0FAAAA"]);
+
+    let actions = confirm(&mut machine, "220B0C0D0E0FAAAA", ms(5));
+    assert!(matches!(actions.as_slice(), [IsoTpAction::TransmitFinished(Ok(()))]));
+    assert!(!machine.is_transmitting());
+
+    Ok(())
+}
+
+#[test]
+fn test_write_separation_time() -> anyhow::Result<()> {
+    let mut machine = IsoTpMachine::new(0, None);
+
+    let data = hex::decode("2EF1900102030405060708090A0B0C0D0E0F10111213")?;
+    machine.write(&data, None, ms(0))?;
+    confirm(&mut machine, "10162EF190010203", ms(1));
+    let actions = machine.on_frame(&hex::decode("300014")?, ms(2));
+    assert_eq!(transmitted(&actions), vec!["210405060708090A"]);
+    assert!(confirm(&mut machine, "210405060708090A", ms(3)).is_empty());
+
+    // the next consecutive frame is transmitted after STmin(20ms).
+    assert_eq!(machine.next_deadline(), Some(ms(23)));
+    assert!(machine.poll_timeout(ms(10)).is_empty());
+    let actions = machine.poll_timeout(ms(23));
+    assert_eq!(transmitted(&actions), vec!["220B0C0D0E0F1011"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_write_stream() -> anyhow::Result<()> {
+    let mut machine = IsoTpMachine::new(0, None);
+
+    machine.write_stream(20, None)?;
+    assert_eq!(machine.tx_state(), IsoTpState::Sending);
+    // the first frame is transmitted when its data is enough.
+    assert_eq!(machine.data_required(), 6);
+    assert!(machine.feed(&hex::decode("2EF190")?, ms(0))?.is_empty());
+    assert_eq!(machine.data_required(), 3);
+    let actions = machine.feed(&hex::decode("010203")?, ms(1))?;
+    assert_eq!(transmitted(&actions), vec!["10142EF190010203"]);
+    assert_eq!(machine.data_required(), 7);
+
+    confirm(&mut machine, "10142EF190010203", ms(2));
+    // the consecutive frame waits for the data.
+    assert!(machine.on_frame(&hex::decode("300000")?, ms(3)).is_empty());
+    let actions = machine.feed(&hex::decode("0405060708090A0B0C")?, ms(4))?;
+    assert_eq!(transmitted(&actions), vec!["210405060708090A"]);
+    assert_eq!(machine.data_required(), 5);
+
+    confirm(&mut machine, "210405060708090A", ms(5));
+    assert!(matches!(
+        machine.feed(&hex::decode("0D0E0F10111213")?, ms(6)),
+        Err(IsoTpError::InvalidDataLength { actual: 22, expect: 20 })
+    ));
+    let actions = machine.feed(&hex::decode("0D0E0F1011")?, ms(6))?;
+    assert_eq!(transmitted(&actions), vec!["220B0C0D0E0F1011"]);
+    let actions = confirm(&mut machine, "220B0C0D0E0F1011", ms(7));
+    assert!(matches!(actions.as_slice(), [IsoTpAction::TransmitFinished(Ok(()))]));
+
+    // the upper layer aborts the writing.
+    machine.write_stream(100, None)?;
+    let actions = machine.abort_write(IsoTpError::SourceError("closed".into()));
+    assert!(matches!(actions.as_slice(), [
+        IsoTpAction::Event(IsoTpEvent::TransmitAborted(IsoTpError::SourceError(_))),
+        IsoTpAction::TransmitFinished(Err(IsoTpError::SourceError(_))),
+    ]));
+    assert!(!machine.is_transmitting());
+
+    Ok(())
+}
+
+#[test]
+fn test_write_timeout() -> anyhow::Result<()> {
+    let mut machine = IsoTpMachine::new(0, None);
+    machine.set_timing(TimingConfig { n_as: 50, n_bs: 150, ..Default::default() });
+
+    let data = hex::decode("2EF1900102030405060708090A0B0C0D0E0F")?;
+    machine.write(&data, None, ms(0))?;
+    let actions = machine.poll_timeout(ms(51));
+    assert!(matches!(
+        actions.as_slice(),
+        [
+            IsoTpAction::Event(IsoTpEvent::TransmitAborted(IsoTpError::Timeout { timer: IsoTpTimer::NAs, .. })),
+            IsoTpAction::TransmitFinished(Err(IsoTpError::Timeout { timer: IsoTpTimer::NAs, .. })),
+        ]
+    ));
+
+    machine.write(&data, None, ms(100))?;
+    confirm(&mut machine, "10122EF190010203", ms(101));
+    // the wait flow control restarts N_Bs.
+    let actions = machine.on_frame(&hex::decode("310000")?, ms(200));
+    assert!(matches!(actions.as_slice(), [IsoTpAction::Event(IsoTpEvent::Wait)]));
+    assert!(machine.poll_timeout(ms(300)).is_empty());
+    let actions = machine.poll_timeout(ms(351));
+    assert!(matches!(
+        actions.as_slice(),
+        [
+            IsoTpAction::Event(IsoTpEvent::TransmitAborted(IsoTpError::Timeout { timer: IsoTpTimer::NBs, .. })),
+            IsoTpAction::TransmitFinished(Err(IsoTpError::Timeout { timer: IsoTpTimer::NBs, .. })),
+        ]
+    ));
+    assert!(!machine.is_transmitting());
+
+    Ok(())
+}
+
+#[test]
+fn test_receive() -> anyhow::Result<()> {
+    let mut machine = IsoTpMachine::new(0, None);
+    machine.set_timing(TimingConfig { n_cr: 100, ..Default::default() });
+
+    let actions = machine.on_frame(&hex::decode("0322F190AAAAAAAA")?, ms(0));
+    assert!(matches!(actions.as_slice(), [IsoTpAction::Event(IsoTpEvent::DataReceived(data))] if data == &hex::decode("22F190")?));
+
+    let actions = machine.on_frame(&hex::decode("100D62F190010203")?, ms(1));
+    assert_eq!(transmitted(&actions), vec!["30000AAAAAAAAAAA"]);
+    assert!(matches!(actions.last(), Some(IsoTpAction::Event(IsoTpEvent::FirstFrameReceived))));
+    assert!(machine.is_receiving());
+
+    // N_Cr is started after the flow control frame is confirmed.
+    confirm(&mut machine, "30000AAAAAAAAAAA", ms(2));
+    assert_eq!(machine.next_deadline(), Some(ms(102)));
+    let actions = machine.on_frame(&hex::decode("2104050607080910")?, ms(3));
+    assert!(matches!(actions.as_slice(), [IsoTpAction::Event(IsoTpEvent::DataReceived(data))] if data == &hex::decode("62F19001020304050607080910")?));
+    assert!(!machine.is_receiving());
+
+    // the reception is aborted when N_Cr is timeout.
+    machine.on_frame(&hex::decode("100D62F190010203")?, ms(200));
+    confirm(&mut machine, "30000AAAAAAAAAAA", ms(201));
+    let actions = machine.poll_timeout(ms(302));
+    assert!(matches!(
+        actions.as_slice(),
+        [IsoTpAction::Event(IsoTpEvent::ReceiveAborted(IsoTpError::Timeout { timer: IsoTpTimer::NCr, .. }))]
+    ));
+    assert!(!machine.is_receiving());
+
+    Ok(())
+}
+
+#[test]
+fn test_full_duplex() -> anyhow::Result<()> {
+    let mut machine = IsoTpMachine::new(0, None);
+
+    let data = hex::decode("2EF1900102030405060708090A0B0C0D0E0F")?;
+    machine.write(&data, None, ms(0))?;
+    confirm(&mut machine, "10122EF190010203", ms(1));
+    assert_eq!(machine.tx_state(), IsoTpState::WaitFlowCtrl);
+
+    // the ECU starts to transmit while waiting for the flow control frame.
+    let actions = machine.on_frame(&hex::decode("100D62F190010203")?, ms(2));
+    assert_eq!(transmitted(&actions), vec!["30000AAAAAAAAAAA"]);
+    assert_eq!(machine.rx_state(), IsoTpState::Sending);
+    assert_eq!(machine.tx_state(), IsoTpState::WaitFlowCtrl);
+
+    confirm(&mut machine, "30000AAAAAAAAAAA", ms(3));
+    assert_eq!(machine.rx_state(), IsoTpState::WaitData);
+
+    let actions = machine.on_frame(&hex::decode("300000")?, ms(4));
+    assert_eq!(transmitted(&actions), vec!["210405060708090A"]);
+    let actions = confirm(&mut machine, "210405060708090A", ms(5));
+    assert_eq!(transmitted(&actions), vec!["220B0C0D0E0FAAAA"]);
+
+    // the error of reception doesn't abort the transmission.
+    let actions = machine.on_frame(&hex::decode("2304050607080910")?, ms(6));
+    assert!(matches!(
+        actions.as_slice(),
+        [IsoTpAction::Event(IsoTpEvent::ReceiveAborted(IsoTpError::InvalidSequence { expect: 1, actual: 3 }))]
+    ));
+    assert_eq!(machine.rx_state(), IsoTpState::Idle);
+    assert!(machine.is_transmitting());
+
+    let actions = confirm(&mut machine, "220B0C0D0E0FAAAA", ms(7));
+    assert!(matches!(actions.as_slice(), [IsoTpAction::TransmitFinished(Ok(()))]));
+    assert_eq!(machine.tx_state(), IsoTpState::Idle);
+
+    Ok(())
+}
+
+#[test]
+fn test_receive_recovery() -> anyhow::Result<()> {
+    let mut machine = IsoTpMachine::new(0, None);
+
+    machine.on_frame(&hex::decode("100D62F190010203")?, ms(0));
+    confirm(&mut machine, "30000AAAAAAAAAAA", ms(1));
+
+    // the invalid frame is ignored.
+    let actions = machine.on_frame(&hex::decode("4001020304050607")?, ms(2));
+    assert!(matches!(actions.as_slice(), [IsoTpAction::Event(IsoTpEvent::ErrorOccurred(_))]));
+    assert_eq!(machine.rx_state(), IsoTpState::WaitData);
+
+    // the unexpected first frame aborts the PDU receiving and starts the new one.
+    let actions = machine.on_frame(&hex::decode("100A62F190414243")?, ms(3));
+    assert!(matches!(actions.first(), Some(IsoTpAction::Event(IsoTpEvent::ReceiveAborted(IsoTpError::MixFramesError)))));
+    assert_eq!(transmitted(&actions), vec!["30000AAAAAAAAAAA"]);
+    confirm(&mut machine, "30000AAAAAAAAAAA", ms(4));
+    let actions = machine.on_frame(&hex::decode("2144454647484950")?, ms(5));
+    assert!(matches!(actions.as_slice(), [IsoTpAction::Event(IsoTpEvent::DataReceived(data))] if data == &hex::decode("62F19041424344454647")?));
+
+    // the wrong sequence aborts the PDU receiving only, the next PDU is received.
+    machine.on_frame(&hex::decode("100A62F190414243")?, ms(6));
+    confirm(&mut machine, "30000AAAAAAAAAAA", ms(7));
+    machine.on_frame(&hex::decode("2244454647484950")?, ms(8));
+    assert_eq!(machine.rx_state(), IsoTpState::Idle);
+    let actions = machine.on_frame(&hex::decode("0322F190")?, ms(9));
+    assert!(matches!(actions.as_slice(), [IsoTpAction::Event(IsoTpEvent::DataReceived(data))] if data == &hex::decode("22F190")?));
+
+    Ok(())
+}
+
+#[test]
+fn test_statistics() -> anyhow::Result<()> {
+    let mut machine = IsoTpMachine::new(0, None);
+
+    let data: Vec<_> = (0..34).collect();
+    let actions = machine.write(&data, None, ms(0))?;
+    confirm(&mut machine, &transmitted(&actions)[0], ms(1));
+    // STmin 500µs.
+    let actions = machine.on_frame(&hex::decode("3000F5")?, ms(2));
+    let mut frame = transmitted(&actions).remove(0);
+    let mut now = ms(3);
+    for gap in [600, 500, 700] {
+        assert!(confirm(&mut machine, &frame, now).is_empty());
+        now += Duration::from_micros(gap);
+        let actions = machine.poll_timeout(now);
+        frame = transmitted(&actions).remove(0);
+    }
+    let actions = confirm(&mut machine, &frame, now);
+    assert!(matches!(actions.as_slice(), [IsoTpAction::TransmitFinished(Ok(()))]));
+
+    let statistics = machine.statistics();
+    assert_eq!(statistics.frames, 4);
+    assert_eq!(statistics.gaps, 3);
+    assert_eq!(statistics.st_min, Duration::from_micros(500));
+    assert_eq!(statistics.min_gap, Some(Duration::from_micros(500)));
+    assert_eq!(statistics.max_gap, Some(Duration::from_micros(700)));
+    assert_eq!(statistics.mean_gap(), Some(Duration::from_micros(600)));
+    assert_eq!(statistics.violations, 0);
+
+    machine.reset_statistics();
+    assert_eq!(machine.statistics(), IsoTpStatistics::default());
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use std::thread;
+use iso15765_2::*;
+
+use common::*;
+
+/// ECU `n` answers at `0x7E8 + n`, the ECU 2 doesn't respond to functional request.
+fn ecu_simulator(frame: &MockFrame) -> Vec<(u32, Vec<u8>)> {
+    let id = frame.id.into_bits();
+    match (id, frame.data[0]) {
+        (0x7DF, 0x02) => vec![
+            (0x7E8, hex::decode("025001AAAAAAAAAA").unwrap()),
+            (0x7E9, hex::decode("025001AAAAAAAAAA").unwrap()),
+        ],
+        (0x7E0..=0x7E2, 0x03) => {
+            let index = (id - 0x7E0) as u8;
+            vec![(id + 8, hex::decode(format!("100A620F{:02X}010203", index)).unwrap())]
+        },
+        (0x7E0..=0x7E2, 0x30) => vec![(id + 8, hex::decode("2104050607AAAAAA").unwrap())],
+        _ => vec![],
+    }
+}
+
+/// The ECUs share the CAN-IDs of mixed addressing(0x700/0x708) and differ by N_AE.
+fn mixed_simulator(frame: &MockFrame) -> Vec<(u32, Vec<u8>)> {
+    let ext = frame.data[0];
+    match (frame.id.into_bits(), frame.data[1]) {
+        (0x700, 0x03) => vec![(0x708, hex::decode(format!("{:02X}100A620F{:02X}0102", ext, ext)).unwrap())],
+        (0x700, 0x30) => vec![(0x708, hex::decode(format!("{:02X}21030405060708", ext)).unwrap())],
+        _ => vec![],
+    }
+}
+
+type Adapter = CanAdapter<MockDevice, String, MockFrame>;
+
+fn setup() -> anyhow::Result<(Adapter, IsoTpMux<String, MockFrame>)> {
+    let device = MockDevice::with_ids(0x7E8, ecu_simulator);
+    let mut adapter = CanAdapter::new(device);
+    let mux = IsoTpMux::new(CHANNEL.to_string(), adapter.sender());
+    for index in 0..3 {
+        mux.add(Address::normal(0x7E0 + index, 0x7E8 + index, 0x7DF), Box::new(NullListener))?;
+    }
+    adapter.register_listener("iso-tp".into(), Box::new(mux.clone()));
+    adapter.start(100)?;
+
+    Ok((adapter, mux))
+}
+
+#[test]
+fn test_concurrent() -> anyhow::Result<()> {
+    let (mut adapter, mux) = setup()?;
+
+    let ret = mux.add(Address::normal(0x7E3, 0x7E8, 0x7DF), Box::new(NullListener));
+    assert!(ret.is_err());
+    let mut rx_ids = mux.rx_ids();
+    rx_ids.sort();
+    assert_eq!(rx_ids, vec![0x7E8, 0x7E9, 0x7EA]);
+
+    let handles: Vec<_> = (0..3_u32)
+        .map(|index| {
+            let iso_tp = mux.get(0x7E8 + index).unwrap();
+            thread::spawn(move || iso_tp.transceive(AddressType::Physical, hex::decode("220F00").unwrap(), 1000))
+        })
+        .collect();
+    for (index, handle) in handles.into_iter().enumerate() {
+        let response = handle.join().unwrap()?;
+        assert_eq!(response, hex::decode(format!("620F{:02X}01020304050607", index))?);
+    }
+
+    assert!(mux.remove(0x7EA).is_some());
+    assert!(mux.get(0x7EA).is_none());
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_broadcast() -> anyhow::Result<()> {
+    let (mut adapter, mux) = setup()?;
+
+    assert!(mux.broadcast(0x7DE, hex::decode("1001")?, 100).is_err());
+    // the functional request must fit in one single frame.
+    assert!(matches!(
+        mux.broadcast(0x7DF, hex::decode("1001020304050607")?, 100),
+        Err(IsoTpError::InvalidDataLength { actual: 8, expect: 7 })
+    ));
+
+    let responses = mux.broadcast(0x7DF, hex::decode("1001")?, 100)?;
+    assert_eq!(responses.len(), 3);
+    assert_eq!(responses[&ConnectionKey::from(0x7E8)].as_ref().unwrap(), &hex::decode("5001")?);
+    assert_eq!(responses[&ConnectionKey::from(0x7E9)].as_ref().unwrap(), &hex::decode("5001")?);
+    assert!(matches!(responses[&ConnectionKey::from(0x7EA)], Err(IsoTpError::ReadTimeout { .. })));
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_servers_functional() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E0, |_| vec![]);
+    let mut adapter = CanAdapter::new(device.clone());
+    let mux = IsoTpMux::new(CHANNEL.to_string(), adapter.sender());
+    let servers = (0..2)
+        .map(|index| {
+            let iso_tp = mux.add(Address::normal(0x7E8 + index, 0x7E0 + index, 0x7DF), Box::new(NullListener))?;
+            iso_tp.set_role(IsoTpRole::Server);
+            Ok(iso_tp)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    adapter.register_listener("iso-tp".into(), Box::new(mux.clone()));
+    adapter.start(100)?;
+
+    // every server receives the functional request.
+    device.inject_with_id(0x7DF, hex::decode("023E80AAAAAAAAAA")?);
+    for server in &servers {
+        assert_eq!(server.read(1000)?, hex::decode("3E80")?);
+    }
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_mixed_shared_id() -> anyhow::Result<()> {
+    let device = MockDevice::with_ids(0x708, mixed_simulator);
+    let mut adapter = CanAdapter::new(device);
+    let mux = IsoTpMux::new(CHANNEL.to_string(), adapter.sender());
+    for ext in 1..=2 {
+        mux.add(Address::mixed(0x700, 0x708, 0x7DF, ext), Box::new(NullListener))?;
+    }
+    // the rx_id can't be shared with the address without extension.
+    assert!(mux.add(Address::normal(0x7E0, 0x708, 0x7DF), Box::new(NullListener)).is_err());
+    let mut keys = mux.keys();
+    keys.sort();
+    assert_eq!(keys, vec![ConnectionKey::new(0x708, Some(1)), ConnectionKey::new(0x708, Some(2))]);
+    assert_eq!(mux.rx_ids(), vec![0x708]);
+    adapter.register_listener("iso-tp".into(), Box::new(mux.clone()));
+    adapter.start(100)?;
+
+    // the frames of both ECUs are routed by N_AE.
+    let handles: Vec<_> = (1..=2_u8)
+        .map(|ext| {
+            let iso_tp = mux.get(ConnectionKey::new(0x708, Some(ext))).unwrap();
+            thread::spawn(move || iso_tp.transceive(AddressType::Physical, hex::decode("220F00").unwrap(), 1000))
+        })
+        .collect();
+    for (index, handle) in handles.into_iter().enumerate() {
+        let response = handle.join().unwrap()?;
+        assert_eq!(response, hex::decode(format!("620F{:02X}01020304050607", index + 1))?);
+    }
+
+    assert!(mux.remove(ConnectionKey::new(0x708, Some(2))).is_some());
+    assert!(mux.get(0x708).is_none());
+
+    adapter.stop()?;
+    Ok(())
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use std::{sync::{Arc, atomic::{AtomicBool, Ordering}}, thread};
+use iso15765_2::*;
+use rs_can::{CanDevice, CanFrame, CanId};
+use common::NullListener;
+
+const CHANNEL: &str = "vcan";
+
+/// The ECU answers the functional request of OBD until it's stopped.
+fn ecu(bus: &VirtualCanBus, functional_id: u32, response_id: u32) -> Arc<AtomicBool> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let device = bus.device();
+    let extended = functional_id > 0x7FF;
+    thread::spawn({
+        let stop = Arc::clone(&stop);
+        move || while !stop.load(Ordering::Relaxed) {
+            let frames = device.receive(CHANNEL.into(), Some(10)).unwrap();
+            for frame in frames.iter().filter(|f| f.id().into_bits() == functional_id) {
+                assert_eq!(frame.data(), hex::decode("020100AAAAAAAAAA").unwrap());
+                let data = hex::decode("064100BE1FA81300").unwrap();
+                let mut response = CanMessage::new(CanId::from_bits(response_id, Some(extended)), &data).unwrap();
+                response.set_channel(CHANNEL.into());
+                device.transmit(response, None).unwrap();
+            }
+        }
+    });
+
+    stop
+}
+
+#[test]
+fn test_address() {
+    let profile = Obd15765_4::default();
+    assert_eq!(profile.functional_id(), 0x7DF);
+    assert_eq!(profile.address(0), Address::normal(0x7E0, 0x7E8, 0x7DF));
+    assert_eq!(profile.address(7), Address::normal(0x7E7, 0x7EF, 0x7DF));
+
+    let profile = Obd15765_4 { id_length: ObdIdLength::Extended, ..Default::default() };
+    assert_eq!(profile.functional_id(), 0x18DB33F1);
+    let address = profile.address(0x10);
+    assert_eq!((address.tx_id, address.rx_id, address.fid), (0x18DA10F1, 0x18DAF110, 0x18DB33F1));
+}
+
+#[test]
+fn test_apply() -> anyhow::Result<()> {
+    let bus = VirtualCanBus::new(CHANNEL);
+    let mut adapter = CanAdapter::new(bus.device());
+    let iso_tp = CanIsoTp::new(CHANNEL.to_string(), Obd15765_4::default().address(0), adapter.sender(), Box::new(NullListener));
+    adapter.register_listener("iso-tp".into(), Box::new(iso_tp.clone()));
+    adapter.start(100)?;
+
+    let profile = Obd15765_4 { padding: 0x55, ..Default::default() };
+    profile.apply(&iso_tp)?;
+    assert_eq!(iso_tp.timing()?, TimingConfig { n_as: 25, n_ar: 25, n_bs: 75, n_br: 42, n_cs: 110, n_cr: 150 });
+    iso_tp.write(AddressType::Functional, hex::decode("0100")?)?;
+    // no flow control in N_Bs.
+    assert!(matches!(
+        iso_tp.write(AddressType::Physical, vec![0x01; 10]),
+        Err(IsoTpError::Timeout { timer: IsoTpTimer::NBs, value: 75, .. })
+    ));
+
+    let history = bus.history();
+    assert_eq!(history[0].id().into_bits(), 0x7DF);
+    assert_eq!(history[0].data(), hex::decode("0201005555555555")?);
+    assert_eq!(history[1].data(), hex::decode("100A010101010101")?);
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_detect() -> anyhow::Result<()> {
+    let bus = VirtualCanBus::new(CHANNEL);
+    let stop = [ecu(&bus, 0x7DF, 0x7E8), ecu(&bus, 0x7DF, 0x7E9)];
+    let detection = Obd15765_4::default().detect(&bus.device(), CHANNEL.to_string())?.unwrap();
+    stop.iter().for_each(|s| s.store(true, Ordering::Relaxed));
+    assert_eq!(detection.profile.id_length, ObdIdLength::Standard);
+    assert_eq!(detection.addresses, vec![
+        Address::normal(0x7E0, 0x7E8, 0x7DF),
+        Address::normal(0x7E1, 0x7E9, 0x7DF),
+    ]);
+
+    let bus = VirtualCanBus::new(CHANNEL);
+    let stop = ecu(&bus, 0x18DB33F1, 0x18DAF110);
+    let detection = Obd15765_4::default().detect(&bus.device(), CHANNEL.to_string())?.unwrap();
+    stop.store(true, Ordering::Relaxed);
+    assert_eq!(detection.profile.id_length, ObdIdLength::Extended);
+    assert_eq!(detection.addresses, vec![Address::normal_fixed(0xF1, 0x10, 0x33)]);
+    // 11bit request is sent first.
+    let history = bus.history();
+    assert_eq!(history[0].id().into_bits(), 0x7DF);
+
+    let bus = VirtualCanBus::new(CHANNEL);
+    assert!(Obd15765_4::default().detect(&bus.device(), CHANNEL.to_string())?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_detect_bitrate() -> anyhow::Result<()> {
+    let silent = VirtualCanBus::new(CHANNEL);
+    let vehicle = VirtualCanBus::new(CHANNEL);
+    let stop = ecu(&vehicle, 0x7DF, 0x7EA);
+
+    let mut opened = Vec::new();
+    let (device, detection) = Obd15765_4::default()
+        .detect_bitrate(CHANNEL.to_string(), |bitrate| {
+            opened.push(bitrate);
+            Ok(match bitrate {
+                ObdBitrate::Kbps500 => silent.device(),
+                ObdBitrate::Kbps250 => vehicle.device(),
+            })
+        })?
+        .unwrap();
+    stop.store(true, Ordering::Relaxed);
+
+    assert_eq!(opened, vec![ObdBitrate::Kbps500, ObdBitrate::Kbps250]);
+    assert!(!device.is_closed());
+    assert_eq!(detection.profile.bitrate, ObdBitrate::Kbps250);
+    assert_eq!(detection.profile.bitrate.bitrate(), 250_000);
+    assert_eq!(detection.addresses, vec![Address::normal(0x7E2, 0x7EA, 0x7DF)]);
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex};
+use iso15765_2::*;
+
+use common::*;
+
+fn setup(device: MockDevice) -> (CanAdapter<MockDevice, String, MockFrame>, CanIsoTp<String, MockFrame>) {
+    let mut adapter = CanAdapter::new(device);
+    let iso_tp = CanIsoTp::new(
+        CHANNEL.to_string(),
+        Address::normal(0x7E0, 0x7E8, 0x7DF),
+        adapter.sender(),
+        Box::new(NullListener),
+    );
+    adapter.register_listener("iso-tp".into(), Box::new(iso_tp.clone()));
+    adapter.start(100).unwrap();
+
+    (adapter, iso_tp)
+}
+
+type Record = (TransferDirection, IsoTpFrameType, Option<u8>);
+
+#[derive(Default, Clone)]
+struct Recorder {
+    frames: Arc<Mutex<Vec<Record>>>,
+    flow_ctrl: Arc<Mutex<Vec<(u8, u8)>>>,
+}
+
+impl IsoTpObserver for Recorder {
+    fn on_frame_transmitted(&self, _: &Address, frame: &FrameRecord) {
+        self.frames.lock().unwrap().push((TransferDirection::Transmit, frame.frame_type, frame.sequence));
+    }
+
+    fn on_frame_received(&self, _: &Address, frame: &FrameRecord) {
+        self.frames.lock().unwrap().push((TransferDirection::Receive, frame.frame_type, frame.sequence));
+    }
+
+    fn on_flow_ctrl_received(&self, _: &Address, ctx: &FlowControlContext) {
+        self.flow_ctrl.lock().unwrap().push((ctx.block_size(), ctx.st_min()));
+    }
+}
+
+#[test]
+fn test_observer() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E8, |frame| match frame.data[0] {
+        0x10 => vec![hex::decode("300005").unwrap()],
+        _ => vec![],
+    });
+    let (mut adapter, iso_tp) = setup(device);
+    let recorder = Recorder::default();
+    iso_tp.set_observer(recorder.clone());
+
+    iso_tp.write(AddressType::Physical, hex::decode("2EF1900102030405060708090A0B0C0D0E0F")?)?;
+
+    assert_eq!(*recorder.frames.lock().unwrap(), vec![
+        (TransferDirection::Transmit, IsoTpFrameType::First, None),
+        (TransferDirection::Receive, IsoTpFrameType::FlowControl, None),
+        (TransferDirection::Transmit, IsoTpFrameType::Consecutive, Some(1)),
+        (TransferDirection::Transmit, IsoTpFrameType::Consecutive, Some(2)),
+    ]);
+    assert_eq!(*recorder.flow_ctrl.lock().unwrap(), vec![(0, 5)]);
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_metrics() -> anyhow::Result<()> {
+    let lost = Arc::new(AtomicBool::new(false));
+    let device = MockDevice::new(0x7E8, {
+        let lost = Arc::clone(&lost);
+        move |frame| match frame.data[0] {
+        0x10 => vec![hex::decode("300000").unwrap()],
+        // the last consecutive frame of request
+        0x22 => vec![hex::decode("100A6EF190414243").unwrap()],
+        // FC sent by CanIsoTp
+        0x30 if !lost.load(Ordering::Relaxed) => vec![hex::decode("2144454647484950").unwrap()],
+        _ => vec![],
+    }});
+    let (mut adapter, iso_tp) = setup(device.clone());
+    let metrics = IsoTpMetrics::new();
+    iso_tp.set_observer(metrics.clone());
+    iso_tp.set_timing(TimingConfig { n_cr: 50, ..Default::default() });
+
+    let response = iso_tp.transceive(AddressType::Physical, hex::decode("2EF1900102030405060708090A0B0C0D0E0F")?, 1000)?;
+    assert_eq!(response, hex::decode("6EF19041424344454647")?);
+
+    // the consecutive frame is lost.
+    lost.store(true, Ordering::Relaxed);
+    device.inject(hex::decode("100A6EF190414243")?);
+    device.inject(hex::decode("4001020304050607")?);
+    assert!(matches!(iso_tp.read(1000), Err(IsoTpError::Timeout { timer: IsoTpTimer::NCr, .. })));
+
+    let address = Address::normal(0x7E0, 0x7E8, 0x7DF);
+    let m = metrics.get(&address).unwrap();
+    assert_eq!(m.pdus_transmitted, 1);
+    assert_eq!(m.bytes_transmitted, 18);
+    assert_eq!(m.pdus_received, 1);
+    assert_eq!(m.bytes_received, 10);
+    // FF, 2 CFs and 2 FCs.
+    assert_eq!(m.frames_transmitted, 5);
+    // FC, FF, CF and the second FF, the invalid frame is not recorded.
+    assert_eq!(m.frames_received, 4);
+    assert_eq!(m.flow_ctrl_received, 1);
+    assert_eq!(m.timeouts, 1);
+    assert_eq!(m.errors, 1);
+    assert!(m.throughput().is_some());
+
+    metrics.reset();
+    assert!(metrics.all().is_empty());
+
+    adapter.stop()?;
+    Ok(())
+}
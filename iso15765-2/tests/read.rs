@@ -0,0 +1,265 @@
+mod common;
+
+use std::{io::Read, sync::{Arc, atomic::{AtomicUsize, Ordering}}, thread, time::{Duration, Instant}};
+use iso15765_2::*;
+use rs_can::{CanDevice, CanFrame, CanId, CanListener};
+
+use common::*;
+
+fn setup<D>(device: D) -> (CanAdapter<D, String, MockFrame>, CanIsoTp<String, MockFrame>)
+where
+    D: CanDevice<Channel = String, Frame = MockFrame> + Clone + Send + 'static,
+{
+    let mut adapter = CanAdapter::new(device);
+    let iso_tp = CanIsoTp::new(
+        CHANNEL.to_string(),
+        Address::normal(0x7E0, 0x7E8, 0x7DF),
+        adapter.sender(),
+        Box::new(NullListener),
+    );
+    adapter.register_listener("iso-tp".into(), Box::new(iso_tp.clone()));
+    adapter.start(100).unwrap();
+
+    (adapter, iso_tp)
+}
+
+#[test]
+fn test_transceive() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E8, |frame| match frame.data[0] {
+        // the request
+        0x03 => vec![hex::decode("100A62F190414243").unwrap()],
+        // FC sent by CanIsoTp
+        0x30 => vec![hex::decode("2144454647484950").unwrap()],
+        _ => vec![],
+    });
+    let (mut adapter, iso_tp) = setup(device.clone());
+
+    let response = iso_tp.transceive(AddressType::Physical, hex::decode("22F190")?, 1000)?;
+    assert_eq!(response, hex::decode("62F19041424344454647")?);
+    assert_eq!(device.transmitted(), vec![
+        "0322F190AAAAAAAA",
+        "30000AAAAAAAAAAA",
+    ]);
+
+    // the reception is complete, no more data is waited.
+    let ret = iso_tp.read(50);
+    assert!(matches!(ret, Err(IsoTpError::ReadTimeout { .. })));
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_read_without_notifying() -> anyhow::Result<()> {
+    // no adapter notifies the CanIsoTp after the first frame, N_Cr is checked by the reader.
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let iso_tp = CanIsoTp::new(
+        CHANNEL.to_string(),
+        Address::normal(0x7E0, 0x7E8, 0x7DF),
+        sender,
+        Box::new(NullListener),
+    );
+    iso_tp.set_timing(TimingConfig { n_cr: 50, ..Default::default() });
+    let mut frame = MockFrame::new(CanId::from_bits(0x7E8, None), &hex::decode("100A62F190414243")?).unwrap();
+    frame.set_channel(CHANNEL.into());
+    iso_tp.on_frame_received(CHANNEL.into(), &[frame]);
+    // the flow control frame is confirmed.
+    let flow_ctrl: MockFrame = receiver.recv()?;
+    iso_tp.on_frame_transmitting(CHANNEL.into(), &flow_ctrl);
+    iso_tp.on_frame_transmitted(CHANNEL.into(), flow_ctrl.id());
+
+    let start = Instant::now();
+    let ret = iso_tp.read(1000);
+    assert!(matches!(ret, Err(IsoTpError::Timeout { timer: IsoTpTimer::NCr, .. })), "{:?}", ret);
+    assert!(start.elapsed() < Duration::from_millis(500));
+
+    Ok(())
+}
+
+#[test]
+fn test_receive_while_writing() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E8, |frame| match frame.data[0] {
+        // the first frame of request, the ECU sends the flow control frame and its own first frame.
+        0x10 => vec![
+            hex::decode("300000").unwrap(),
+            hex::decode("100A86F190414243").unwrap(),
+        ],
+        // FC sent by CanIsoTp
+        0x30 => vec![hex::decode("2144454647484950").unwrap()],
+        _ => vec![],
+    });
+    let (mut adapter, iso_tp) = setup(device.clone());
+
+    iso_tp.write(AddressType::Physical, hex::decode("2EF1900102030405060708090A0B0C0D0E0F")?)?;
+    let response = iso_tp.read(1000)?;
+    assert_eq!(response, hex::decode("86F19041424344454647")?);
+
+    let transmitted = device.transmitted();
+    assert_eq!(transmitted.len(), 4);
+    assert!(transmitted.contains(&"30000AAAAAAAAAAA".to_string()));
+    assert_eq!(transmitted.last(), Some(&"220B0C0D0E0FAAAA".to_string()));
+    assert_eq!(iso_tp.tx_state()?, IsoTpState::Idle);
+    assert_eq!(iso_tp.rx_state()?, IsoTpState::Idle);
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_read_recovery() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E8, |frame| match frame.data[0] {
+        // FC sent by CanIsoTp
+        0x30 => vec![hex::decode("2244454647484950").unwrap()],
+        _ => vec![],
+    });
+    let (mut adapter, iso_tp) = setup(device.clone());
+
+    // the garbled frame is ignored.
+    device.inject(hex::decode("4001020304050607")?);
+    device.inject(hex::decode("0362F190")?);
+    assert_eq!(iso_tp.read(1000)?, hex::decode("62F190")?);
+
+    // the PDU with wrong sequence is aborted and reported.
+    device.inject(hex::decode("100A62F190414243")?);
+    let ret = iso_tp.read(1000);
+    assert!(matches!(ret, Err(IsoTpError::InvalidSequence { expect: 1, actual: 2 })));
+    assert_eq!(iso_tp.rx_state()?, IsoTpState::Idle);
+
+    // the channel is not deaf after the errors.
+    device.inject(hex::decode("0362F190")?);
+    assert_eq!(iso_tp.read(1000)?, hex::decode("62F190")?);
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_write_st_min() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E8, |frame| match frame.data[0] {
+        // STmin 300µs.
+        0x10 => vec![hex::decode("3000F3").unwrap()],
+        _ => vec![],
+    });
+    let (mut adapter, iso_tp) = setup(device.clone());
+
+    let data: Vec<_> = (0..100).collect();
+    iso_tp.write(AddressType::Physical, data)?;
+
+    let statistics = iso_tp.statistics()?;
+    assert_eq!(statistics.frames, 14);
+    assert_eq!(statistics.gaps, 13);
+    assert_eq!(statistics.st_min, Duration::from_micros(300));
+    assert_eq!(statistics.violations, 0);
+    assert!(statistics.min_gap >= Some(statistics.st_min));
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_write_busy() -> anyhow::Result<()> {
+    // the flow control frame is injected by the test.
+    let device = MockDevice::new(0x7E8, |_| vec![]);
+    let (mut adapter, iso_tp) = setup(device.clone());
+
+    let writer = {
+        let iso_tp = iso_tp.clone();
+        thread::spawn(move || iso_tp.write(AddressType::Physical, hex::decode("2EF1900102030405060708090A0B0C0D0E0F").unwrap()))
+    };
+    while device.transmitted().is_empty() {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    // the second write is rejected without changing the transfer in progress.
+    let ret = iso_tp.write(AddressType::Functional, hex::decode("3E00")?);
+    assert!(matches!(ret, Err(IsoTpError::Busy)));
+    device.inject(hex::decode("300000")?);
+    writer.join().unwrap()?;
+
+    assert_eq!(device.transmitted(), vec![
+        "10122EF190010203",
+        "210405060708090A",
+        "220B0C0D0E0FAAAA",
+    ]);
+    let ids: Vec<_> = device.transmitted.lock().unwrap()
+        .iter()
+        .map(|f| f.id.into_bits())
+        .collect();
+    assert_eq!(ids, vec![0x7E0; 3]);
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_write_st_min_accuracy() -> anyhow::Result<()> {
+    // the adapter checks the timers rarely when blocking on the device.
+    let device = BlockingDevice::new(MockDevice::new(0x7E8, |frame| match frame.data[0] {
+        // STmin 500µs.
+        0x10 => vec![hex::decode("3000F5").unwrap()],
+        _ => vec![],
+    }));
+    let (mut adapter, iso_tp) = setup(device);
+
+    let data: Vec<_> = (0..200).collect();
+    iso_tp.write(AddressType::Physical, data)?;
+
+    // the writer is woken by the confirmation, the gaps are not delayed by polling(1ms).
+    let statistics = iso_tp.statistics()?;
+    assert_eq!(statistics.gaps, 27);
+    assert_eq!(statistics.violations, 0);
+    let min_gap = statistics.min_gap.unwrap();
+    assert!(min_gap < statistics.st_min + Duration::from_micros(200), "{:?}", statistics);
+
+    adapter.stop()?;
+    Ok(())
+}
+
+/// The reader records the largest length requested.
+struct Source {
+    data: Vec<u8>,
+    position: usize,
+    max_read: Arc<AtomicUsize>,
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.max_read.fetch_max(buf.len(), Ordering::Relaxed);
+        let length = buf.len().min(self.data.len() - self.position);
+        buf[..length].copy_from_slice(&self.data[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+#[test]
+fn test_write_from_reader() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E8, |frame| match frame.data[0] {
+        // block size 2
+        0x10 | 0x22 => vec![hex::decode("300200").unwrap()],
+        _ => vec![],
+    });
+    let (mut adapter, iso_tp) = setup(device.clone());
+
+    let data: Vec<_> = (0..27).collect();
+    let max_read = Arc::new(AtomicUsize::new(0));
+    let source = Source { data: data.clone(), position: 0, max_read: Arc::clone(&max_read) };
+    iso_tp.write_from_reader(AddressType::Physical, data.len(), source)?;
+    assert_eq!(device.transmitted(), vec![
+        "101B000102030405",
+        "21060708090A0B0C",
+        "220D0E0F10111213",
+        "231415161718191A",
+    ]);
+    // the data is read frame by frame.
+    assert_eq!(max_read.load(Ordering::Relaxed), 7);
+
+    // the source ends before the length declared.
+    let source = Source { data, position: 0, max_read };
+    let ret = iso_tp.write_from_reader(AddressType::Physical, 100, source);
+    assert!(matches!(ret, Err(IsoTpError::InvalidDataLength { actual: 27, expect: 100 })));
+    assert_eq!(iso_tp.tx_state()?, IsoTpState::Idle);
+
+    adapter.stop()?;
+    Ok(())
+}
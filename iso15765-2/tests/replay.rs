@@ -0,0 +1,229 @@
+use iso15765_2::*;
+use rs_can::{CanDirect, CanFrame};
+
+const CANDUMP: &str = "\
+(1700000000.100000) vcan0 7E0#0322F19000000000
+(1700000000.101000) vcan0 7E8#101462F190574442
+(1700000000.102000) vcan0 7E0#3000000000000000
+(1700000000.103000) vcan0 7E8#2130313233343536
+(1700000000.104000) vcan0 7E8#2237383941424344
+(1700000000.105000) vcan0 123#0102030405060708
+
+(1700000000.200000) vcan0 7DF#0210010000000000
+";
+
+const ASC: &str = "\
+date Wed Oct 18 10:00:00.000 am 2026
+base hex  timestamps absolute
+internal events logged
+// version 13.0.0
+Begin TriggerBlock Wed Oct 18 10:00:00.000 am 2026
+   0.000000 Start of measurement
+   0.100000 1  7E0             Tx   d 8 03 22 F1 90 00 00 00 00  Length = 0 BitCount = 0 ID = 2016
+   0.101000 1  7E8             Rx   d 8 10 14 62 F1 90 57 44 42  Length = 0 BitCount = 0 ID = 2024
+   0.102000 1  7E0             Tx   d 8 30 00 00 00 00 00 00 00  Length = 0 BitCount = 0 ID = 2016
+   0.103000 1  7E8             Rx   d 8 21 30 31 32 33 34 35 36  Length = 0 BitCount = 0 ID = 2024
+   0.104000 1  7E8             Rx   d 8 22 37 38 39 41 42 43 44  Length = 0 BitCount = 0 ID = 2024
+   0.105000 CANFD   1 Tx        7E0  Request                          1 0 9 12 00 0A 2E F1 90 01 02 03 04 05 06 07
+   0.106000 1  ErrorFrame
+   0.107000 1  18DAF110x       Rx   r
+End TriggerBlock
+";
+
+fn address() -> Address {
+    Address::normal(0x7E0, 0x7E8, 0x7DF)
+}
+
+fn pdus(events: &[ReplayEvent]) -> Vec<&ReplayPdu> {
+    events.iter()
+        .filter_map(|e| match e {
+            ReplayEvent::Pdu(v) => Some(v),
+            _ => None,
+        })
+        .collect()
+}
+
+fn violations(events: &[ReplayEvent]) -> Vec<&ReplayViolation> {
+    events.iter()
+        .filter_map(|e| match e {
+            ReplayEvent::Violation(v) => Some(v),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_candump() -> anyhow::Result<()> {
+    let frames = CandumpReader::new(CANDUMP.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(frames.len(), 7);
+    assert_eq!(frames[0].timestamp(), 1_700_000_000_100_000);
+    assert_eq!(frames[0].channel(), "vcan0");
+    assert_eq!(frames[0].id().into_bits(), 0x7E0);
+    assert_eq!(frames[0].data(), hex::decode("0322F19000000000")?);
+
+    let events = Reassembler::new([address()]).reassemble(frames);
+    assert!(violations(&events).is_empty());
+    let pdus = pdus(&events);
+    assert_eq!(pdus.len(), 3);
+
+    assert_eq!(pdus[0].direction, TransferDirection::Transmit);
+    assert_eq!(pdus[0].data, hex::decode("22F190")?);
+    assert_eq!((pdus[0].start, pdus[0].end), (1_700_000_000_100_000, 1_700_000_000_100_000));
+
+    assert_eq!(pdus[1].direction, TransferDirection::Receive);
+    assert_eq!(pdus[1].data, hex::decode("62F1905744423031323334353637383941424344")?);
+    assert_eq!((pdus[1].start, pdus[1].end), (1_700_000_000_101_000, 1_700_000_000_104_000));
+
+    assert_eq!(pdus[2].addr_type, AddressType::Functional);
+    assert_eq!(pdus[2].data, hex::decode("1001")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_candump_formats() -> anyhow::Result<()> {
+    let trace = "\
+  vcan0  7E0   [8]  03 22 F1 90 00 00 00 00
+ (1700000000.000001)  vcan0  TX - -  18DAF110   [3]  02 10 03
+(1.5) can1 7E8##10F62F1900102030405060708090A0B0C
+(2.0) can1 7E0#R8
+";
+    let frames = CandumpReader::new(trace.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(frames.len(), 4);
+
+    assert_eq!(frames[0].timestamp(), 0);
+    assert_eq!(frames[0].data(), hex::decode("0322F19000000000")?);
+
+    assert_eq!(frames[1].timestamp(), 1_700_000_000_000_001);
+    assert!(frames[1].is_extended());
+    assert_eq!(frames[1].id().into_bits(), 0x18DAF110);
+    assert_eq!(frames[1].data(), hex::decode("021003")?);
+
+    assert_eq!(frames[2].timestamp(), 1_500_000);
+    assert_eq!(frames[2].channel(), "can1");
+    assert!(frames[2].is_can_fd());
+    assert!(frames[2].is_bitrate_switch());
+    assert!(!frames[2].is_esi());
+    assert_eq!(frames[2].data().len(), 16);
+
+    assert!(frames[3].is_remote());
+    assert_eq!(frames[3].data().len(), 8);
+
+    Ok(())
+}
+
+#[test]
+fn test_candump_error() {
+    let trace = "(0.1) vcan0 7E0#0322F190\n(0.2) vcan0 7E0#0322F\n";
+    let frames: Vec<_> = CandumpReader::new(trace.as_bytes()).collect();
+    assert!(frames[0].is_ok());
+    assert!(matches!(frames[1], Err(IsoTpError::InvalidTrace { line: 2, .. })));
+
+    let frames: Vec<_> = CandumpReader::new("(x.1) vcan0 7E0#00\n".as_bytes()).collect();
+    assert!(matches!(frames[0], Err(IsoTpError::InvalidTrace { line: 1, .. })));
+}
+
+#[test]
+fn test_asc() -> anyhow::Result<()> {
+    let frames = AscReader::new(ASC.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(frames.len(), 7);
+    assert_eq!(frames[0].timestamp(), 100_000);
+    assert_eq!(frames[0].channel(), "1");
+    assert_eq!(frames[0].direct(), CanDirect::Transmit);
+    assert_eq!(frames[1].direct(), CanDirect::Receive);
+    assert!(frames[5].is_can_fd());
+    assert!(frames[5].is_bitrate_switch());
+    assert_eq!(frames[5].data().len(), 12);
+    assert!(frames[6].is_remote());
+    assert!(frames[6].is_extended());
+    assert_eq!(frames[6].id().into_bits(), 0x18DAF110);
+
+    let events = Reassembler::new([address()]).reassemble(frames);
+    assert!(violations(&events).is_empty());
+    let pdus = pdus(&events);
+    assert_eq!(pdus.len(), 3);
+    assert_eq!(pdus[1].data, hex::decode("62F1905744423031323334353637383941424344")?);
+    assert_eq!((pdus[1].start, pdus[1].end), (101_000, 104_000));
+    assert_eq!(pdus[2].data, hex::decode("2EF19001020304050607")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_asc_base_dec() -> anyhow::Result<()> {
+    let trace = "base dec  timestamps absolute\n   1.000000 1  2016  Tx   d 3 02 10 03\n";
+    let frames = AscReader::new(trace.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].id().into_bits(), 0x7E0);
+    assert_eq!(frames[0].timestamp(), 1_000_000);
+
+    let trace = "   1.000000 1  7E0  Tx   d 8 02 10\n";
+    let frames: Vec<_> = AscReader::new(trace.as_bytes()).collect();
+    assert!(matches!(frames[0], Err(IsoTpError::InvalidTrace { line: 1, .. })));
+
+    Ok(())
+}
+
+#[test]
+fn test_violations() -> anyhow::Result<()> {
+    let trace = "\
+(0.000000) vcan0 7E8#101462F190574442
+(0.001000) vcan0 7E0#3000000000000000
+(0.002000) vcan0 7E8#2230313233343536
+(0.100000) vcan0 7E8#2130313233343536
+(0.110000) vcan0 7E8#101462F190574442
+(0.111000) vcan0 7E0#3000000000000000
+(0.112000) vcan0 7E8#2130313233343536
+(0.300000) vcan0 7E8#2237383941424344
+(0.400000) vcan0 7E0#3000000000000000
+(0.500000) vcan0 7E8#101462F190574442
+";
+    let frames = CandumpReader::new(trace.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+    let mut reassembler = Reassembler::new([address()]);
+    reassembler.set_timing(TimingConfig { n_cr: 150, ..Default::default() });
+    let events = reassembler.reassemble(frames);
+    assert!(pdus(&events).is_empty());
+
+    let violations = violations(&events);
+    assert_eq!(violations.len(), 6);
+    assert!(matches!(violations[0].error, IsoTpError::InvalidSequence { expect: 1, actual: 2 }));
+    assert_eq!(violations[0].timestamp, 2_000);
+    assert_eq!(violations[0].direction, TransferDirection::Receive);
+    // the PDU is discarded by the wrong sequence.
+    assert!(matches!(violations[1].error, IsoTpError::UnexpectedFrame(IsoTpFrameType::Consecutive)));
+    // N_Cr is exceeded.
+    assert!(matches!(violations[2].error, IsoTpError::Timeout { timer: IsoTpTimer::NCr, .. }));
+    assert_eq!(violations[2].timestamp, 300_000);
+    assert!(matches!(violations[3].error, IsoTpError::UnexpectedFrame(IsoTpFrameType::Consecutive)));
+    // no first frame is receiving.
+    assert!(matches!(violations[4].error, IsoTpError::UnexpectedFrame(IsoTpFrameType::FlowControl)));
+    assert_eq!(violations[4].direction, TransferDirection::Transmit);
+    // the trace is finished while receiving.
+    assert!(matches!(violations[5].error, IsoTpError::InvalidDataLength { actual: 6, expect: 20 }));
+
+    Ok(())
+}
+
+#[test]
+fn test_n_cr_from_flow_ctrl() -> anyhow::Result<()> {
+    // the flow control frames are delayed(N_Br), the consecutive frames follow them in time.
+    let trace = "\
+(0.000000) vcan0 7E8#101462F190574442
+(0.120000) vcan0 7E0#3001000000000000
+(0.200000) vcan0 7E8#2130313233343536
+(0.210000) vcan0 7E0#3100000000000000
+(0.330000) vcan0 7E0#3001000000000000
+(0.400000) vcan0 7E8#2237383941424344
+";
+    let frames = CandumpReader::new(trace.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+    let mut reassembler = Reassembler::new([address()]);
+    reassembler.set_timing(TimingConfig { n_cr: 100, ..Default::default() });
+    let events = reassembler.reassemble(frames);
+    assert!(violations(&events).is_empty());
+
+    let pdus = pdus(&events);
+    assert_eq!(pdus.len(), 1);
+    assert_eq!(pdus[0].data, hex::decode("62F1905744423031323334353637383941424344")?);
+
+    Ok(())
+}
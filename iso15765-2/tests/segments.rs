@@ -0,0 +1,89 @@
+use iso15765_2::*;
+
+fn pdu(length: usize) -> Vec<u8> {
+    (0..length).map(|v| v as u8).collect()
+}
+
+#[test]
+fn test_segments() -> anyhow::Result<()> {
+    for (length, ext, version, tx_dl) in [
+        (1, None, IsoTpVersion::Std2004, 8),
+        (7, None, IsoTpVersion::Std2004, 8),
+        (6, Some(0xF1), IsoTpVersion::Std2004, 8),
+        (8, None, IsoTpVersion::Std2004, 8),
+        (100, Some(0xF1), IsoTpVersion::Std2004, 8),
+        (4095, None, IsoTpVersion::Std2004, 8),
+        (30, None, IsoTpVersion::Std2016, 64),
+        (62, None, IsoTpVersion::Std2016, 64),
+        (200, Some(0x55), IsoTpVersion::Std2016, 12),
+        (5000, None, IsoTpVersion::Std2016, 64),
+    ] {
+        let data = pdu(length);
+        let ext_len = ext.map_or(0, |_| 1);
+        let expect: Vec<_> = IsoTpFrame::from_data_with_dl(&data, ext_len, version, tx_dl)?
+            .into_iter()
+            .map(|f| f.encode_with_ext(ext, Some(0xCC)))
+            .collect();
+
+        let config = SegmentConfig { ext, version, tx_dl, padding: Some(0xCC) };
+        let segments = IsoTpFrame::segments(&data, config)?;
+        assert_eq!(segments.len(), expect.len());
+        assert_eq!(segments.collect::<Vec<_>>(), expect, "length: {}, tx_dl: {}", length, tx_dl);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_next_into() -> anyhow::Result<()> {
+    let data = pdu(20);
+    let mut segments = IsoTpFrame::segments(&data, Default::default())?;
+    let mut buffer = [0; 8];
+
+    assert_eq!(segments.next_into(&mut buffer[..4]).unwrap().err().map(|e| e.to_string()),
+               Some(IsoTpError::InvalidDataLength { actual: 4, expect: 8 }.to_string()));
+    // the frame is not skipped by the error.
+    assert_eq!(segments.next_into(&mut buffer).unwrap()?, 8);
+    assert_eq!(buffer, [0x10, 0x14, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+    assert_eq!(segments.len(), 2);
+    assert_eq!(segments.next_into(&mut buffer).unwrap()?, 8);
+    assert_eq!(buffer, [0x21, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C]);
+    assert_eq!(segments.next_into(&mut buffer).unwrap()?, 8);
+    assert_eq!(buffer, [0x22, 0x0D, 0x0E, 0x0F, 0x10, 0x11, 0x12, 0x13]);
+    assert!(segments.next_into(&mut buffer).is_none());
+    assert_eq!(segments.len(), 0);
+
+    assert!(matches!(IsoTpFrame::segments(&[], Default::default()), Err(IsoTpError::EmptyPdu)));
+    assert!(matches!(IsoTpFrame::segments(&pdu(4096), Default::default()), Err(IsoTpError::LengthOutOfRange(4096))));
+    let config = SegmentConfig { tx_dl: 10, ..Default::default() };
+    assert!(matches!(IsoTpFrame::segments(&data, config), Err(IsoTpError::InvalidParam(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_frame_ref() -> anyhow::Result<()> {
+    let data = hex::decode("F1100A6EF19041424344")?;
+    let frame = IsoTpFrameRef::decode_with_offset(&data[..8], 1)?;
+    assert_eq!(frame.frame_type(), IsoTpFrameType::First);
+    match frame {
+        IsoTpFrameRef::FirstFrame { length, data: payload } => {
+            assert_eq!(length, 10);
+            assert_eq!(payload, hex::decode("6EF1904142")?);
+            // borrowed from the source.
+            assert!(std::ptr::eq(payload.as_ptr(), data[3..].as_ptr()));
+        },
+        _ => panic!("Wrong frame type"),
+    }
+
+    let frame = IsoTpFrameRef::decode(&data[1..9])?;
+    assert!(matches!(IsoTpFrame::from(frame), IsoTpFrame::FirstFrame { length: 10, .. }));
+
+    let data = hex::decode("2141424344AAAAAA")?;
+    assert_eq!(IsoTpFrameRef::decode(&data)?, IsoTpFrameRef::ConsecutiveFrame { sequence: 1, data: &data[1..] });
+    let data = hex::decode("300814")?;
+    assert_eq!(IsoTpFrameRef::decode(&data)?, IsoTpFrameRef::FlowControlFrame(FlowControlContext::new(FlowControlState::Continues, 8, 0x14)?));
+    assert!(matches!(IsoTpFrameRef::decode(&[0x02, 0x10]), Err(IsoTpError::InvalidPdu(_))));
+
+    Ok(())
+}
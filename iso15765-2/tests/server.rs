@@ -0,0 +1,134 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use iso15765_2::*;
+
+use common::*;
+
+/// Record the errors reported.
+#[derive(Clone, Default)]
+struct ErrorListener(Arc<Mutex<Vec<IsoTpError>>>);
+
+impl IsoTpEventListener for ErrorListener {
+    fn buffer_data(&mut self) -> Option<IsoTpEvent> { None }
+    fn clear_buffer(&mut self) {}
+    fn on_iso_tp_event(&mut self, event: IsoTpEvent) {
+        if let IsoTpEvent::ErrorOccurred(e) = event {
+            self.0.lock().unwrap().push(e);
+        }
+    }
+}
+
+fn setup(device: MockDevice, role: IsoTpRole) -> (CanAdapter<MockDevice, String, MockFrame>, CanIsoTp<String, MockFrame>, ErrorListener) {
+    setup_with(device, Address::normal(0x7E8, 0x7E0, 0x7DF), role)
+}
+
+fn setup_with(device: MockDevice, address: Address, role: IsoTpRole) -> (CanAdapter<MockDevice, String, MockFrame>, CanIsoTp<String, MockFrame>, ErrorListener) {
+    let mut adapter = CanAdapter::new(device);
+    let errors = ErrorListener::default();
+    let iso_tp = CanIsoTp::new(
+        CHANNEL.to_string(),
+        address,
+        adapter.sender(),
+        Box::new(errors.clone()),
+    );
+    iso_tp.set_role(role);
+    adapter.register_listener("iso-tp".into(), Box::new(iso_tp.clone()));
+    adapter.start(100).unwrap();
+
+    (adapter, iso_tp, errors)
+}
+
+fn transmitted_ids(device: &MockDevice) -> Vec<u32> {
+    device.transmitted.lock().unwrap()
+        .iter()
+        .map(|f| f.id.into_bits())
+        .collect()
+}
+
+#[test]
+fn test_functional_request() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E0, |_| vec![]);
+    let (mut adapter, iso_tp, _) = setup(device.clone(), IsoTpRole::Server);
+    assert_eq!(iso_tp.role()?, IsoTpRole::Server);
+
+    device.inject_with_id(0x7DF, hex::decode("023E80AAAAAAAAAA")?);
+    let request = iso_tp.read(1000)?;
+    assert_eq!(request, hex::decode("3E80")?);
+
+    // the response is transmitted on the physical tx_id.
+    iso_tp.write(AddressType::Physical, hex::decode("7E00")?)?;
+    assert_eq!(device.transmitted(), vec!["027E00AAAAAAAAAA"]);
+    assert_eq!(transmitted_ids(&device), vec![0x7E8]);
+
+    // the server can't write functionally.
+    let ret = iso_tp.write(AddressType::Functional, hex::decode("7E00")?);
+    assert!(matches!(ret, Err(IsoTpError::InvalidParam(_))));
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_functional_multi_frame_rejected() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E0, |frame| match frame.data[0] {
+        // FC sent by server
+        0x30 => vec![hex::decode("2144454647484950").unwrap()],
+        _ => vec![],
+    });
+    let (mut adapter, iso_tp, errors) = setup(device.clone(), IsoTpRole::Server);
+
+    device.inject_with_id(0x7DF, hex::decode("100A2EF190414243")?);
+    // the functional first frame is reported and ignored, no flow control is answered.
+    let ret = iso_tp.read(100);
+    assert!(matches!(ret, Err(IsoTpError::ReadTimeout { .. })));
+    assert!(device.transmitted().is_empty());
+    let reported = errors.0.lock().unwrap().clone();
+    assert!(matches!(reported.as_slice(), [IsoTpError::UnexpectedFrame(IsoTpFrameType::First)]));
+
+    // the physical multi-frame request is received as usual.
+    device.inject(hex::decode("100A2EF190414243")?);
+    let request = iso_tp.read(1000)?;
+    assert_eq!(request, hex::decode("2EF19041424344454647")?);
+    assert_eq!(device.transmitted(), vec!["30000AAAAAAAAAAA"]);
+    assert_eq!(transmitted_ids(&device), vec![0x7E8]);
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_client_ignores_functional() -> anyhow::Result<()> {
+    let device = MockDevice::new(0x7E0, |_| vec![]);
+    let (mut adapter, iso_tp, _) = setup(device.clone(), IsoTpRole::default());
+    assert_eq!(iso_tp.role()?, IsoTpRole::Client);
+
+    device.inject_with_id(0x7DF, hex::decode("023E80AAAAAAAAAA")?);
+    let ret = iso_tp.read(100);
+    assert!(matches!(ret, Err(IsoTpError::ReadTimeout { .. })));
+
+    adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_normal_fixed_server() -> anyhow::Result<()> {
+    // the ECU 0x10 answers the tester 0xF1.
+    let address = Address::normal_fixed_server(0x10, 0xF1, 0x33);
+    assert_eq!((address.tx_id, address.rx_id, address.fid), (0x18DAF110, 0x18DA10F1, 0x18DB33F1));
+    let device = MockDevice::new(0x18DA10F1, |_| vec![]);
+    let (mut adapter, iso_tp, _) = setup_with(device.clone(), address, IsoTpRole::Server);
+
+    // the functional request of the tester.
+    device.inject_with_id(0x18DB33F1, hex::decode("023E80AAAAAAAAAA")?);
+    assert_eq!(iso_tp.read(1000)?, hex::decode("3E80")?);
+
+    device.inject(hex::decode("0322F190AAAAAAAA")?);
+    assert_eq!(iso_tp.read(1000)?, hex::decode("22F190")?);
+    iso_tp.write(AddressType::Physical, hex::decode("62F19001")?)?;
+    assert_eq!(device.transmitted(), vec!["0462F19001AAAAAA"]);
+    assert_eq!(transmitted_ids(&device), vec![0x18DAF110]);
+
+    adapter.stop()?;
+    Ok(())
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use std::{thread, time::Duration};
+use iso15765_2::*;
+use rs_can::{CanDevice, CanFrame, CanId};
+use common::{vcan, NullListener};
+
+fn receive(device: &SocketCan, channel: &str) -> anyhow::Result<Vec<SocketCanFrame>> {
+    for _ in 0..10 {
+        let frames = device.receive(channel.into(), Some(100))?;
+        if !frames.is_empty() {
+            return Ok(frames);
+        }
+    }
+
+    Ok(vec![])
+}
+
+#[test]
+#[ignore = "requires the vcan interface"]
+fn test_transmit_receive() -> anyhow::Result<()> {
+    let channel = "vcan0";
+    vcan(channel)?;
+
+    let tester = SocketCan::new();
+    tester.open(channel, true)?;
+    let ecu = SocketCan::new();
+    ecu.open(channel, true)?;
+    assert_eq!(ecu.opened_channels(), vec![channel.to_string()]);
+
+    let mut frame = SocketCanFrame::new(CanId::from_bits(0x7E0, None), &hex::decode("0322F190")?).unwrap();
+    frame.set_channel(channel.into());
+    tester.transmit(frame, Some(100))?;
+
+    let frames = receive(&ecu, channel)?;
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].id().into_bits(), 0x7E0);
+    assert!(!frames[0].is_can_fd());
+    assert_eq!(frames[0].data(), hex::decode("0322F190")?);
+    assert_ne!(frames[0].timestamp(), 0);
+    // stamped by kernel(SO_TIMESTAMPING) instead of the system time when reading.
+    assert_ne!(frames[0].timestamp_source(), TimestampSource::System);
+
+    // CAN FD frame with extended CAN-ID and bitrate switch.
+    let data: Vec<_> = (0..20).collect();
+    let mut frame = SocketCanFrame::new(CanId::from_bits(0x18DA00F1, Some(true)), &data).unwrap();
+    frame.set_channel(channel.into())
+        .set_bitrate_switch(true);
+    tester.transmit(frame, Some(100))?;
+
+    let frames = receive(&ecu, channel)?;
+    assert_eq!(frames.len(), 1);
+    assert!(frames[0].is_can_fd());
+    assert!(frames[0].is_extended());
+    assert!(frames[0].is_bitrate_switch());
+    assert_eq!(frames[0].id().into_bits(), 0x18DA00F1);
+    // padded to the valid data length.
+    assert_eq!(frames[0].data().len(), 24);
+    assert_eq!(frames[0].data()[..20], data);
+
+    // the classic socket can't transmit CAN FD frame.
+    let classic = SocketCan::new();
+    classic.open(channel, false)?;
+    let mut frame = SocketCanFrame::new(CanId::from_bits(0x7E0, None), &data).unwrap();
+    frame.set_channel(channel.into());
+    assert!(classic.transmit(frame, Some(100)).is_err());
+
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires the vcan interface"]
+fn test_iso_tp() -> anyhow::Result<()> {
+    let channel = "vcan1";
+    vcan(channel)?;
+
+    let tester_device = SocketCan::new();
+    tester_device.open(channel, true)?;
+    let mut tester_adapter = CanAdapter::new(tester_device);
+    let tester = CanIsoTp::new(
+        channel.to_string(),
+        Address::normal(0x7E0, 0x7E8, 0x7DF),
+        tester_adapter.sender(),
+        Box::new(NullListener),
+    );
+    tester.set_tx_dl(64)?;
+    tester_adapter.register_listener("iso-tp".into(), Box::new(tester.clone()));
+    tester_adapter.start(100)?;
+
+    let ecu_device = SocketCan::new();
+    ecu_device.open(channel, true)?;
+    let mut ecu_adapter = CanAdapter::new(ecu_device);
+    let ecu = CanIsoTp::new(
+        channel.to_string(),
+        Address::normal(0x7E8, 0x7E0, 0x7DF),
+        ecu_adapter.sender(),
+        Box::new(NullListener),
+    );
+    ecu_adapter.register_listener("iso-tp".into(), Box::new(ecu.clone()));
+    ecu_adapter.start(100)?;
+
+    let request: Vec<_> = (0..200_u32).map(|v| v as u8).collect();
+    let response: Vec<_> = (0..100_u32).map(|v| !v as u8).collect();
+    let handle = {
+        let response = response.clone();
+        thread::spawn(move || -> Result<Vec<u8>, IsoTpError> {
+            let request = ecu.read(1000)?;
+            ecu.write(AddressType::Physical, response)?;
+            Ok(request)
+        })
+    };
+    thread::sleep(Duration::from_millis(10));
+
+    let data = tester.transceive(AddressType::Physical, request.clone(), 1000)?;
+    assert_eq!(data, response);
+    assert_eq!(handle.join().unwrap()?, request);
+
+    tester_adapter.stop()?;
+    ecu_adapter.stop()?;
+    Ok(())
+}
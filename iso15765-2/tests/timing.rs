@@ -0,0 +1,25 @@
+use iso15765_2::*;
+
+#[test]
+fn test_timing_config() -> anyhow::Result<()> {
+    let timing = TimingConfig::default();
+    assert_eq!(timing.value(IsoTpTimer::NAs), TIMEOUT_AS_ISO15765_2);
+    assert_eq!(timing.value(IsoTpTimer::NBs), TIMEOUT_BS_ISO15765_2);
+    assert_eq!(timing.value(IsoTpTimer::NCr), TIMEOUT_CR_ISO15765_2);
+
+    let timing = TimingConfig { n_bs: 150, n_cr: 250, ..Default::default() };
+    match timing.timeout(IsoTpTimer::NBs) {
+        IsoTpError::Timeout { timer, value, unit } => {
+            assert_eq!(timer, IsoTpTimer::NBs);
+            assert_eq!(value, 150);
+            assert_eq!(unit, "ms");
+        },
+        _ => panic!("Wrong error type"),
+    }
+    assert_eq!(
+        timing.timeout(IsoTpTimer::NCr).to_string(),
+        "ISO-TP - N_Cr timeout when time(250ms)"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use iso15765_2::*;
+
+use common::*;
+
+fn large_pdu() -> Vec<u8> {
+    (0..5000_u32).map(|v| v as u8).collect()
+}
+
+fn decode_all(frames: &[Vec<u8>]) -> anyhow::Result<Vec<u8>> {
+    let mut length = 0;
+    let mut result = Vec::new();
+    for data in frames {
+        match IsoTpFrame::decode(data)? {
+            IsoTpFrame::SingleFrame { data } => return Ok(data),
+            IsoTpFrame::FirstFrame { length: len, data } => {
+                length = len as usize;
+                result.extend(data);
+            },
+            IsoTpFrame::ConsecutiveFrame { data, .. } => result.extend(data),
+            IsoTpFrame::FlowControlFrame(_) => panic!("Wrong frame type"),
+        }
+    }
+    result.truncate(length);
+
+    Ok(result)
+}
+
+#[test]
+fn test_escape_first_frame() -> anyhow::Result<()> {
+    let source = large_pdu();
+
+    let ret = IsoTpFrame::from_data_with_version(&source, 0, IsoTpVersion::Std2004);
+    assert!(matches!(ret, Err(IsoTpError::LengthOutOfRange(5000))));
+
+    let frames: Vec<_> = IsoTpFrame::from_data_with_version(&source, 0, IsoTpVersion::Std2016)?
+        .into_iter()
+        .map(|f| f.encode(None))
+        .collect();
+    // 2 bytes in first frame, 7 bytes in each consecutive frame.
+    assert_eq!(frames.len(), 1 + (5000 - 2_usize).div_ceil(7));
+    assert_eq!(frames[0], hex::decode("1000000013880001")?);
+    assert_eq!(frames[1], hex::decode("210203040506070809")?[..8]);
+    assert!(frames.iter().all(|f| f.len() == 8));
+    assert_eq!(decode_all(&frames)?, source);
+
+    // the PDU not larger than 4095 bytes is encoded in the same way.
+    let source = hex::decode("36010102030405060708090A0B0C")?;
+    let v2004: Vec<_> = IsoTpFrame::from_data_with_version(&source, 0, IsoTpVersion::Std2004)?
+        .into_iter()
+        .map(|f| f.encode(None))
+        .collect();
+    let v2016: Vec<_> = IsoTpFrame::from_data_with_version(&source, 0, IsoTpVersion::Std2016)?
+        .into_iter()
+        .map(|f| f.encode(None))
+        .collect();
+    assert_eq!(v2004, v2016);
+    assert_eq!(v2004[0], hex::decode("100E360101020304")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_escape() -> anyhow::Result<()> {
+    match IsoTpFrame::decode(hex::decode("0003223344AAAAAA")?)? {
+        IsoTpFrame::SingleFrame { data } => assert_eq!(data, hex::decode("223344")?),
+        _ => panic!("Wrong frame type"),
+    }
+    // escape single frame with zero length.
+    assert!(IsoTpFrame::decode(hex::decode("0000AAAAAAAAAAAA")?).is_err());
+    // escape first frame is only valid for the length larger than 4095.
+    assert!(IsoTpFrame::decode(hex::decode("1000000001000102")?).is_err());
+
+    // the first frame of PDU fits in a single frame is ignored.
+    assert!(IsoTpFrame::decode(hex::decode("1007010203040506")?).is_err());
+    assert!(IsoTpFrame::decode(hex::decode("1008010203040506")?).is_ok());
+    // extended addressing: the max SF_DL is 6.
+    assert!(matches!(
+        IsoTpFrame::decode_with_offset(hex::decode("F110060102030405")?, 1),
+        Err(IsoTpError::InvalidDataLength { actual: 6, expect: 6 })
+    ));
+    assert!(IsoTpFrame::decode_with_offset(hex::decode("F110070102030405")?, 1).is_ok());
+    // CAN FD: the FF_DL is less than the data carried(62 bytes).
+    let mut data = hex::decode("1020")?;
+    data.resize(64, 0x55);
+    assert!(matches!(IsoTpFrame::decode(&data), Err(IsoTpError::InvalidDataLength { actual: 0x20, expect: 62 })));
+    data[1] = 0x3F;
+    assert!(IsoTpFrame::decode(&data).is_ok());
+
+    match IsoTpFrame::decode(hex::decode("1000000010000102")?)? {
+        IsoTpFrame::FirstFrame { length, data } => {
+            assert_eq!(length, 0x1000);
+            assert_eq!(data, hex::decode("0102")?);
+        },
+        _ => panic!("Wrong frame type"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_large_pdu() -> anyhow::Result<()> {
+    let source = large_pdu();
+    let response: Vec<_> = IsoTpFrame::from_data_with_version(&source, 0, IsoTpVersion::Std2016)?
+        .into_iter()
+        .map(|f| f.encode(None))
+        .collect();
+    let device = MockDevice::new(0x7E8, move |frame| match frame.data[0] {
+        // the first frame sent by CanIsoTp
+        0x10 => vec![hex::decode("300000").unwrap()],
+        // the request
+        0x02 => vec![response[0].clone()],
+        // FC sent by CanIsoTp
+        0x30 => response[1..].to_vec(),
+        _ => vec![],
+    });
+    let mut adapter = CanAdapter::new(device.clone());
+    let iso_tp = CanIsoTp::new(
+        CHANNEL.to_string(),
+        Address::normal(0x7E0, 0x7E8, 0x7DF),
+        adapter.sender(),
+        Box::new(NullListener),
+    );
+    adapter.register_listener("iso-tp".into(), Box::new(iso_tp.clone()));
+    adapter.start(100)?;
+
+    let ret = iso_tp.write(AddressType::Physical, source.clone());
+    assert!(matches!(ret, Err(IsoTpError::LengthOutOfRange(5000))));
+
+    iso_tp.set_version(IsoTpVersion::Std2016);
+    assert_eq!(iso_tp.version()?, IsoTpVersion::Std2016);
+    iso_tp.write(AddressType::Physical, source.clone())?;
+    let transmitted = device.transmitted();
+    assert_eq!(transmitted.len(), 1 + (5000 - 2_usize).div_ceil(7));
+    assert_eq!(transmitted[0], "1000000013880001");
+
+    let data = iso_tp.transceive(AddressType::Physical, hex::decode("3601")?, 1000)?;
+    assert_eq!(data, source);
+
+    adapter.stop()?;
+    Ok(())
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use std::{thread, time::Duration};
+use iso15765_2::*;
+use rs_can::{CanDevice, CanFrame, CanId};
+use common::NullListener;
+
+const CHANNEL: &str = "vcan";
+
+type Adapter = CanAdapter<VirtualCanDevice, String, CanMessage>;
+
+fn node(bus: &VirtualCanBus, address: Address) -> (Adapter, CanIsoTp<String, CanMessage>) {
+    let mut adapter = CanAdapter::new(bus.device());
+    let iso_tp = CanIsoTp::new(CHANNEL.to_string(), address, adapter.sender(), Box::new(NullListener));
+    adapter.register_listener("iso-tp".into(), Box::new(iso_tp.clone()));
+    adapter.start(100).unwrap();
+
+    (adapter, iso_tp)
+}
+
+fn frame(id: u32, data: &str) -> CanMessage {
+    let mut frame = CanMessage::new(CanId::from_bits(id, None), &hex::decode(data).unwrap()).unwrap();
+    frame.set_channel(CHANNEL.into());
+    frame
+}
+
+#[test]
+fn test_multi_frame() -> anyhow::Result<()> {
+    let bus = VirtualCanBus::with_config(CHANNEL, VirtualBusConfig { latency: Duration::from_millis(1), ..Default::default() });
+    let (mut tester_adapter, tester) = node(&bus, Address::normal(0x7E0, 0x7E8, 0x7DF));
+    let (mut ecu_adapter, ecu) = node(&bus, Address::normal(0x7E8, 0x7E0, 0x7DF));
+    ecu.set_flow_ctrl(FlowControlConfig { block_size: 4, st_min: 1, ..Default::default() })?;
+
+    let request: Vec<_> = (0..200_u32).map(|v| v as u8).collect();
+    let response: Vec<_> = (0..100_u32).map(|v| !v as u8).collect();
+    let handle = {
+        let response = response.clone();
+        thread::spawn(move || -> Result<Vec<u8>, IsoTpError> {
+            let request = ecu.read(1000)?;
+            ecu.write(AddressType::Physical, response)?;
+            Ok(request)
+        })
+    };
+
+    assert_eq!(tester.transceive(AddressType::Physical, request.clone(), 1000)?, response);
+    assert_eq!(handle.join().unwrap()?, request);
+    // 1 FF + 28 CFs of request with 7 FCs(BS=4), 1 FF + 14 CFs of response with 1 FC.
+    assert_eq!(bus.history().len(), 1 + 28 + 7 + 1 + 14 + 1);
+
+    tester_adapter.stop()?;
+    ecu_adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_flow_ctrl_wait() -> anyhow::Result<()> {
+    let bus = VirtualCanBus::new(CHANNEL);
+    let (mut tester_adapter, tester) = node(&bus, Address::normal(0x7E0, 0x7E8, 0x7DF));
+    let (mut ecu_adapter, ecu) = node(&bus, Address::normal(0x7E8, 0x7E0, 0x7DF));
+    ecu.set_flow_ctrl(FlowControlConfig { wait_count: 2, ..Default::default() })?;
+    ecu.set_timing(TimingConfig { n_br: 20, ..Default::default() });
+
+    tester.write(AddressType::Physical, hex::decode("2EF1900102030405060708090A0B0C0D0E0F")?)?;
+    assert_eq!(ecu.read(1000)?, hex::decode("2EF1900102030405060708090A0B0C0D0E0F")?);
+
+    let flow_ctrl: Vec<_> = bus.history().into_iter()
+        .filter(|f| f.id().into_bits() == 0x7E8)
+        .map(|f| f.data()[0])
+        .collect();
+    assert_eq!(flow_ctrl, vec![0x31, 0x31, 0x30]);
+
+    // the sender aborts when N_WFTmax is exceeded.
+    tester.set_flow_ctrl(FlowControlConfig { wft_max: 1, ..Default::default() })?;
+    let ret = tester.write(AddressType::Physical, hex::decode("2EF1900102030405060708090A0B0C0D0E0F")?);
+    assert!(matches!(ret, Err(IsoTpError::WaitFlowOverrun(1))));
+
+    tester_adapter.stop()?;
+    ecu_adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_timeout() -> anyhow::Result<()> {
+    let bus = VirtualCanBus::new(CHANNEL);
+    let (mut tester_adapter, tester) = node(&bus, Address::normal(0x7E0, 0x7E8, 0x7DF));
+    tester.set_timing(TimingConfig { n_bs: 50, n_cr: 50, ..Default::default() });
+
+    // no receiver responds the flow control frame.
+    let ret = tester.write(AddressType::Physical, hex::decode("2EF1900102030405060708090A0B0C0D0E0F")?);
+    assert!(matches!(ret, Err(IsoTpError::Timeout { timer: IsoTpTimer::NBs, .. })));
+
+    // the consecutive frames are never transmitted.
+    let ecu = bus.device();
+    ecu.transmit(frame(0x7E8, "100A62F190414243"), None)?;
+    let ret = tester.read(1000);
+    assert!(matches!(ret, Err(IsoTpError::Timeout { timer: IsoTpTimer::NCr, .. })));
+
+    tester_adapter.stop()?;
+    Ok(())
+}
+
+#[test]
+fn test_faults() -> anyhow::Result<()> {
+    // the same seed gives the same faults.
+    let config = VirtualBusConfig { drop_rate: 0.5, seed: 0x1234, ..Default::default() };
+    let histories: Vec<_> = (0..2).map(|_| {
+        let bus = VirtualCanBus::with_config(CHANNEL, config);
+        let device = bus.device();
+        (0..32).for_each(|i| device.transmit(frame(0x7E0, &format!("{:02X}", i)), None).unwrap());
+        bus.history().iter().map(|f| f.data()[0]).collect::<Vec<_>>()
+    }).collect();
+    assert!(!histories[0].is_empty() && histories[0].len() < 32);
+    assert_eq!(histories[0], histories[1]);
+
+    let bus = VirtualCanBus::new(CHANNEL);
+    let tester = bus.device();
+    let ecu = bus.device();
+
+    // the delayed frame is overtaken.
+    bus.set_config(VirtualBusConfig { reorder_rate: 1., reorder_delay: Duration::from_millis(20), ..Default::default() });
+    tester.transmit(frame(0x7E0, "01"), None)?;
+    bus.set_config(Default::default());
+    tester.transmit(frame(0x7E0, "02"), None)?;
+    let frames = ecu.receive(CHANNEL.into(), Some(100))?;
+    assert_eq!(frames.iter().map(|f| f.data().to_vec()).collect::<Vec<_>>(), vec![vec![0x02]]);
+    let frames = ecu.receive(CHANNEL.into(), Some(100))?;
+    assert_eq!(frames.iter().map(|f| f.data().to_vec()).collect::<Vec<_>>(), vec![vec![0x01]]);
+
+    // one bit of the payload is flipped, the corrupted frame is received.
+    bus.set_config(VirtualBusConfig { bit_error_rate: 1., ..Default::default() });
+    tester.transmit(frame(0x7E0, "0322F190"), None)?;
+    let frames = ecu.receive(CHANNEL.into(), Some(100))?;
+    let diff: u32 = frames[0].data().iter()
+        .zip(hex::decode("0322F190")?)
+        .map(|(a, b)| (a ^ b).count_ones())
+        .sum();
+    assert_eq!(diff, 1);
+    // the transmitter doesn't receive its frame.
+    assert!(tester.receive(CHANNEL.into(), None)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_history_capacity() -> anyhow::Result<()> {
+    // the oldest frames are discarded.
+    let bus = VirtualCanBus::with_config(CHANNEL, VirtualBusConfig { history_capacity: 4, ..Default::default() });
+    let device = bus.device();
+    (0..10).for_each(|i| device.transmit(frame(0x7E0, &format!("{:02X}", i)), None).unwrap());
+    assert_eq!(bus.history().iter().map(|f| f.data()[0]).collect::<Vec<_>>(), vec![6, 7, 8, 9]);
+
+    bus.set_config(VirtualBusConfig { history_capacity: 2, ..Default::default() });
+    assert_eq!(bus.history().iter().map(|f| f.data()[0]).collect::<Vec<_>>(), vec![8, 9]);
+
+    // the history is disabled.
+    bus.set_config(VirtualBusConfig { history_capacity: 0, ..Default::default() });
+    device.transmit(frame(0x7E0, "0A"), None)?;
+    assert!(bus.history().is_empty());
+
+    Ok(())
+}